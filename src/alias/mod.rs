@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+/// Shorthands people commonly type instead of the language keys Wandbox uses
+const DEFAULT_ALIASES : &[(&str, &str)] = &[
+    ("cpp", "c++"),
+    ("cxx", "c++"),
+    ("cc", "c++"),
+    ("h", "c"),
+    ("cs", "c#"),
+    ("csharp", "c#"),
+    ("py", "python"),
+    ("python3", "python"),
+    ("js", "javascript"),
+    ("node", "javascript"),
+    ("ts", "typescript"),
+    ("rs", "rust"),
+    ("rb", "ruby"),
+    ("hs", "haskell"),
+    ("sh", "bash script"),
+    ("vim", "vim script"),
+    ("vimscript", "vim script"),
+    ("pl", "perl"),
+    ("ex", "elixir"),
+    ("erl", "erlang"),
+    ("ml", "ocaml"),
+    ("jl", "julia"),
+    ("cr", "crystal"),
+];
+
+pub type AliasTable = HashMap<String, String>;
+
+pub fn defaults() -> AliasTable {
+    DEFAULT_ALIASES.iter()
+        .map(|(alias, lang)| (alias.to_string(), lang.to_string()))
+        .collect()
+}
+
+/// Source file extensions & the language Wandbox knows them as
+const EXTENSIONS : &[(&str, &str)] = &[
+    ("cpp", "c++"),
+    ("cc", "c++"),
+    ("cxx", "c++"),
+    ("c++", "c++"),
+    ("hpp", "c++"),
+    ("hh", "c++"),
+    ("hxx", "c++"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cs", "c#"),
+    ("py", "python"),
+    ("rs", "rust"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("ts", "typescript"),
+    ("rb", "ruby"),
+    ("go", "go"),
+    ("hs", "haskell"),
+    ("java", "java"),
+    ("lua", "lua"),
+    ("pl", "perl"),
+    ("pm", "perl"),
+    ("php", "php"),
+    ("sh", "bash script"),
+    ("bash", "bash script"),
+    ("swift", "swift"),
+    ("erl", "erlang"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("d", "d"),
+    ("lisp", "lisp"),
+    ("cl", "lisp"),
+    ("pas", "pascal"),
+    ("scala", "scala"),
+    ("nim", "nim"),
+    ("cr", "crystal"),
+    ("ml", "ocaml"),
+    ("r", "r"),
+    ("sql", "sql"),
+    ("groovy", "groovy"),
+    ("jl", "julia"),
+    ("vim", "vim script"),
+    ("rill", "rill"),
+];
+
+/// Guesses the language of a source file from its extension, i.e "cpp" or ".cpp" is "c++"
+///
+/// The language may not be available on every Wandbox instance, see `Wandbox::target_for_filename`.
+///
+/// # Arguments
+/// * `ext` - The file extension, with or without the leading dot
+pub fn language_for_extension(ext : &str) -> Option<&'static str> {
+    let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+    EXTENSIONS.iter()
+        .find(|(e, _lang)| *e == ext)
+        .map(|(_e, lang)| *lang)
+}
@@ -0,0 +1,197 @@
+//! A blocking API for consumers that don't run an async runtime
+//!
+//! The blocking `Wandbox` sends its requests through `reqwest::blocking`, wrapped in a
+//! `WandboxTransport`, so the compiler cache, `CompilationBuilder` & `Compilation` are shared
+//! with the async API and behave the same. No tokio runtime is started, the shared code is
+//! driven on the calling thread.
+//!
+//! Methods here return `WandboxError::BlockingInRuntime` when called from within a tokio runtime,
+//! as blocking there would stall it. Async code should use `crate::Wandbox` instead.
+use std::collections::HashSet;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+
+use crate::{Compilation, CompilationResult, PermlinkResult, Template, TransportResponse, WandboxBuilder, WandboxError,
+            WandboxResult, WandboxTransport};
+
+/// A blocking handle to the compiler cache
+///
+/// All lookups from the async `Wandbox` are available through `Deref`
+/// ```no_run
+///use wandbox::CompilationBuilder;
+///use wandbox::blocking::Wandbox;
+///
+///fn main() {
+///    let wbox : Wandbox = match Wandbox::new(None, None) {
+///        Ok(wbox) => wbox,
+///        Err(e) => return println!("{}", e)
+///    };
+///    let mut builder = CompilationBuilder::new();
+///    builder.target("c++");
+///    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+///    let compilation = match builder.build(&wbox) {
+///        Ok(compilation) => compilation,
+///        Err(e) => return println!("{}", e)
+///    };
+///    let result = wbox.dispatch(&compilation);
+///}
+/// ```
+pub struct Wandbox {
+    inner : crate::Wandbox,
+}
+impl Wandbox {
+    /// Initializes the cache for Wandbox requests
+    ///
+    /// # Arguments
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub fn new(comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        let mut builder = WandboxBuilder::new();
+        if let Some(comps) = comps {
+            builder = builder.blocked_compilers(comps);
+        }
+        if let Some(langs) = langs {
+            builder = builder.blocked_languages(langs);
+        }
+        Wandbox::from_builder(builder)
+    }
+
+    /// Initializes the cache for Wandbox requests from a configured `WandboxBuilder`
+    ///
+    /// Unless the builder was given a transport, requests go through a blocking client with the
+    /// builder's timeouts, proxy & user agent. A transport given to the builder is driven without
+    /// a tokio runtime, so one made with `WandboxBuilder::client` won't work here.
+    /// `WandboxBuilder::auto_refresh` isn't supported & returns
+    /// `WandboxError::AutoRefreshUnsupported`.
+    ///
+    /// # Arguments
+    /// * `builder` - The configuration to build the cache with
+    pub fn from_builder(mut builder : WandboxBuilder) -> WandboxResult<Wandbox> {
+        // checked before the client is made, as dropping one within a runtime panics
+        outside_runtime()?;
+        if builder.auto_refresh.is_some() {
+            return Err(WandboxError::AutoRefreshUnsupported);
+        }
+        if builder.transport.is_none() {
+            builder.transport = Some(Arc::new(BlockingTransport::new(&builder)?));
+        }
+
+        let inner = block_on(builder.build())??;
+        Ok(Wandbox {
+            inner
+        })
+    }
+
+    /// Dispatches a built request to Wandbox, blocking until the result is available
+    ///
+    /// # Arguments
+    /// * `compilation` - A request that has been built against this instance
+    pub fn dispatch(&self, compilation : &Compilation) -> WandboxResult<CompilationResult> {
+        block_on(compilation.dispatch_with(&self.inner))?
+    }
+
+    /// Fetches a previously saved compilation from Wandbox
+    ///
+    /// # Arguments
+    /// * `id` - The permlink identifier Wandbox replied with when the compilation was saved
+    pub fn get_permlink(&self, id : &str) -> WandboxResult<PermlinkResult> {
+        block_on(self.inner.get_permlink(id))?
+    }
+
+    /// Fetches one of Wandbox's code templates, a default snippet for a compiler
+    ///
+    /// # Arguments
+    /// * `name` - The template name i.e "gcc"
+    pub fn get_template(&self, name : &str) -> WandboxResult<Template> {
+        block_on(self.inner.get_template(name))?
+    }
+}
+
+impl Deref for Wandbox {
+    type Target = crate::Wandbox;
+
+    fn deref(&self) -> &crate::Wandbox {
+        &self.inner
+    }
+}
+
+/// Fails with `WandboxError::BlockingInRuntime` when called from within a tokio runtime
+fn outside_runtime() -> WandboxResult<()> {
+    match tokio::runtime::Handle::try_current() {
+        Ok(_handle) => Err(WandboxError::BlockingInRuntime),
+        Err(_e) => Ok(())
+    }
+}
+
+/// Runs a future to completion on the calling thread, unless that would stall a tokio runtime
+fn block_on<F : Future>(future : F) -> WandboxResult<F::Output> {
+    outside_runtime()?;
+    Ok(futures_executor::block_on(future))
+}
+
+/// Sends requests through a `reqwest::blocking::Client`, each one completing as it's polled
+struct BlockingTransport {
+    client : reqwest::blocking::Client,
+}
+impl BlockingTransport {
+    /// Creates a client with the connection settings of a `WandboxBuilder`
+    fn new(builder : &WandboxBuilder) -> WandboxResult<BlockingTransport> {
+        let mut client = reqwest::blocking::Client::builder();
+        // unlike the async client, the blocking one times requests out after 30 seconds by default
+        client = client.timeout(builder.request_timeout);
+        if let Some(timeout) = builder.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        if builder.no_proxy {
+            client = client.no_proxy();
+        }
+        if let Some(proxy) = builder.reqwest_proxy()? {
+            client = client.proxy(proxy);
+        }
+        if let Some(user_agent) = &builder.user_agent {
+            client = client.user_agent(user_agent.as_str());
+        }
+
+        Ok(BlockingTransport {
+            client : client.build()?
+        })
+    }
+}
+
+/// Reads a blocking reqwest reply in full
+fn read(response : reqwest::blocking::Response) -> WandboxResult<TransportResponse> {
+    let status = response.status().as_u16();
+    let headers = response.headers().iter()
+        .filter_map(|(k, v)| Some((k.as_str().to_string(), v.to_str().ok()?.to_string())))
+        .collect();
+
+    Ok(TransportResponse { status, headers, body : response.text()? })
+}
+
+impl WandboxTransport for BlockingTransport {
+    fn get_json<'a>(&'a self, url : &'a str) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        self.get_json_with(url, Vec::new())
+    }
+
+    fn get_json_with<'a>(&'a self, url : &'a str, headers : Vec<(String, String)>) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        Box::pin(async move {
+            let mut request = self.client.get(url);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            read(request.send()?)
+        })
+    }
+
+    fn post_json<'a>(&'a self, url : &'a str, body : String) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        Box::pin(async move {
+            let request = self.client.post(url)
+                .body(body)
+                .header("Content-Type", "application/json; charset=utf-8");
+            read(request.send()?)
+        })
+    }
+}
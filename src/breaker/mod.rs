@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{Instant, SystemTime};
+
+use crate::{read_lock, write_lock, CircuitBreaker, CircuitState, WandboxError};
+
+/// Fails dispatches fast while Wandbox keeps failing, see `WandboxBuilder::circuit_breaker`
+#[derive(Default)]
+pub(crate) struct Breaker {
+    // `None` while no breaker is configured, every dispatch goes through then
+    config : RwLock<Option<CircuitBreaker>>,
+    phase : RwLock<Phase>,
+}
+
+enum Phase {
+    // when each failure since the last success happened, oldest first
+    Closed { failures : VecDeque<Instant> },
+    Open { until : Instant },
+    // the cool-down is over & a probe is in flight
+    HalfOpen { since : Instant },
+}
+impl Default for Phase {
+    fn default() -> Phase {
+        Phase::Closed { failures : VecDeque::new() }
+    }
+}
+
+/// Admission of a dispatch through the breaker, reporting how it went with `record`
+///
+/// Dropped without being recorded, i.e when the dispatch is cancelled, a probe lets the next
+/// dispatch probe instead.
+pub(crate) struct Ticket {
+    breaker : Arc<Breaker>,
+    probe : bool,
+}
+
+impl Breaker {
+    /// Changes the thresholds, closing the breaker
+    pub fn configure(&self, config : Option<CircuitBreaker>) {
+        *write_lock(&self.config) = config;
+        *write_lock(&self.phase) = Phase::default();
+    }
+
+    /// Returns the state of the breaker, `None` when none is configured
+    pub fn state(&self) -> Option<CircuitState> {
+        read_lock(&self.config).as_ref()?;
+        let state = match &*read_lock(&self.phase) {
+            Phase::Closed { failures } => CircuitState::Closed { failures : failures.len() as u32 },
+            Phase::Open { until } if *until > Instant::now() => CircuitState::Open { retry_at : system_time(*until) },
+            Phase::Open { .. } | Phase::HalfOpen { .. } => CircuitState::HalfOpen
+        };
+        Some(state)
+    }
+
+    /// Lets a dispatch through unless the breaker is open, or half-open with a probe in flight
+    pub fn admit(self : &Arc<Self>) -> Result<Ticket, WandboxError> {
+        let config = match *read_lock(&self.config) {
+            Some(config) => config,
+            None => return Ok(Ticket { breaker : self.clone(), probe : false })
+        };
+
+        let mut phase = write_lock(&self.phase);
+        let now = Instant::now();
+        let probe = match &*phase {
+            Phase::Closed { .. } => false,
+            Phase::Open { until } if *until <= now => true,
+            Phase::Open { until } => return Err(WandboxError::CircuitOpen { retry_at : system_time(*until) }),
+            // the probe should be done within another cool-down
+            Phase::HalfOpen { since } => return Err(WandboxError::CircuitOpen { retry_at : system_time(*since + config.cool_down) })
+        };
+        if probe {
+            *phase = Phase::HalfOpen { since : now };
+        }
+        Ok(Ticket { breaker : self.clone(), probe })
+    }
+}
+
+impl Ticket {
+    /// Records how the dispatch went, only outages count as failures as any other reply shows
+    /// Wandbox is up
+    pub fn record<T>(mut self, result : &Result<T, WandboxError>) {
+        let failed = matches!(result, Err(e) if is_outage(e));
+        let config = match *read_lock(&self.breaker.config) {
+            Some(config) => config,
+            None => return
+        };
+
+        let mut phase = write_lock(&self.breaker.phase);
+        let now = Instant::now();
+        match (&mut *phase, failed) {
+            (_, false) => *phase = Phase::default(),
+            (Phase::Closed { failures }, true) => {
+                failures.retain(|at| now.duration_since(*at) <= config.window);
+                failures.push_back(now);
+                if failures.len() as u32 >= config.failures {
+                    *phase = Phase::Open { until : now + config.cool_down };
+                }
+            },
+            (Phase::HalfOpen { .. }, true) if self.probe => *phase = Phase::Open { until : now + config.cool_down },
+            // a dispatch let through before the breaker opened
+            (Phase::Open { .. } | Phase::HalfOpen { .. }, true) => {}
+        }
+        // recorded, so dropping must not release the probe
+        self.probe = false;
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if !self.probe {
+            return;
+        }
+        let mut phase = write_lock(&self.breaker.phase);
+        if matches!(*phase, Phase::HalfOpen { .. }) {
+            *phase = Phase::Open { until : Instant::now() };
+        }
+    }
+}
+
+/// Whether an error means Wandbox is down, rather than turning a request away
+fn is_outage(e : &WandboxError) -> bool {
+    match e {
+        WandboxError::Network(_) | WandboxError::Timeout(_) | WandboxError::InvalidResponse { .. } => true,
+        WandboxError::Http { status, .. } => *status >= 500,
+        _ => false
+    }
+}
+
+fn system_time(at : Instant) -> SystemTime {
+    SystemTime::now() + at.saturating_duration_since(Instant::now())
+}
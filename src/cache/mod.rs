@@ -1,41 +1,407 @@
-use std::error::Error;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::{Language, Compiler};
+use serde::{Deserialize, Serialize};
 
-pub type CompilerCache = HashMap<String, Language>;
+use crate::{Language, Compiler, WandboxError};
+use crate::error::read_error;
+use crate::{intern, trace};
+use crate::transport::{TransportResponse, WandboxTransport};
 
-pub async fn load() -> Result<CompilerCache, Box<dyn Error>> {
-    // grab wandbox compilers
-    let res = reqwest::get("https://wandbox.org/api/list.json").await?;
-    // retrieve compilers as vector
-    let result : Vec<Compiler> = res.json().await?;
-
-    // we have to build our cache, iterating our vector and organizing
-    // compilers by their language. The language id should be lowercase.
-    let mut comp_cache : CompilerCache = HashMap::new();
-    for c in result {
-        let language_name = c.language.to_ascii_lowercase();
-
-        // see if we can grab a mutable |Language|
-        let entry = comp_cache.get_mut(&language_name);
-        match entry {
-            Some(p) => {
-                p.compilers.push(c);
+/// Wandbox's compilers, grouped by the lowercase identifier of their language
+///
+/// Compilers are normalized as they're inserted: they're filed under their lowercase language,
+/// share a single copy of its name & fall back to their id when they have no display name.
+/// Read access goes through the underlying map, which it dereferences to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "HashMap<String, Language>", into = "HashMap<String, Language>")]
+pub struct CompilerCache(HashMap<String, Language>);
+impl CompilerCache {
+    /// Creates a cache without any compilers
+    pub fn new() -> CompilerCache {
+        CompilerCache::default()
+    }
+
+    /// Adds a compiler to its language, replacing any compiler of the same name
+    ///
+    /// A compiler replaced within its language keeps its position, so the default compiler of a
+    /// language stays the same.
+    ///
+    /// # Arguments
+    /// * `compiler` - The compiler to add, its language may be in any case
+    pub fn insert_compiler(&mut self, compiler : Compiler) {
+        self.insert_all(vec![compiler]);
+    }
+
+    /// Removes a compiler, along with its language if it was the last one
+    ///
+    /// # Arguments
+    /// * `name` - The compiler identifier i.e "gcc-head"
+    pub fn remove_compiler(&mut self, name : &str) -> Option<Compiler> {
+        let key = self.0.iter()
+            .find(|(_k, v)| v.compilers.iter().any(|c| c.name == name))
+            .map(|(k, _v)| k.clone())?;
+        let lang = self.0.get_mut(&key)?;
+
+        let removed = lang.compilers.iter().find(|c| c.name == name).cloned();
+        lang.compilers = lang.compilers.iter().filter(|c| c.name != name).cloned().collect();
+        if lang.compilers.is_empty() {
+            self.0.remove(&key);
+        }
+        removed
+    }
+
+    /// Removes a language & all of its compilers
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier in any case, i.e "C++"
+    pub fn remove_language(&mut self, lang : &str) -> Option<Language> {
+        self.0.remove(&lang.to_ascii_lowercase())
+    }
+
+    /// Looks up a compiler by its identifier
+    ///
+    /// # Arguments
+    /// * `name` - The compiler identifier i.e "gcc-head"
+    pub fn find_compiler(&self, name : &str) -> Option<&Compiler> {
+        self.0.values()
+            .flat_map(|l| l.compilers.iter())
+            .find(|c| c.name == name)
+    }
+
+    /// The languages in the cache, in no particular order
+    pub fn languages(&self) -> impl Iterator<Item = &Language> {
+        self.0.values()
+    }
+
+    /// The number of languages in the cache
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the cache has no languages, and so no compilers
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Keeps the compilers `keep` accepts, dropping languages left without any
+    pub(crate) fn retain_compilers<F : Fn(&str, &Compiler) -> bool>(&mut self, keep : F) {
+        for (k, v) in self.0.iter_mut() {
+            // only copy the compilers when some are dropped
+            if !v.compilers.iter().all(|c| keep(k, c)) {
+                v.compilers = v.compilers.iter().filter(|c| keep(k, c)).cloned().collect();
             }
+        }
+        self.0.retain(|_k, v| !v.compilers.is_empty());
+    }
+
+    /// Inserts compilers in bulk, copying each affected language's compilers only once
+    fn insert_all(&mut self, compilers : Vec<Compiler>) {
+        let mut grouped : HashMap<String, Vec<Compiler>> = HashMap::new();
+        for mut c in compilers {
+            fill_display_name(&mut c);
+            let group = grouped.entry(c.language.to_ascii_lowercase()).or_default();
+            match group.iter().position(|existing| existing.name == c.name) {
+                Some(i) => group[i] = c,
+                None => group.push(c)
+            }
+        }
+
+        for (key, group) in grouped {
+            // a compiler filed under another language moves to this one
+            let names : HashSet<&str> = group.iter().map(|c| c.name.as_str()).collect();
+            self.retain_compilers(|k, c| k == key || !names.contains(c.name.as_str()));
+
+            let name = match self.0.get(&key) {
+                Some(lang) => lang.name.clone(),
+                None => Arc::from(key.as_str())
+            };
+            let mut merged = self.0.get(&key).map(|l| l.compilers.to_vec()).unwrap_or_default();
+            for mut c in group {
+                c.language = name.clone();
+                match merged.iter().position(|existing| existing.name == c.name) {
+                    Some(i) => merged[i] = c,
+                    None => merged.push(c)
+                }
+            }
+            self.0.insert(key, Language { name, compilers : merged.into() });
+        }
+    }
+}
+impl Deref for CompilerCache {
+    type Target = HashMap<String, Language>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<'a> IntoIterator for &'a CompilerCache {
+    type Item = (&'a String, &'a Language);
+    type IntoIter = std::collections::hash_map::Iter<'a, String, Language>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+/// Regroups languages that may not have been normalized, i.e read from a file
+impl From<HashMap<String, Language>> for CompilerCache {
+    fn from(languages : HashMap<String, Language>) -> CompilerCache {
+        build_cache(languages.into_values().flat_map(|l| l.compilers.to_vec()).collect())
+    }
+}
+impl From<CompilerCache> for HashMap<String, Language> {
+    fn from(cache : CompilerCache) -> HashMap<String, Language> {
+        cache.0
+    }
+}
+
+/// Maps each compiler name to its language & position within that language's compilers
+pub type CompilerIndex = HashMap<String, (String, usize)>;
+
+pub fn index(cache : &CompilerCache) -> CompilerIndex {
+    let mut index = CompilerIndex::new();
+    for (k, v) in cache.iter() {
+        for (i, c) in v.compilers.iter().enumerate() {
+            index.insert(c.name.clone(), (k.clone(), i));
+        }
+    }
+    index
+}
+
+/// How the compilers differ between two caches, i.e before & after a `Wandbox::refresh`
+///
+/// Compilers are matched on their identifier & each list is sorted by it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheDiff {
+    /// Compilers only in the newer cache
+    pub added : Vec<Compiler>,
+    /// Compilers only in the older cache
+    pub removed : Vec<Compiler>,
+    /// Compilers in both caches whose details differ i.e their version or switches, as (old, new)
+    pub changed : Vec<(Compiler, Compiler)>,
+    /// Whether Wandbox confirmed the list hasn't changed, in which case the cache was kept as is
+    pub not_modified : bool,
+}
+impl CacheDiff {
+    /// Compares two caches
+    ///
+    /// # Arguments
+    /// * `old` - The cache before the change
+    /// * `new` - The cache after the change
+    pub fn between(old : &CompilerCache, new : &CompilerCache) -> CacheDiff {
+        let by_name = |cache : &CompilerCache| -> BTreeMap<String, Compiler> {
+            cache.values()
+                .flat_map(|l| l.compilers.iter())
+                .map(|c| (c.name.clone(), c.clone()))
+                .collect()
+        };
+        let old = by_name(old);
+        let mut new = by_name(new);
 
-            // create one then..
-            None => {
-                let mut lang = Language {
-                    name : language_name.clone(),
-                    compilers : Vec::new()
-                };
-                lang.compilers.push(c);
-                comp_cache.insert(language_name, lang);
+        let mut diff = CacheDiff::default();
+        for (name, before) in old {
+            match new.remove(&name) {
+                Some(after) if after != before => diff.changed.push((before, after)),
+                Some(_after) => {},
+                None => diff.removed.push(before)
             }
         }
+        diff.added = new.into_values().collect();
+        diff
+    }
+
+    /// Whether no compiler was added, removed or changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
     }
+}
+
+/// The version of the on-disk cache format, bumped whenever the layout changes
+pub const CACHE_FORMAT_VERSION : u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile<C> {
+    version : u32,
+    // seconds since the unix epoch
+    fetched_at : u64,
+    languages : C,
+}
+
+pub fn write(path : &Path, cache : &CompilerCache, fetched_at : SystemTime) -> Result<(), WandboxError> {
+    let contents = to_json(cache, fetched_at)?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+pub fn read(path : &Path) -> Result<(CompilerCache, SystemTime), WandboxError> {
+    let contents = fs::read(path)?;
+    from_json(&contents)
+}
+
+/// Wraps the cache with the format version & fetch time, as stored by `write`
+pub fn to_json(cache : &CompilerCache, fetched_at : SystemTime) -> Result<String, WandboxError> {
+    let fetched_at = fetched_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
 
-    Ok(comp_cache)
-}
\ No newline at end of file
+    let file = CacheFile {
+        version : CACHE_FORMAT_VERSION,
+        fetched_at,
+        languages : cache
+    };
+    Ok(serde_json::to_string(&file)?)
+}
+
+/// Unwraps a cache written by `to_json`, rejecting other format versions
+pub fn from_json(contents : &[u8]) -> Result<(CompilerCache, SystemTime), WandboxError> {
+    // check the version before committing to the layout
+    let version : serde_json::Value = serde_json::from_slice(contents)?;
+    let version = version.get("version").and_then(|v| v.as_u64());
+    if version != Some(CACHE_FORMAT_VERSION as u64) {
+        return Err(WandboxError::UnsupportedCacheVersion {
+            found : version,
+            expected : CACHE_FORMAT_VERSION
+        });
+    }
+
+    // the languages are regrouped as they're read, so compilers share their language's name again
+    let file : CacheFile<CompilerCache> = intern::scope(|| serde_json::from_slice(contents))?;
+    Ok((file.languages, UNIX_EPOCH + Duration::from_secs(file.fetched_at)))
+}
+
+/// The validators Wandbox sent along with list.json, sent back on the next fetch so an unchanged
+/// list isn't downloaded again
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Validators {
+    etag : Option<String>,
+    last_modified : Option<String>,
+}
+impl Validators {
+    fn from_response(res : &TransportResponse) -> Validators {
+        Validators {
+            etag : res.header("ETag").map(|v| v.to_string()),
+            last_modified : res.header("Last-Modified").map(|v| v.to_string())
+        }
+    }
+
+    /// The conditional request headers, none if Wandbox sent no validators
+    fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push((String::from("If-None-Match"), etag.clone()));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.push((String::from("If-Modified-Since"), last_modified.clone()));
+        }
+        headers
+    }
+}
+
+/// The outcome of fetching list.json
+pub enum Fetched {
+    /// The list was downloaded, along with the compilers that were skipped
+    Modified { compilers : Vec<Compiler>, warnings : Vec<String>, validators : Validators },
+    /// Wandbox confirmed the list hasn't changed since it was fetched with the given validators
+    NotModified,
+}
+
+/// Fetches the compilers of list.json, see `parse` for what `strict` changes
+pub async fn load(transport : &dyn WandboxTransport, endpoint : &str, strict : bool) -> Result<(Vec<Compiler>, Vec<String>, Validators), WandboxError> {
+    let span = trace::span!("wandbox::load", endpoint, status = tracing::field::Empty, compilers = tracing::field::Empty);
+    span.run_async(async {
+        match fetch(transport, endpoint, strict, &Validators::default(), &span).await? {
+            Fetched::Modified { compilers, warnings, validators } => {
+                span.record("compilers", compilers.len());
+                Ok((compilers, warnings, validators))
+            },
+            // unreachable without validators, as 304 is only accepted for a conditional request
+            Fetched::NotModified => Err(WandboxError::http(304, ""))
+        }
+    }).await
+}
+
+/// Fetches list.json unless it hasn't changed since it was fetched with `validators`
+pub async fn fetch(transport : &dyn WandboxTransport, endpoint : &str, strict : bool, validators : &Validators,
+                   span : &trace::Span) -> Result<Fetched, WandboxError> {
+    // grab wandbox compilers
+    let headers = validators.headers();
+    let conditional = !headers.is_empty();
+    let res = transport.get_json_with(&format!("{}/api/list.json", endpoint), headers).await?;
+    span.record("status", res.status);
+    if res.status == 304 && conditional {
+        return Ok(Fetched::NotModified);
+    }
+    if !res.is_success() {
+        return Err(read_error(res));
+    }
+    let validators = Validators::from_response(&res);
+    let (status, body) = (res.status, res.body);
+
+    let entries : Vec<serde_json::Value> = match serde_json::from_str(&body) {
+        Ok(entries) => entries,
+        Err(e) => return Err(WandboxError::invalid_response(status, &body, e))
+    };
+    match convert(entries, strict) {
+        Ok((compilers, warnings)) => Ok(Fetched::Modified { compilers, warnings, validators }),
+        Err(e) => Err(WandboxError::invalid_response(status, &body, e))
+    }
+}
+
+/// A snapshot of Wandbox's list.json bundled with the crate, for use without network access
+pub const SNAPSHOT : &str = include_str!("list.json");
+
+/// Builds the cache from the contents of list.json
+///
+/// Unless `strict` is set, compilers that can't be understood are skipped rather than failing the
+/// whole list, with a warning for each.
+pub fn parse(json : &str, strict : bool) -> Result<(CompilerCache, Vec<String>), WandboxError> {
+    let entries : Vec<serde_json::Value> = serde_json::from_str(json)?;
+    let (compilers, warnings) = convert(entries, strict)?;
+    Ok((build_cache(compilers), warnings))
+}
+
+/// Converts the entries of list.json one at a time, so a single malformed compiler only fails the
+/// list in `strict` mode
+fn convert(entries : Vec<serde_json::Value>, strict : bool) -> Result<(Vec<Compiler>, Vec<String>), serde_json::Error> {
+    // the compilers share their language & compile command strings
+    intern::scope(|| convert_entries(entries, strict))
+}
+
+fn convert_entries(entries : Vec<serde_json::Value>, strict : bool) -> Result<(Vec<Compiler>, Vec<String>), serde_json::Error> {
+    let mut compilers = Vec::new();
+    let mut warnings = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let name = match entry.get("name").and_then(|n| n.as_str()) {
+            Some(name) => name.to_string(),
+            None => format!("#{}", i)
+        };
+        match serde_json::from_value(entry) {
+            Ok(c) => compilers.push(c),
+            Err(e) if !strict => warnings.push(format!("Skipped compiler {}: {}", name, e)),
+            Err(e) => return Err(e)
+        }
+    }
+    Ok((compilers, warnings))
+}
+
+/// Organizes compilers by language into the cache a `Wandbox` looks compilers up in
+///
+/// Language ids are lowercased & compilers without a display name get their id as one, just as
+/// for the compiler list a `Wandbox` fetches itself.
+///
+/// # Arguments
+/// * `result` - The compilers, i.e as returned by `fetch_compiler_list`
+pub fn build_cache(result : Vec<Compiler>) -> CompilerCache {
+    let mut cache = CompilerCache::new();
+    cache.insert_all(result);
+    cache
+}
+/// Falls back to the compiler id for compilers without a display name
+fn fill_display_name(c : &mut Compiler) {
+    if c.display_name.is_empty() {
+        c.display_name = c.name.clone();
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::watch;
+
+use crate::{write_lock, CompilationRequest, CompilationResult, DispatchInfo, WandboxError};
+
+/// A successful dispatch, the result along with details of the reply
+pub(crate) type Outcome = (CompilationResult, DispatchInfo);
+
+/// Shares the reply to a compilation with identical ones dispatched while it's in flight, see
+/// `WandboxBuilder::coalesce_requests`
+#[derive(Default)]
+pub(crate) struct Coalescer {
+    enabled : AtomicBool,
+    // request body -> the reply once the first of them has it
+    in_flight : RwLock<HashMap<String, watch::Receiver<Option<Outcome>>>>,
+}
+
+/// Takes a request out of `in_flight` once it's done, whether it finished or was dropped
+struct Entry<'a> {
+    coalescer : &'a Coalescer,
+    key : String,
+}
+
+impl Coalescer {
+    pub fn set_enabled(&self, enabled : bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Runs `dispatch` unless an identical request is already in flight, in which case its
+    /// reply is returned instead
+    ///
+    /// Saved compilations always run, as each gets its own permlink. When the request already
+    /// in flight fails, or is cancelled, `dispatch` runs after all since errors can't be shared.
+    pub async fn run<F>(&self, request : &CompilationRequest, dispatch : F) -> Result<Outcome, WandboxError>
+        where F : Future<Output = Result<Outcome, WandboxError>> {
+        if !self.enabled.load(Ordering::SeqCst) || request.save {
+            return dispatch.await;
+        }
+
+        let key = serde_json::to_string(request).expect("CompilationRequest always serializes");
+        let leader = {
+            let mut in_flight = write_lock(&self.in_flight);
+            match in_flight.get(&key) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    in_flight.insert(key.clone(), receiver);
+                    Ok(sender)
+                }
+            }
+        };
+
+        match leader {
+            Ok(sender) => {
+                let _entry = Entry { coalescer : self, key };
+                let result = dispatch.await;
+                if let Ok(outcome) = &result {
+                    sender.send_replace(Some(outcome.clone()));
+                }
+                result
+            },
+            Err(mut receiver) => {
+                // the sender is dropped without a reply when the first request fails
+                let shared = receiver.wait_for(|outcome| outcome.is_some()).await.ok().and_then(|outcome| outcome.clone());
+                match shared {
+                    Some(outcome) => Ok(outcome),
+                    None => dispatch.await
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Entry<'_> {
+    fn drop(&mut self) {
+        write_lock(&self.coalescer.in_flight).remove(&self.key);
+    }
+}
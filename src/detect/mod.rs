@@ -0,0 +1,75 @@
+/// A line-level hint that code is written in a language
+type Rule = (&'static str, fn(&str) -> bool);
+
+/// Interpreters named on a shebang line, these settle the language on their own
+const SHEBANGS : &[(&str, &str)] = &[
+    ("python", "python"),
+    ("bash", "bash script"),
+    ("/sh", "bash script"),
+    ("node", "javascript"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+];
+
+/// Hints checked against every trimmed line, in order
+const RULES : &[Rule] = &[
+    ("c++", |l| l.starts_with("#include <iostream>") || l.contains("std::cout") || l.contains("std::vector")),
+    ("c++", |l| l.starts_with("using namespace std") || l.starts_with("template<") || l.starts_with("template <")),
+    ("c", |l| l.starts_with("#include")),
+    ("rust", |l| l.starts_with("fn main(") || l.contains("println!(") || l.starts_with("let mut ") || l.starts_with("use std::")),
+    ("go", |l| l.starts_with("package main") || l.starts_with("func main(") || l.contains("fmt.Println")),
+    ("python", |l| (l.starts_with("def ") && l.ends_with(':')) || l.starts_with("if __name__") || l.starts_with("elif ")),
+    ("java", |l| l.contains("public static void main") || l.contains("System.out.println")),
+    ("c#", |l| l.contains("Console.WriteLine") || l.contains("static void Main") || l.starts_with("using System;")),
+    ("typescript", |l| l.starts_with("interface ") || l.contains(": string") || l.contains(": number")),
+    ("javascript", |l| l.contains("console.log(") || l.starts_with("function ") || l.starts_with("const ")),
+    ("ruby", |l| l.starts_with("puts ") || l.starts_with("require '") || (l.starts_with("def ") && !l.ends_with(':'))),
+    ("php", |l| l.starts_with("<?php")),
+    ("haskell", |l| l.starts_with("main = ") || l.starts_with("main :: ") || l.contains("putStrLn")),
+    ("perl", |l| l.starts_with("my $") || l.starts_with("use strict;")),
+    ("lua", |l| l.starts_with("local function ") || l.starts_with("local ")),
+];
+
+/// Languages whose hints imply another's, i.e c++ code usually also looks like c
+const SUPERSETS : &[(&str, &str)] = &[
+    ("c++", "c"),
+    ("typescript", "javascript"),
+];
+
+/// Guesses the language of some code from telltale lines such as a shebang, `#include` or
+/// `fn main`
+///
+/// Returns the language identifier Wandbox uses, or `None` when nothing matches or the hints point
+/// to several languages.
+///
+/// # Arguments
+/// * `code` - The code to look at
+pub fn detect_language(code : &str) -> Option<&'static str> {
+    let first = code.trim_start().lines().next()?;
+    if let Some(interpreter) = first.strip_prefix("#!") {
+        return SHEBANGS.iter()
+            .find(|(name, _lang)| interpreter.contains(name))
+            .map(|(_name, lang)| *lang);
+    }
+
+    let mut found : Vec<&'static str> = Vec::new();
+    for line in code.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        for (lang, rule) in RULES {
+            if !found.contains(lang) && rule(line) {
+                found.push(lang);
+            }
+        }
+    }
+    for (superset, subset) in SUPERSETS {
+        if found.contains(superset) {
+            found.retain(|lang| lang != subset);
+        }
+    }
+
+    match found.as_slice() {
+        [lang] => Some(lang),
+        _ => None
+    }
+}
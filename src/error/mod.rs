@@ -0,0 +1,273 @@
+use core::fmt;
+use std::time::{Duration, SystemTime};
+
+use serde::de::DeserializeOwned;
+
+use crate::transport::TransportResponse;
+
+/// The result of fallible Wandbox operations
+pub type WandboxResult<T> = Result<T, WandboxError>;
+
+/// An error raised while building or dispatching Wandbox requests
+#[derive(Debug)]
+pub enum WandboxError {
+    /// The request is wrong in itself, i.e a misspelled compiler, found before anything was sent
+    Build(BuildError),
+    /// Wandbox replied with an unsuccessful status code, `body` holds the start of the reply
+    Http { status : u16, body : String },
+    /// Wandbox could not be reached
+    Network(reqwest::Error),
+    /// Wandbox did not respond within the configured timeout
+    Timeout(reqwest::Error),
+    /// Wandbox replied with something that could not be understood
+    Deserialize(serde_json::Error),
+    /// Wandbox replied successfully with a body that could not be understood, i.e an HTML error
+    /// page from a proxy, `body` holds the start of the reply
+    InvalidResponse { status : u16, body : String, source : serde_json::Error },
+    /// A file could not be read or written
+    Io(std::io::Error),
+    /// The Wandbox endpoint is not a valid http(s) url
+    InvalidEndpoint(String),
+    /// The proxy is not a valid url
+    InvalidProxy(String),
+    /// Wandbox is turning requests away because too many were sent, `retry_after` holds how long
+    /// it asked to wait, if it said so
+    RateLimited { retry_after : Option<Duration> },
+    /// The compilation was cancelled through its `CancelHandle`
+    Cancelled,
+    /// A compilation was saved but Wandbox replied with neither a permlink nor a url
+    MissingPermlink,
+    /// An additional source file has no name
+    EmptyFileName,
+    /// An additional source file shares its name with one added before it
+    DuplicateFile(String),
+    /// A cache file was written with a format this version of the crate does not understand
+    UnsupportedCacheVersion { found : Option<u64>, expected : u32 },
+    /// A builder was saved with a format this version of the crate does not understand, i.e by a
+    /// newer version
+    UnsupportedBuilderVersion { found : Option<u64>, expected : u32 },
+    /// The compiler list could be neither fetched from Wandbox nor loaded from the fallback cache
+    /// file, with why each attempt failed
+    FallbackFailed { network : Box<WandboxError>, cache : Box<WandboxError> },
+    /// A language alias would shadow a real language name or compiler id
+    AliasConflict(String),
+    /// A pinned default compiler is unknown or doesn't belong to the language it was pinned for
+    InvalidDefault { language : String, compiler : String },
+    /// Wandbox kept failing so the circuit breaker isn't sending requests, `retry_at` is when
+    /// one may be sent again
+    CircuitOpen { retry_at : SystemTime },
+    /// A permlink id has characters other than letters, digits, `_` & `-`
+    InvalidPermlink(String),
+    /// A template name has characters other than letters, digits, `_` & `-`
+    InvalidTemplate(String),
+    /// A method of `blocking::Wandbox` was called from within a tokio runtime, which it would stall
+    BlockingInRuntime,
+    /// `WandboxBuilder::auto_refresh` was used with `blocking::Wandbox`, which has no runtime to
+    /// refresh in the background with
+    AutoRefreshUnsupported,
+}
+
+/// A problem with a request found by `CompilationBuilder::build`, before anything is sent
+///
+/// These are mistakes in the request itself, i.e a misspelled compiler, as opposed to Wandbox
+/// failing to handle it. They're returned wrapped in `WandboxError::Build`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// No compilation target was set on the builder
+    MissingTarget,
+    /// The code to compile is empty
+    EmptyCode,
+    /// The target is neither a known language nor a known compiler, `suggestions` holds up to
+    /// three of the closest languages, compilers & aliases
+    UnknownTarget { input : String, suggestions : Vec<String> },
+    /// A compiler prefix matches several compilers with no single newest version
+    AmbiguousTarget { target : String, candidates : Vec<String> },
+    /// The language has no compiler available to compile with
+    NoDefaultCompiler { language : String },
+    /// Raw compiler options were given for a compiler that doesn't accept them
+    RawOptionsUnsupported { compiler : String },
+    /// Raw runtime options were given for a compiler that doesn't accept them
+    RuntimeOptionsUnsupported { compiler : String },
+    /// A switch was given that the compiler doesn't offer, `valid` holds the options of the
+    /// closest select group or else every switch the compiler offers
+    UnknownSwitch { compiler : String, switch : String, valid : Vec<String> },
+    /// The code or stdin is larger than the `RequestLimits` allow, sizes are in bytes
+    TooLarge { field : String, size : usize, limit : usize },
+    /// A command line of options could not be split, `reason` tells what's wrong with it
+    MalformedOptions { input : String, reason : String },
+    /// A builder flag helper such as `cpp_standard` was used with a compiler other than gcc or
+    /// clang, `flag` is the name of the helper
+    FlagUnsupported { compiler : String, flag : String },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::MissingTarget => write!(f, "No language or compiler was set to compile with"),
+            BuildError::EmptyCode => write!(f, "There is no code to compile"),
+            BuildError::UnknownTarget { input, suggestions } if suggestions.is_empty() => write!(f, "Unable to find compiler or language for target {}", input),
+            BuildError::UnknownTarget { input, suggestions } => write!(f, "Unable to find compiler or language for target {}, did you mean {}?", input, suggestions.join(", ")),
+            BuildError::AmbiguousTarget { target, candidates } => write!(f, "Target {} is ambiguous, it could mean any of: {}", target, candidates.join(", ")),
+            BuildError::NoDefaultCompiler { language } => write!(f, "Unable to determine default compiler for language {}", language),
+            BuildError::RawOptionsUnsupported { compiler } => write!(f, "Compiler {} does not accept raw compiler options", compiler),
+            BuildError::RuntimeOptionsUnsupported { compiler } => write!(f, "Compiler {} does not accept raw runtime options", compiler),
+            BuildError::UnknownSwitch { compiler, switch, valid } if valid.is_empty() => write!(f, "Compiler {} has no switch named {}", compiler, switch),
+            BuildError::UnknownSwitch { compiler, switch, valid } => write!(f, "Compiler {} has no switch named {}, valid switches are {}", compiler, switch, valid.join(", ")),
+            BuildError::TooLarge { field, size, limit } => write!(f, "The {} is {} bytes, more than the limit of {} bytes", field, size, limit),
+            BuildError::MalformedOptions { input, reason } => write!(f, "Unable to split options {}: {}", input, reason),
+            BuildError::FlagUnsupported { compiler, flag } => write!(f, "Compiler {} does not support {}, only gcc & clang {}", compiler, flag, supporting(flag)),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<reqwest::Error> for WandboxError {
+    fn from(e : reqwest::Error) -> WandboxError {
+        if e.is_timeout() {
+            WandboxError::Timeout(e)
+        }
+        else {
+            WandboxError::Network(e)
+        }
+    }
+}
+
+impl From<serde_json::Error> for WandboxError {
+    fn from(e : serde_json::Error) -> WandboxError {
+        WandboxError::Deserialize(e)
+    }
+}
+
+impl From<std::io::Error> for WandboxError {
+    fn from(e : std::io::Error) -> WandboxError {
+        WandboxError::Io(e)
+    }
+}
+
+impl From<BuildError> for WandboxError {
+    fn from(e : BuildError) -> WandboxError {
+        WandboxError::Build(e)
+    }
+}
+
+/// How much of a reply is kept in errors, in bytes
+const BODY_PREFIX_LEN : usize = 1024;
+
+/// Cuts a reply down to `BODY_PREFIX_LEN` bytes on a character boundary
+fn body_prefix(body : &str) -> String {
+    let mut end = body.len().min(BODY_PREFIX_LEN);
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    body[..end].to_string()
+}
+
+impl WandboxError {
+    pub(crate) fn http(status : u16, body : &str) -> WandboxError {
+        WandboxError::Http { status, body : body_prefix(body) }
+    }
+
+    pub(crate) fn invalid_response(status : u16, body : &str, source : serde_json::Error) -> WandboxError {
+        WandboxError::InvalidResponse { status, body : body_prefix(body), source }
+    }
+
+    /// Whether this error was caused by Wandbox not responding within the configured timeout
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, WandboxError::Timeout(_))
+    }
+
+    /// Whether this error was caused by Wandbox rate limiting requests
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, WandboxError::RateLimited { .. })
+    }
+
+    /// The problem with the request, if this error was found before anything was sent
+    pub fn build_error(&self) -> Option<&BuildError> {
+        match self {
+            WandboxError::Build(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// Reads the delay of a Retry-After header. Only a number of seconds is understood, an HTTP date
+/// is treated as missing.
+pub(crate) fn retry_after(response : &TransportResponse) -> Option<Duration> {
+    let value = response.header("Retry-After")?;
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Turns an unsuccessful reply from Wandbox into an error
+pub(crate) fn read_error(response : TransportResponse) -> WandboxError {
+    if response.status == 429 {
+        return WandboxError::RateLimited { retry_after : retry_after(&response) };
+    }
+    WandboxError::http(response.status, &response.body)
+}
+
+/// Reads a JSON reply from Wandbox, surfacing unsuccessful status codes
+pub(crate) fn read_json<T : DeserializeOwned>(response : TransportResponse) -> Result<T, WandboxError> {
+    if !response.is_success() {
+        return Err(read_error(response));
+    }
+    serde_json::from_str(&response.body).map_err(|e| WandboxError::invalid_response(response.status, &response.body, e))
+}
+
+impl fmt::Display for WandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WandboxError::Build(e) => write!(f, "{}", e),
+            WandboxError::Http { status, body } => write!(f, "Wandbox replied with: {}\n\
+            This could mean WandBox is experiencing an outage, or the requested resource does not exist\n{}", status, body),
+            WandboxError::Network(e) => write!(f, "Unable to reach Wandbox: {}", e),
+            WandboxError::Timeout(e) => write!(f, "Wandbox did not respond in time: {}", e),
+            WandboxError::Deserialize(e) => write!(f, "Wandbox replied with an unexpected response: {}", e),
+            WandboxError::InvalidResponse { status, body, source } => write!(f, "Wandbox replied with an unexpected response ({}): {}\n{}", status, source, body),
+            WandboxError::Io(e) => write!(f, "{}", e),
+            WandboxError::InvalidEndpoint(endpoint) => write!(f, "Wandbox endpoint {} must be an http:// or https:// url with a host", endpoint),
+            WandboxError::InvalidProxy(proxy) => write!(f, "Proxy {} is not a valid url", proxy),
+            WandboxError::RateLimited { retry_after : Some(delay) } => write!(f, "Wandbox is rate limiting requests, retry in {} seconds", delay.as_secs()),
+            WandboxError::RateLimited { retry_after : None } => write!(f, "Wandbox is rate limiting requests, retry later"),
+            WandboxError::Cancelled => write!(f, "The compilation was cancelled"),
+            WandboxError::MissingPermlink => write!(f, "Wandbox saved the compilation but did not reply with a permlink"),
+            WandboxError::EmptyFileName => write!(f, "Additional source files must have a file name"),
+            WandboxError::DuplicateFile(file) => write!(f, "A source file named {} was already added", file),
+            WandboxError::UnsupportedCacheVersion { found : Some(found), expected } => write!(f, "Cache file has format version {}, expected {}", found, expected),
+            WandboxError::UnsupportedCacheVersion { found : None, expected } => write!(f, "Cache file has no format version, expected {}", expected),
+            WandboxError::UnsupportedBuilderVersion { found : Some(found), expected } => write!(f, "Saved builder has format version {}, expected at most {}", found, expected),
+            WandboxError::UnsupportedBuilderVersion { found : None, expected } => write!(f, "Saved builder has no format version, expected at most {}", expected),
+            WandboxError::FallbackFailed { network, cache } => write!(f, "Unable to fetch the compiler list ({}) or load the cached one ({})", network, cache),
+            WandboxError::AliasConflict(alias) => write!(f, "{} is already a language or compiler and cannot be used as an alias", alias),
+            WandboxError::InvalidDefault { language, compiler } => write!(f, "{} is not a {} compiler and cannot be its default", compiler, language),
+            WandboxError::CircuitOpen { retry_at } => {
+                let wait = retry_at.duration_since(SystemTime::now()).unwrap_or_default();
+                write!(f, "Wandbox keeps failing, requests are paused for another {} seconds", wait.as_secs())
+            },
+            WandboxError::InvalidPermlink(id) => write!(f, "{} is not a valid permlink id", id),
+            WandboxError::InvalidTemplate(name) => write!(f, "{} is not a valid template name", name),
+            WandboxError::BlockingInRuntime => write!(f, "The blocking API cannot be used from within an async runtime, use the async Wandbox instead"),
+            WandboxError::AutoRefreshUnsupported => write!(f, "Background refreshes need a tokio runtime, which the blocking API doesn't run"),
+        }
+    }
+}
+
+impl std::error::Error for WandboxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WandboxError::Build(e) => Some(e),
+            WandboxError::Network(e) | WandboxError::Timeout(e) => Some(e),
+            WandboxError::Deserialize(e) | WandboxError::InvalidResponse { source : e, .. } => Some(e),
+            WandboxError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// The gcc & clang compilers a flag helper works with, for `FlagUnsupported` messages
+fn supporting(flag : &str) -> &'static str {
+    match flag {
+        "cpp_standard" => "C++ compilers do",
+        _ => "do"
+    }
+}
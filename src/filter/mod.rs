@@ -0,0 +1,42 @@
+use std::collections::HashSet;
+
+use crate::cache::CompilerCache;
+
+/// Decides which compilers & languages from Wandbox's list are exposed
+///
+/// A compiler is kept unless it or its language is blocked. When an allowlist is set, the
+/// compiler must also be listed itself or belong to a listed language.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub blocked_compilers : HashSet<String>,
+    pub blocked_languages : HashSet<String>,
+    pub allowed_compilers : Option<HashSet<String>>,
+    pub allowed_languages : Option<HashSet<String>>,
+}
+
+impl Filter {
+    pub fn blocking(comps : HashSet<String>, langs : HashSet<String>) -> Filter {
+        Filter {
+            blocked_compilers : comps,
+            blocked_languages : langs,
+            ..Filter::default()
+        }
+    }
+
+    fn allows(&self, lang : &str, comp : &str) -> bool {
+        if self.blocked_languages.contains(lang) || self.blocked_compilers.contains(comp) {
+            return false;
+        }
+        if self.allowed_compilers.is_none() && self.allowed_languages.is_none() {
+            return true;
+        }
+
+        self.allowed_compilers.as_ref().is_some_and(|c| c.contains(comp)) ||
+            self.allowed_languages.as_ref().is_some_and(|l| l.contains(lang))
+    }
+
+    /// Drops every compiler the filter rejects, and languages left without any compilers
+    pub fn apply(&self, cache : &mut CompilerCache) {
+        cache.retain_compilers(|lang, c| self.allows(lang, &c.name));
+    }
+}
@@ -0,0 +1,51 @@
+//! Shares identical strings between the compilers of a list as it's read, i.e the language of
+//! every C++ compiler, used through `#[serde(with = "crate::intern")]`
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+thread_local! {
+    // the pool of the `scope` running on this thread, `None` outside of one
+    static POOL : RefCell<Option<HashSet<Arc<str>>>> = const { RefCell::new(None) };
+}
+
+/// Puts back the pool of an enclosing scope, even if the scope panicked
+struct Restore(Option<HashSet<Arc<str>>>);
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        POOL.with(|pool| *pool.borrow_mut() = outer);
+    }
+}
+
+/// Runs `f` with a fresh pool, so equal strings deserialized within it share one allocation
+pub(crate) fn scope<T>(f : impl FnOnce() -> T) -> T {
+    let _restore = Restore(POOL.with(|pool| pool.replace(Some(HashSet::new()))));
+    f()
+}
+
+/// The pooled copy of `s`, or a new one outside of a `scope`
+fn intern(s : &str) -> Arc<str> {
+    POOL.with(|pool| match &mut *pool.borrow_mut() {
+        Some(pool) => match pool.get(s) {
+            Some(shared) => shared.clone(),
+            None => {
+                let shared : Arc<str> = Arc::from(s);
+                pool.insert(shared.clone());
+                shared
+            }
+        },
+        None => Arc::from(s)
+    })
+}
+
+pub(crate) fn serialize<S : Serializer>(s : &Arc<str>, serializer : S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(s)
+}
+
+pub(crate) fn deserialize<'de, D : Deserializer<'de>>(deserializer : D) -> Result<Arc<str>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Ok(intern(&s))
+}
@@ -1,19 +1,135 @@
+//! An api binding for [Wandbox](https://wandbox.org)
+//!
+//! # Features
+//! * `native-tls` (default) - Reaches https endpoints through the platform's TLS library, OpenSSL
+//!   on Linux
+//! * `rustls` - Reaches https endpoints through rustls with bundled root certificates, for static
+//!   & cross compiled builds without OpenSSL
+//! * `blocking` - Adds `blocking::Wandbox`, a synchronous API built on `reqwest::blocking` for use
+//!   outside async code
+//! * `test-util` - Adds `MemoryTransport`, a transport serving canned replies for tests
+//!
+//! # TLS backends
+//! | Features                                  | TLS          | Reaches `https://wandbox.org` |
+//! |-------------------------------------------|--------------|-------------------------------|
+//! | default                                   | `native-tls` | Yes                           |
+//! | `--no-default-features --features rustls` | `rustls`     | Yes                           |
+//! | `--no-default-features`                   | None         | No, plain http endpoints only |
+//!
+//! When both `native-tls` & `rustls` are enabled, i.e `--features rustls` on top of the defaults,
+//! `native-tls` is used. Building without either leaves out TLS entirely, which only suits
+//! self-hosted instances served over plain http, as the default `WANDBOX_URL` can't be reached.
+
 mod tests;
 mod cache;
+mod stream;
+mod error;
+mod alias;
+mod version;
+mod filter;
+mod throttle;
+mod breaker;
+mod coalesce;
+mod memo;
+mod detect;
+mod transport;
+mod suggest;
+mod listing;
+mod sanitize;
+mod preview;
+mod shell;
+mod trace;
+mod intern;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 
 use core::fmt;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::cmp::Ordering;
 
 use serde::{Deserialize, Serialize};
-use crate::cache::CompilerCache;
-use std::sync::{RwLock, Arc};
-use std::error::Error;
+use crate::cache::{CompilerIndex, Fetched, Validators};
+use crate::alias::AliasTable;
+use crate::filter::Filter;
+use crate::throttle::{Permit, Throttle};
+use crate::breaker::{Breaker, Ticket};
+use crate::coalesce::Coalescer;
+use crate::memo::ResultCache;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime};
+use std::path::Path;
+use std::io;
+
+use std::future::Future;
+
+use futures_core::Stream;
+use futures_util::future::join_all;
+use futures_util::stream::{self as stream_util, StreamExt};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+pub use crate::stream::CompileEvent;
+pub use crate::error::{BuildError, WandboxError, WandboxResult};
+pub use crate::cache::{build_cache, CacheDiff, CompilerCache};
+pub use crate::version::Version;
+pub use crate::alias::language_for_extension;
+pub use crate::detect::detect_language;
+pub use crate::sanitize::{Sanitize, SanitizedResult};
+pub use crate::transport::{ChunkStream, ReqwestTransport, StreamResponse, TransportResponse, WandboxTransport};
+#[cfg(any(test, feature = "test-util"))]
+pub use crate::transport::{MemoryTransport, RecordedRequest};
+use crate::error::{read_error, read_json};
 
-use std::collections::HashSet;
+/// The Wandbox instance used when no endpoint is configured
+pub const WANDBOX_URL : &str = "https://wandbox.org";
+
+/// The most bytes of a body passed to the logger set with `Wandbox::set_wire_logger`, unless
+/// changed with `Wandbox::set_wire_log_limit`
+pub const DEFAULT_WIRE_LOG_BYTES : usize = 4 * 1024;
+
+/// The most bytes of reply headers, names & values together, kept in a `DispatchInfo`
+pub const MAX_CAPTURED_HEADER_BYTES : usize = 8 * 1024;
+
+/// The most compilations `Wandbox::run_matrix` runs at once
+pub const MATRIX_CONCURRENCY : usize = 4;
 
 /// The main cache that holds on to the compiler cache
+///
+/// Cloning is cheap: clones share the cache, so refreshes, blocklist changes, aliases and the
+/// concurrency limit apply to every clone. A background refresh keeps running until the last clone
+/// is dropped.
+#[derive(Clone)]
 pub struct Wandbox {
+    // the compiler list as last fetched, before `filter` is applied
+    source : Arc<RwLock<CompilerCache>>,
     cache : Arc<RwLock<CompilerCache>>,
+    // compiler name -> (language name, position in that language's compilers), always locked
+    // after `cache` and updated alongside it
+    index : Arc<RwLock<CompilerIndex>>,
+    aliases : Arc<RwLock<AliasTable>>,
+    strategy : Arc<RwLock<DefaultStrategy>>,
+    endpoint : String,
+    transport : Arc<dyn WandboxTransport>,
+    // the longest a rate limited compilation waits before its one retry, if it retries at all
+    rate_limit_retry : Option<Duration>,
+    throttle : Arc<Throttle>,
+    breaker : Arc<Breaker>,
+    coalescer : Arc<Coalescer>,
+    results : Arc<ResultCache>,
+    limits : Arc<RwLock<RequestLimits>>,
+    hooks : Arc<RwLock<Hooks>>,
+    fetched_at : Arc<RwLock<SystemTime>>,
+    // locked before `source`, `cache` & `index` whenever the cache is rebuilt
+    filter : Arc<RwLock<Filter>>,
+    // compilers skipped from the last list.json, and whether to fail on them instead
+    load_warnings : Arc<RwLock<Vec<String>>>,
+    strict : bool,
+    // the ETag & Last-Modified of the last list.json, sent back to skip unchanged downloads
+    validators : Arc<RwLock<Validators>>,
+    // shared by clones, the task is stopped when the last of them is dropped
+    refresher : Option<Arc<RefreshTask>>,
 }
 impl Wandbox {
     /// Initializes the cache for Wandbox requests
@@ -40,358 +156,3344 @@ impl Wandbox {
     ///    };
     /// }
     ///```
-    pub async fn new(comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> Result<Wandbox, Box<dyn Error>> {
+    pub async fn new(comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        Wandbox::with_endpoint(WANDBOX_URL, comps, langs).await
+    }
 
-        let mut cache : CompilerCache = cache::load().await?;
+    /// Initializes the cache for Wandbox requests against a specific Wandbox instance
+    ///
+    /// This is useful for self-hosted Wandbox instances or staging mirrors.
+    ///
+    /// # Arguments
+    /// * `endpoint` - The base url of the Wandbox instance i.e "https://wandbox.org"
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub async fn with_endpoint(endpoint : &str, comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        let mut builder = WandboxBuilder::new().endpoint(endpoint);
+        if let Some(comps) = comps {
+            builder = builder.blocked_compilers(comps);
+        }
+        if let Some(langs) = langs {
+            builder = builder.blocked_languages(langs);
+        }
+        builder.build().await
+    }
 
+    /// Initializes the cache for Wandbox requests, sending every request through an existing client
+    ///
+    /// This is useful when requests have to go through a proxy, trust custom root certificates or
+    /// carry a specific user agent.
+    ///
+    /// # Arguments
+    /// * `client` - The client used to reach Wandbox
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub async fn new_with_client(client : reqwest::Client, comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        let mut builder = WandboxBuilder::new().client(client);
+        if let Some(comps) = comps {
+            builder = builder.blocked_compilers(comps);
+        }
         if let Some(langs) = langs {
-            cache = cache.into_iter().filter(|(_x, v)| !langs.contains(&v.name)).collect();
+            builder = builder.blocked_languages(langs);
         }
+        builder.build().await
+    }
 
+    /// Initializes the cache for Wandbox requests, sending every request through a transport of
+    /// your own
+    ///
+    /// This is useful to serve canned replies in tests, see `MemoryTransport`.
+    ///
+    /// # Arguments
+    /// * `transport` - The transport used to reach Wandbox
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub async fn new_with_transport(transport : Box<dyn WandboxTransport>, comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        let mut builder = WandboxBuilder::new().transport(transport);
         if let Some(comps) = comps {
-            for (_k, v) in cache.iter_mut() {
-                for str in &comps {
-                    v.remove_compiler(str);
-                }
-            }
+            builder = builder.blocked_compilers(comps);
+        }
+        if let Some(langs) = langs {
+            builder = builder.blocked_languages(langs);
         }
+        builder.build().await
+    }
 
-        // adjust language names to lower
-        for (_k, v) in cache.iter_mut() {
-            for mut c in v.compilers.iter_mut() {
-                c.language = c.language.to_ascii_lowercase();
-            }
+    /// Initializes the cache for Wandbox requests, falling back to a previously saved compiler list
+    /// when Wandbox can't be reached
+    ///
+    /// A successfully fetched list is written to `path`, so the next start has a fallback. When
+    /// both attempts fail, the error is `WandboxError::FallbackFailed` describing each of them.
+    ///
+    /// # Arguments
+    /// * `path` - The file the compiler list is saved to & loaded from
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub async fn new_with_fallback<P : AsRef<Path>>(path : P, comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<(Wandbox, CacheOrigin)> {
+        let mut builder = WandboxBuilder::new();
+        if let Some(comps) = comps {
+            builder = builder.blocked_compilers(comps);
         }
+        if let Some(langs) = langs {
+            builder = builder.blocked_languages(langs);
+        }
+        builder.build_with_fallback(path).await
+    }
 
-        Ok(Wandbox {
-            cache: Arc::new(RwLock::new(cache))
-        })
+    /// Initializes the cache from a snapshot of Wandbox's compiler list bundled with the crate,
+    /// without contacting Wandbox
+    ///
+    /// This is useful for tests, or as a fallback when Wandbox can't be reached. The snapshot may
+    /// be missing compilers Wandbox has added since it was taken. Requests are sent to
+    /// wandbox.org.
+    ///
+    /// # Arguments
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub fn from_embedded_snapshot(comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        Wandbox::from_list_json(cache::SNAPSHOT, comps, langs)
     }
 
-    /// Gets a list of compilers given a certain language
+    /// Initializes the cache from the contents of Wandbox's list.json, fetched some other way
+    ///
+    /// The list is organized & filtered just like a fetched one. Requests are sent to wandbox.org.
     ///
     /// # Arguments
-    /// * `lang` - The language identifier to return the compilers for
-    pub fn get_compilers(&self, lang : &str) -> Option<Vec<Compiler>> {
-        let lock = self.cache.read().unwrap();
-        let language_option = lock.get(lang);
-        let lang = match language_option {
-            Some(l) => l,
-            None => return None
-        };
+    /// * `json` - The compiler list, as served at /api/list.json
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub fn from_list_json(json : &str, comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> WandboxResult<Wandbox> {
+        let filter = Filter::blocking(comps.unwrap_or_default(), langs.unwrap_or_default());
 
-        Some(lang.compilers.clone())
+        let (cache, warnings) = cache::parse(json, false)?;
+
+        let wbox = Wandbox::assemble(cache, WANDBOX_URL.to_string(), default_transport(), SystemTime::now(), filter);
+        *write_lock(&wbox.load_warnings) = warnings;
+        Ok(wbox)
     }
 
-    /// Returns a list of every language
-    pub fn get_languages(&self) -> Vec<Language> {
-        let lock = self.cache.read().unwrap();
+    /// Initializes the cache from an existing compiler cache, i.e one built by hand for tests
+    ///
+    /// The compilers are filtered just like a fetched list.
+    /// Requests are sent to wandbox.org.
+    ///
+    /// # Arguments
+    /// * `cache` - The compilers to use, keyed by language
+    /// * `comps` - A vector of compiler identifiers that the library should ignore
+    /// * `langs` - A vector of language identifiers that the library should ignore
+    pub fn from_cache(cache : CompilerCache, comps : Option<HashSet<String>>, langs : Option<HashSet<String>>) -> Wandbox {
+        let filter = Filter::blocking(comps.unwrap_or_default(), langs.unwrap_or_default());
 
-        let mut vec : Vec<Language> = Vec::new();
-        for (_k, v) in lock.iter() {
-            vec.push(v.clone());
-        }
-        vec
+        Wandbox::assemble(cache, WANDBOX_URL.to_string(), default_transport(), SystemTime::now(), filter)
     }
 
-    /// Determines if the compiler string supplied is a valid compiler
+    /// Initializes the cache from a file previously written by `save_cache`, without contacting
+    /// Wandbox
+    ///
+    /// Requests are sent to wandbox.org. Use `fetched_at` to determine whether the loaded cache is
+    /// too old to be trusted.
     ///
     /// # Arguments
-    /// * `c` - compiler identifier to check for
-    //  n^2 :(
-    pub fn is_valid_compiler_str(&self, c : &str) -> bool {
-        // aquire our lock
-        let lock = self.cache.read().unwrap();
-        for (_l, k) in lock.iter() {
-            for v in k.compilers.iter() {
-                if v.name == c {
-                    return true;
-                }
-            }
-        }
+    /// * `path` - The file to read the cache from
+    pub fn from_cache_file<P : AsRef<Path>>(path : P) -> WandboxResult<Wandbox> {
+        let (cache, fetched_at) = cache::read(path.as_ref())?;
 
-        return false;
+        Ok(Wandbox::assemble(cache, WANDBOX_URL.to_string(), default_transport(), fetched_at, Filter::default()))
     }
 
-    pub fn get_compiler_language_str(&self, c : &str) -> Option<String> {
-        // aquire our lock
-        let lock = self.cache.read().unwrap();
+    /// Initializes the cache from JSON previously returned by `cache_json`, without contacting
+    /// Wandbox
+    ///
+    /// Requests are sent to wandbox.org. JSON written in a different format version is rejected
+    /// with `WandboxError::UnsupportedCacheVersion`.
+    ///
+    /// # Arguments
+    /// * `json` - The cache, as returned by `cache_json`
+    pub fn from_cache_json(json : &str) -> WandboxResult<Wandbox> {
+        let (cache, fetched_at) = cache::from_json(json.as_bytes())?;
 
-        for (_k, v) in lock.iter() {
-            for comp in &v.compilers {
-                if comp.name == c {
-                    return Some(v.name.clone());
-                }
-            }
-        }
+        Ok(Wandbox::assemble(cache, WANDBOX_URL.to_string(), default_transport(), fetched_at, Filter::default()))
+    }
 
-        return None;
+    /// Serializes the compiler cache along with its format version & fetch time, in the same
+    /// layout as `save_cache`
+    pub fn cache_json(&self) -> WandboxResult<String> {
+        let lock = read_lock(&self.cache);
+        cache::to_json(&lock, self.fetched_at())
     }
 
-    pub fn is_valid_language(&self, l : &str) -> bool {
-        let lock = self.cache.read().unwrap();
-        return lock.get(l).is_some();
+    /// Writes the compiler cache to a file so it can be loaded later with `from_cache_file`
+    ///
+    /// # Arguments
+    /// * `path` - The file to write the cache to
+    pub fn save_cache<P : AsRef<Path>>(&self, path : P) -> WandboxResult<()> {
+        let lock = read_lock(&self.cache);
+        cache::write(path.as_ref(), &lock, self.fetched_at())
     }
 
-    pub fn get_default_compiler(&self, l : &str) -> Option<String> {
-        let lock = self.cache.read().unwrap();
-        if let Some(lang) = lock.get(l) {
-            Some(lang.compilers.get(0).expect("awd").name.clone())
-        }
-        else {
-            None
-        }
+    /// Returns when the compiler list was fetched from Wandbox
+    pub fn fetched_at(&self) -> SystemTime {
+        *read_lock(&self.fetched_at)
     }
-}
 
-/// Representation of a compiler
-#[derive(Hash, Eq, PartialEq, Serialize, Deserialize)]
-pub struct Compiler {
-    #[serde(rename = "compiler-option-raw")]
-    pub compiler_option_raw : bool,
-    #[serde(rename = "display-compile-command")]
-    pub display_compile_command : String,
-    #[serde(rename = "runtime-option-raw")]
-    pub runtime_option_raw : bool,
+    /// Counts the languages & compilers currently available, after block & allow lists apply
+    pub fn stats(&self) -> CacheStats {
+        let per_language : BTreeMap<String, usize> = self.with_cache(|cache| {
+            cache.iter().map(|(k, v)| (k.clone(), v.compilers.len())).collect()
+        });
 
-    pub version : String,
-    pub language : String,
-    pub name : String,
-}
-impl Clone for Compiler {
-    fn clone(&self) -> Self {
-        Compiler {
-            compiler_option_raw : self.compiler_option_raw,
-            display_compile_command : self.display_compile_command.clone(),
-            runtime_option_raw : self.runtime_option_raw,
-            version : self.version.clone(),
-            language : self.language.clone(),
-            name : self.name.clone(),
+        CacheStats {
+            languages : per_language.len(),
+            compilers : per_language.values().sum(),
+            per_language,
+            fetched_at : self.fetched_at()
         }
     }
-}
-impl fmt::Debug for Compiler {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{} {}] : {}", self.name, self.version, self.language)
+
+    /// Returns why compilers were left out of the last compiler list, one warning per compiler
+    ///
+    /// Compilers Wandbox lists in a shape this crate doesn't understand are skipped instead of
+    /// failing the whole list, unless `WandboxBuilder::strict` is set.
+    pub fn load_warnings(&self) -> Vec<String> {
+        read_lock(&self.load_warnings).clone()
     }
-}
 
-/// A builder to allow you to easily build requests
-///
-/// ```edition2018
-///use std::collections::HashSet;
-///use tokio::macros::*;
-///use std::error::Error;
-///use wandbox::{Wandbox, CompilationBuilder};
-///#[tokio::main]
-///async fn main() {
-///    let wbox : Wandbox = match Wandbox::new(None, None).await {
-///        Ok(wbox) => wbox,
-///        Err(e) => return println!("{}", e)
-///    };
-///    let mut builder = CompilationBuilder::new();
-///    builder.target("gcc-6.3.0");
-///    builder.options_str(vec!["-Wall", "-Werror"]);
-///    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
-///    let result = match builder.build(&wbox) {
-///        Ok(res) => res,
-///        Err(e) => return println!("{}", e)
-///    };
-///}
-/// ```
-#[derive(Default, Serialize)]
-pub struct CompilationBuilder {
-    #[serde(skip)]
-    target : String,
-    pub lang : String,
-    compiler : String,
-    code : String,
-    stdin : String,
-    #[serde(skip)]
-    options : Vec<String>,
-    #[serde(rename = "compiler-option-raw")]
-    compiler_options_raw : String,
-    save : bool
-}
-impl CompilationBuilder {
-    /// Creates a new CompilationBuilder with default values to be filled in later
-    pub fn new() -> CompilationBuilder {
-        return CompilationBuilder { ..Default::default()}
+    /// Returns a receiver for the outcome of background refreshes, if `auto_refresh` was enabled
+    pub fn refresh_status(&self) -> Option<watch::Receiver<RefreshStatus>> {
+        self.refresher.as_ref().map(|r| r.status.clone())
     }
 
-    /// Sets the target of the compilation
-    ///
-    /// # Arguments
-    /// * `target` - The target of a compilation, this can be a language ('c++'), or a compiler ('gcc-head')
-    pub fn target(&mut self, target : &str) -> () {
-        self.target = target.trim().to_string();
+    fn assemble(source : CompilerCache, endpoint : String, transport : Arc<dyn WandboxTransport>, fetched_at : SystemTime,
+                filter : Filter) -> Wandbox {
+        let mut cache = source.clone();
+        filter.apply(&mut cache);
+
+        Wandbox {
+            source : Arc::new(RwLock::new(source)),
+            index : Arc::new(RwLock::new(cache::index(&cache))),
+            cache : Arc::new(RwLock::new(cache)),
+            aliases : Arc::new(RwLock::new(alias::defaults())),
+            strategy : Arc::new(RwLock::new(DefaultStrategy::First)),
+            endpoint,
+            transport,
+            rate_limit_retry : None,
+            throttle : Arc::new(Throttle::default()),
+            breaker : Arc::new(Breaker::default()),
+            coalescer : Arc::new(Coalescer::default()),
+            results : Arc::new(ResultCache::default()),
+            limits : Arc::new(RwLock::new(RequestLimits::default())),
+            hooks : Arc::new(RwLock::new(Hooks::default())),
+            fetched_at : Arc::new(RwLock::new(fetched_at)),
+            filter : Arc::new(RwLock::new(filter)),
+            load_warnings : Arc::new(RwLock::new(Vec::new())),
+            strict : false,
+            validators : Arc::new(RwLock::new(Validators::default())),
+            refresher : None
+        }
     }
 
-    /// Sets the code to be compiled
-    ///
-    /// # Arguments
-    /// * `code` - String of code to be compiled
-    pub fn code(&mut self, code : &str)  -> () {
-        self.code = code.trim().to_string();
+    /// A handle sharing this instance's cache, without ownership of its background refresh
+    fn detached(&self) -> Wandbox {
+        Wandbox {
+            refresher : None,
+            ..self.clone()
+        }
     }
 
-    /// Sets the stdin to directed towards the application
+    /// Re-downloads the compiler list from Wandbox & replaces the cache
     ///
-    /// # Arguments
-    /// * `stdin` - program input
-    pub fn stdin(&mut self, stdin : &str) -> () {
-        self.stdin = stdin.trim().to_string();
+    /// The current block & allow lists are applied to the new list, including compilers blocked
+    /// with `block_compiler`. Readers holding on to results from the previous cache are unaffected.
+    /// The returned diff lists the compilers that were added, removed or changed, i.e to log them.
+    ///
+    /// If Wandbox sent an ETag or Last-Modified header with the list, it is sent back so an unchanged
+    /// list isn't downloaded again. The cache is then kept as is & the diff reports `not_modified`.
+    pub async fn refresh(&self) -> WandboxResult<CacheDiff> {
+        let validators = read_lock(&self.validators).clone();
+        let (source, warnings, validators) = match cache::fetch(self.transport.as_ref(), &self.endpoint, self.strict, &validators, &trace::Span::none()).await? {
+            Fetched::Modified { compilers, warnings, validators } => (build_cache(compilers), warnings, validators),
+            Fetched::NotModified => {
+                *write_lock(&self.fetched_at) = SystemTime::now();
+                return Ok(CacheDiff { not_modified : true, ..Default::default() });
+            }
+        };
+
+        let filter = read_lock(&self.filter);
+        let mut cache = source.clone();
+        filter.apply(&mut cache);
+
+        let mut lock = write_lock(&self.cache);
+        let diff = CacheDiff::between(&lock, &cache);
+
+        *write_lock(&self.source) = source;
+        *write_lock(&self.index) = cache::index(&cache);
+        *lock = cache;
+        *write_lock(&self.fetched_at) = SystemTime::now();
+        *write_lock(&self.load_warnings) = warnings;
+        *write_lock(&self.validators) = validators;
+        Ok(diff)
     }
 
-    /// Determines whether or not Wandbox saves the compilation & replies with a link for you
+    /// Stops exposing a compiler, i.e because it is broken on Wandbox right now
+    ///
+    /// The compiler stays blocked across refreshes until `unblock_compiler` is called.
     ///
     /// # Arguments
-    /// * `save` - true if Wandbox should save this compilation
-    pub fn save(&mut self, save : bool) -> () {
-        self.save = save;
+    /// * `id` - The compiler identifier to block
+    pub fn block_compiler(&self, id : &str) {
+        let mut filter = write_lock(&self.filter);
+        filter.blocked_compilers.insert(id.to_string());
+        self.rebuild(&filter);
     }
 
-    /// Sets the list of compilation options. Useful for languages like c++ to pass linker/optimization
-    /// flags.
+    /// Exposes a compiler again after it was blocked, if Wandbox still offers it
     ///
     /// # Arguments
-    /// * `options` - A list of compiler options i.e ["-Wall", "-Werror"]
-    pub fn options(&mut self, options : Vec<String>) -> () {
-        self.options = options;
+    /// * `id` - The compiler identifier to unblock
+    pub fn unblock_compiler(&self, id : &str) {
+        let mut filter = write_lock(&self.filter);
+        filter.blocked_compilers.remove(id);
+        self.rebuild(&filter);
     }
 
-    /// Sets the list of compilation options. Useful for languages like c++ to pass linker/optimization
-    /// flags.
+    /// Stops exposing every compiler of a language
     ///
-    /// This version allows you to pass a `Vec<&str>`
+    /// The language stays blocked across refreshes until `unblock_language` is called.
     ///
     /// # Arguments
-    /// * `options` - A list of compiler options i.e ["-Wall", "-Werror"]
-    pub fn options_str(&mut self, options : Vec<&str>) -> () {
-        self.options = options.into_iter().map(|f| f.to_owned()).collect();
+    /// * `lang` - The language identifier to block
+    pub fn block_language(&self, lang : &str) {
+        let mut filter = write_lock(&self.filter);
+        filter.blocked_languages.insert(lang.to_string());
+        self.rebuild(&filter);
     }
 
-    /// Finalizes the builder & prepares itself for compilation dispatch.
+    /// Exposes a language again after it was blocked, if Wandbox still offers it
     ///
     /// # Arguments
-    /// * `wb` - An instance of the Wandbox cache to resolve the compilation target
-    pub fn build(&mut self, wb : &Wandbox) -> Result<(), WandboxError> {
-        self.compiler_options_raw = self.options.join("\n");
+    /// * `lang` - The language identifier to unblock
+    pub fn unblock_language(&self, lang : &str) {
+        let mut filter = write_lock(&self.filter);
+        filter.blocked_languages.remove(lang);
+        self.rebuild(&filter);
+    }
 
-        if wb.is_valid_language(&self.target) {
-            let comp = match wb.get_default_compiler(&self.target) {
-                Some(def) => def,
-                None => return Err(WandboxError::new("Unable to determine default compiler for input language"))
-            };
-            self.compiler = comp;
-            self.lang = self.target.clone();
-        }
-        else if wb.is_valid_compiler_str(&self.target) {
-            let lang = match wb.get_compiler_language_str(&self.target) {
-                Some(lang) => lang,
-                None => return Err(WandboxError::new("Unable to determine language for compiler}"))
-            };
+    /// Re-derives the cache & index from the last fetched compiler list
+    fn rebuild(&self, filter : &Filter) {
+        let mut cache = read_lock(&self.source).clone();
+        filter.apply(&mut cache);
 
-            self.lang = lang;
-            self.compiler = self.target.clone();
-        }
-        else {
-            return Err(WandboxError::new("Unable to find compiler or language for target"));
-        }
-        Ok(())
+        let mut lock = write_lock(&self.cache);
+        *write_lock(&self.index) = cache::index(&cache);
+        *lock = cache;
     }
 
-    /// Dispatches the built request to Wandbox
-    pub async fn dispatch(&self) -> Result<CompilationResult, WandboxError> {
-        let client = reqwest::Client::new();
-
-        let result = client.post("https://wandbox.org/api/compile.json")
-            .json(&self)
-            .header("Content-Type", "application/json; charset=utf-8")
-            .send().await;
+    /// Returns the base url of the Wandbox instance requests are sent to
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
 
-        let response = match result {
-            Ok(r) => r,
-            Err(e) => return Err(WandboxError::new(&format!("{}", e)))
-        };
+    /// Gets a list of compilers given a certain language
+    ///
+    /// The list is shared with the cache rather than copied, and stays as it was if the cache is
+    /// refreshed afterwards.
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier to return the compilers for
+    pub fn get_compilers(&self, lang : &str) -> Option<Arc<[Compiler]>> {
+        let lock = read_lock(&self.cache);
+        let lang = lock.get(lang)?;
 
-        let status_code = response.status().clone();
-        let res : CompilationResult = match response.json().await {
-            Ok(res) => res,
-            Err(_e) => return Err(WandboxError::new(&format!("Wandbox replied with: {}\n\
-            This could mean WandBox is experiencing an outage, or a network connection error has occured", status_code)))
-        };
-        return Ok(res);
+        Some(lang.compilers.clone())
     }
-}
 
-/// Information regarding the result of a compilation request.
-#[derive(Default, Deserialize)]
-pub struct CompilationResult {
-    #[serde(default)]
-    pub status : String,
-    #[serde(default)]
-    pub signal : String,
-    #[serde(rename = "compiler_output", default)]
-    pub compiler_stdout : String,
-    #[serde(rename = "compiler_error", default)]
-    pub compiler_stderr : String,
-    #[serde(rename = "compiler_message", default)]
-    pub compiler_all : String,
-    #[serde(rename = "program_output", default)]
-    pub program_stdout : String,
-    #[serde(rename = "program_error", default)]
-    pub program_stderr : String,
-    #[serde(rename = "program_message", default)]
-    pub program_all : String,
-    #[serde(default)]
-    pub permlink : String,
-    #[serde(default)]
-    pub url : String,
-}
+    /// Returns a list of every language
+    pub fn get_languages(&self) -> Vec<Language> {
+        let lock = read_lock(&self.cache);
 
-impl fmt::Debug for CompilationResult {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{} {}] {}: {}", self.status, self.signal, self.compiler_all, self.program_all)
+        let mut vec : Vec<Language> = Vec::new();
+        for (_k, v) in lock.iter() {
+            vec.push(v.clone());
+        }
+        vec.sort_by(|a, b| a.name.cmp(&b.name));
+        vec
     }
-}
 
+    /// Gets the compilers of a language or family whose version lies between two bounds, newest
+    /// first
+    ///
+    /// Bounds are inclusive and only as precise as they're written, so a max of "10" includes
+    /// gcc 10.2.0 while "10.1" stops at 10.1.x. Compilers without a version number only match when
+    /// no bound is given, bounds without a version number are ignored.
+    ///
+    /// # Arguments
+    /// * `lang_or_family` - A language identifier or alias, or a compiler prefix such as "gcc"
+    /// * `min` - The oldest version to include, i.e "7"
+    /// * `max` - The newest version to include, i.e "10"
+    /// * `include_head` - Whether to include `-head` builds, which usually fall outside any range
+    pub fn get_compilers_in_range(&self, lang_or_family : &str, min : Option<&str>, max : Option<&str>, include_head : bool) -> Vec<Compiler> {
+        let lang = self.resolve_language(lang_or_family);
+        let family = format!("{}-", lang_or_family.trim().to_lowercase());
 
-/// A representation of a language with a list of it's compilers
-#[derive(Hash, Eq, PartialEq, Debug, Clone)]
-pub struct Language {
-    pub name : String,
-    pub compilers : Vec<Compiler>
-}
+        let mut compilers : Vec<Compiler> = Vec::new();
+        self.for_each_compiler(|l, c| {
+            let matches = match &lang {
+                Some(lang) => l == lang,
+                None => c.name.starts_with(&family)
+            };
+            if matches && (include_head || !c.is_head()) {
+                compilers.push(c.clone());
+            }
+        });
 
-impl Language {
-    fn remove_compiler(&mut self, str : &str) {
-        let mut copy = self.compilers.clone();
-        copy = copy.into_iter().filter(|v| v.name != str).collect();
-        self.compilers = copy;
+        let in_range = |c : &Compiler| {
+            let version = c.parsed_version();
+            let check = |bound : Option<&str>, outside : Ordering| match (bound, &version) {
+                (None, _) => true,
+                (Some(bound), Some(v)) => version::compare_to_bound(v, bound) != Some(outside),
+                (Some(bound), None) => Version::parse(bound).is_none()
+            };
+            check(min, Ordering::Less) && check(max, Ordering::Greater)
+        };
+        compilers.retain(in_range);
+        compilers.sort_by(|a, b| b.cmp_version(a).then_with(|| a.name.cmp(&b.name)));
+        compilers
     }
-}
 
+    /// Groups a language's compilers by toolchain, i.e gcc, clang & zapcc for C++, each newest
+    /// first with `-head` builds leading
+    ///
+    /// The family is the part of the compiler id before its version, so "gcc-10.1.0" is a gcc.
+    /// Ids like "openjdk-jdk-15.0.2+7" join a shorter family of the same language they start with,
+    /// "openjdk". Families keep the order Wandbox first lists them in, an unknown language has
+    /// none.
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier or alias to group the compilers of
+    pub fn get_compiler_families(&self, lang : &str) -> Vec<CompilerFamily> {
+        let compilers = match self.resolve_language(lang).and_then(|l| self.get_compilers(&l)) {
+            Some(compilers) => compilers,
+            None => return Vec::new()
+        };
 
-#[derive(Debug)]
-pub struct WandboxError {
-    details: String
-}
+        let names : Vec<&str> = compilers.iter().map(|c| version::family(&c.name)).collect();
+        let mut families : Vec<CompilerFamily> = Vec::new();
+        for (c, name) in compilers.iter().zip(&names) {
+            // the shortest family this one extends, if any
+            let name = names.iter()
+                .filter(|other| name.len() > other.len() && name.starts_with(*other) && name[other.len()..].starts_with('-'))
+                .min_by_key(|other| other.len())
+                .unwrap_or(name);
 
-impl WandboxError {
-    fn new(msg: &str) -> WandboxError {
-        WandboxError{details: msg.to_string()}
-    }
-}
+            match families.iter_mut().find(|f| f.name == *name) {
+                Some(family) => family.compilers.push(c.clone()),
+                None => families.push(CompilerFamily { name : name.to_string(), compilers : vec![c.clone()] })
+            }
+        }
 
-impl fmt::Display for WandboxError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"{}",self.details)
+        for family in families.iter_mut() {
+            family.compilers.sort_by(|a, b| {
+                b.is_head().cmp(&a.is_head())
+                    .then_with(|| b.cmp_version(a))
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+        }
+        families
     }
-}
 
-impl std::error::Error for WandboxError {
-    fn description(&self) -> &str {
-        &self.details
-    }
-}
\ No newline at end of file
+    /// Formats a language's compilers for a chat message, in Wandbox's own order
+    ///
+    /// Compilers are listed by their display name, see `format_compilers_capped` to shorten long
+    /// lists.
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier or alias to list the compilers of
+    /// * `style` - How to lay out the list
+    /// # Example
+    /// ```edition2018
+    ///use wandbox::{ListStyle, Wandbox};
+    ///
+    ///let wbox = Wandbox::from_embedded_snapshot(None, None).unwrap();
+    ///println!("{}", wbox.format_compilers("rust", ListStyle::Plain).unwrap());
+    /// ```
+    pub fn format_compilers(&self, lang : &str, style : ListStyle) -> Option<String> {
+        self.format_compilers_capped(lang, style, usize::MAX)
+    }
+
+    /// Formats a language's compilers like `format_compilers`, listing at most `max_len` of them
+    ///
+    /// Compilers from both ends are kept, so the newest & head builds stay visible, and the middle
+    /// of the list is replaced by a "… and N more" line. The last compiler needs a `max_len` of at
+    /// least 2, with 1 only the first is listed & with 0 only the "… and N more" line is left.
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier or alias to list the compilers of
+    /// * `style` - How to lay out the list
+    /// * `max_len` - The most compilers to list
+    pub fn format_compilers_capped(&self, lang : &str, style : ListStyle, max_len : usize) -> Option<String> {
+        let compilers = self.get_compilers(&self.resolve_language(lang)?)?;
+        Some(listing::format(&compilers, style, max_len))
+    }
+
+    /// Gets a sorted copy of a language's compilers, `get_compilers` keeps Wandbox's own order
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier to return the compilers for
+    /// * `sort` - How to order the compilers
+    pub fn get_compilers_sorted(&self, lang : &str, sort : SortBy) -> Option<Vec<Compiler>> {
+        let mut compilers = self.get_compilers(lang)?.to_vec();
+        match sort {
+            SortBy::Name => compilers.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortBy::Version { head_first } => compilers.sort_by(|a, b| {
+                let heads = if head_first {
+                    b.is_head().cmp(&a.is_head())
+                } else {
+                    a.is_head().cmp(&b.is_head())
+                };
+                heads.then_with(|| b.cmp_version(a))
+                    .then_with(|| a.name.cmp(&b.name))
+            })
+        }
+        Some(compilers)
+    }
+
+    /// Runs `f` over the cache without copying it, i.e to build an autocomplete index
+    ///
+    /// The cache's read lock is held while `f` runs, so `f` should be quick and must not call back
+    /// into this `Wandbox`: refreshes & blocklist changes wait for it, and calls made from within
+    /// `f` may deadlock.
+    ///
+    /// # Arguments
+    /// * `f` - Receives the cache, languages keyed by their lowercase identifier
+    pub fn with_cache<R, F : FnOnce(&CompilerCache) -> R>(&self, f : F) -> R {
+        let lock = read_lock(&self.cache);
+        f(&lock)
+    }
+
+    /// Runs `f` over every compiler along with its language, without copying them
+    ///
+    /// The same constraints as `with_cache` apply, the read lock is held throughout.
+    ///
+    /// # Arguments
+    /// * `f` - Receives the language identifier & the compiler
+    pub fn for_each_compiler<F : FnMut(&str, &Compiler)>(&self, mut f : F) {
+        self.with_cache(|cache| {
+            for (name, lang) in cache.iter() {
+                lang.compilers.iter().for_each(|c| f(name, c));
+            }
+        })
+    }
+
+    /// Finds compilers whose identifier, display name or language contain every word of the query,
+    /// ignoring case
+    ///
+    /// Compilers whose identifier or display name starts with the query come first, the rest
+    /// follow by language & in Wandbox's order. An empty query matches nothing.
+    ///
+    /// # Arguments
+    /// * `query` - The words to look for, i.e "clang" or "python 3"
+    pub fn search_compilers(&self, query : &str) -> Vec<Compiler> {
+        let query = query.trim().to_lowercase();
+        let terms : Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        self.with_cache(|cache| {
+            let mut found : Vec<(bool, &str, Compiler)> = Vec::new();
+            for (lang, l) in cache.iter() {
+                for c in l.compilers.iter() {
+                    let name = c.name.to_lowercase();
+                    let display_name = c.display_name.to_lowercase();
+                    let text = format!("{} {} {}", name, display_name, lang);
+                    if terms.iter().all(|t| text.contains(t)) {
+                        let prefix = name.starts_with(&query) || display_name.starts_with(&query);
+                        found.push((!prefix, lang.as_str(), c.clone()));
+                    }
+                }
+            }
+            // stable, so each language keeps Wandbox's order
+            found.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+            found.into_iter().map(|(_prefix, _lang, c)| c).collect()
+        })
+    }
+
+    /// Determines if the compiler string supplied is a valid compiler
+    ///
+    /// # Arguments
+    /// * `c` - compiler identifier to check for
+    pub fn is_valid_compiler_str(&self, c : &str) -> bool {
+        read_lock(&self.index).contains_key(c)
+    }
+
+    /// Gets the switches a compiler supports
+    ///
+    /// # Arguments
+    /// * `c` - compiler identifier to get the switches for
+    pub fn get_switches(&self, c : &str) -> Option<Vec<Switch>> {
+        self.with_compiler(c, |_lang, comp| comp.switches.clone())
+    }
+
+    /// Gets the human friendly name of a compiler, i.e "gcc HEAD" for "gcc-head"
+    ///
+    /// # Arguments
+    /// * `c` - compiler identifier to get the display name for
+    pub fn get_display_name(&self, c : &str) -> Option<String> {
+        self.with_compiler(c, |_lang, comp| comp.display_name.clone())
+    }
+
+    /// Gets a compiler by its identifier
+    ///
+    /// # Arguments
+    /// * `c` - compiler identifier to look up, this is case sensitive
+    pub fn get_compiler(&self, c : &str) -> Option<Compiler> {
+        self.with_compiler(c, |_lang, comp| comp.clone())
+    }
+
+    /// Gets the language a compiler belongs to, along with all of its compilers
+    ///
+    /// # Arguments
+    /// * `c` - compiler identifier to look up, this is case sensitive
+    pub fn get_language_of(&self, c : &str) -> Option<Language> {
+        self.with_compiler(c, |lang, _comp| lang.clone())
+    }
+
+    /// Looks up a compiler & the language it belongs to, handing both to `f` under the read lock
+    fn with_compiler<R, F : FnOnce(&Language, &Compiler) -> R>(&self, c : &str, f : F) -> Option<R> {
+        let lock = read_lock(&self.cache);
+        let index = read_lock(&self.index);
+
+        let (lang, i) = index.get(c)?;
+        let lang = lock.get(lang)?;
+        lang.compilers.get(*i).map(|comp| f(lang, comp))
+    }
+
+    pub fn get_compiler_language_str(&self, c : &str) -> Option<String> {
+        read_lock(&self.index).get(c).map(|(lang, _i)| lang.clone())
+    }
+
+    /// Resolves a compiler id, accepting a version prefix such as `gcc-10` or `clang-11`
+    ///
+    /// An exact id always wins. Otherwise every id continuing the prefix with a `.` or `-` is
+    /// considered, `-head` builds are skipped unless asked for, and the newest version is picked.
+    /// If several compilers share the newest version, i.e the C & C++ builds of the same gcc,
+    /// `BuildError::AmbiguousTarget` lists them instead.
+    ///
+    /// # Arguments
+    /// * `c` - The compiler id or id prefix to resolve
+    pub fn resolve_compiler(&self, c : &str) -> WandboxResult<String> {
+        self.resolve_prefix(c).map_err(WandboxError::from)
+    }
+
+    fn resolve_prefix(&self, c : &str) -> Result<String, BuildError> {
+        let prefix = c.to_lowercase();
+        if self.is_valid_compiler_str(&prefix) {
+            return Ok(prefix);
+        }
+
+        let lock = read_lock(&self.cache);
+        let mut candidates : Vec<&Compiler> = lock.values()
+            .flat_map(|lang| lang.compilers.iter())
+            .filter(|comp| match comp.name.strip_prefix(&prefix) {
+                Some(rest) => rest.starts_with('.') || rest.starts_with('-'),
+                None => false
+            })
+            .filter(|comp| prefix.contains("head") || !comp.is_head())
+            .collect();
+        candidates.sort_by(|a, b| b.cmp_version(a).then_with(|| a.name.cmp(&b.name)));
+
+        let newest = match candidates.first() {
+            Some(newest) => newest,
+            None => return Err(BuildError::UnknownTarget { input : c.to_string(), suggestions : self.suggest_targets(c) })
+        };
+        let tied : Vec<String> = candidates.iter()
+            .take_while(|comp| comp.cmp_version(newest).is_eq())
+            .map(|comp| comp.name.clone())
+            .collect();
+
+        if tied.len() > 1 {
+            return Err(BuildError::AmbiguousTarget { target : c.to_string(), candidates : tied });
+        }
+        Ok(newest.name.clone())
+    }
+
+    /// The languages, compilers & aliases closest to a target that couldn't be resolved
+    fn suggest_targets(&self, target : &str) -> Vec<String> {
+        let cache = read_lock(&self.cache);
+        let aliases = read_lock(&self.aliases);
+        let candidates = cache.values()
+            .flat_map(|l| std::iter::once(&*l.name).chain(l.compilers.iter().map(|c| c.name.as_str())))
+            .chain(aliases.iter().filter(|(_a, lang)| cache.contains_key(*lang)).map(|(a, _lang)| a.as_str()));
+        suggest::closest(target, candidates)
+    }
+
+    /// Builds & dispatches several compilations concurrently, within the concurrency limit
+    ///
+    /// Each request succeeds or fails on its own, the results are in the same order as the
+    /// requests.
+    ///
+    /// # Arguments
+    /// * `requests` - The compilations to run
+    pub async fn dispatch_all(&self, requests : Vec<CompilationBuilder>) -> Vec<WandboxResult<CompilationResult>> {
+        let dispatches = requests.into_iter().map(|builder| async move {
+            let compilation = builder.build(self)?;
+            compilation.dispatch_with(self).await
+        });
+        join_all(dispatches).await
+    }
+
+    /// Compiles the same code with every compiler of a language, i.e to find the first compiler
+    /// version that accepts it
+    ///
+    /// At most `MATRIX_CONCURRENCY` compilations run at once, fewer if a lower concurrency limit is
+    /// set. When options are given, compilers that don't accept raw compiler options are skipped.
+    /// Each result is paired with the compiler it came from, in the order Wandbox lists them.
+    ///
+    /// # Arguments
+    /// * `lang` - The language identifier or alias to compile with
+    /// * `code` - The code to compile
+    /// * `options` - Raw compiler options passed to every compiler, may be empty
+    pub async fn run_matrix(&self, lang : &str, code : &str, options : Vec<String>) -> Vec<(Compiler, WandboxResult<CompilationResult>)> {
+        let compilers = match self.resolve_language(lang).and_then(|l| self.get_compilers(&l)) {
+            Some(compilers) => compilers,
+            None => return Vec::new()
+        };
+
+        let dispatches = compilers.iter()
+            .filter(|c| options.is_empty() || c.compiler_option_raw)
+            .cloned()
+            .map(|c| {
+                let mut builder = CompilationBuilder::new();
+                builder.target(&c.name);
+                builder.code(code);
+                builder.options(options.clone());
+                async move {
+                    let result = match builder.build(self) {
+                        Ok(compilation) => compilation.dispatch_with(self).await,
+                        Err(e) => Err(e)
+                    };
+                    (c, result)
+                }
+            });
+        stream_util::iter(dispatches).buffered(MATRIX_CONCURRENCY).collect().await
+    }
+
+    /// Compiles & runs a snippet in one call, without going through `CompilationBuilder`
+    ///
+    /// The request is sent like any other, respecting the configured timeouts, rate limit retries
+    /// & concurrency limit.
+    ///
+    /// # Arguments
+    /// * `target` - A language ('c++'), or a compiler ('gcc-head')
+    /// * `code` - The code to compile
+    /// # Example
+    /// ```edition2018
+    ///use wandbox::{Wandbox, WandboxResult};
+    ///
+    ///#[tokio::main]
+    ///async fn main() -> WandboxResult<()> {
+    ///    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    ///    match wbox.run("python", "print('hello')").await {
+    ///        Ok(result) => println!("{}", result.program_all),
+    ///        Err(e) => println!("{}", e)
+    ///    }
+    ///    Ok(())
+    ///}
+    /// ```
+    pub async fn run(&self, target : &str, code : &str) -> WandboxResult<CompilationResult> {
+        self.run_with(target, code, RunOptions::default()).await
+    }
+
+    /// Compiles & runs a snippet in one call like `run`, with stdin, options or switches
+    ///
+    /// # Arguments
+    /// * `target` - A language ('c++'), or a compiler ('gcc-head')
+    /// * `code` - The code to compile
+    /// * `options` - The rest of the request, unset fields are left at their defaults
+    /// # Example
+    /// ```edition2018
+    ///use wandbox::{RunOptions, Wandbox};
+    ///
+    ///#[tokio::main]
+    ///async fn main() {
+    ///    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None).unwrap();
+    ///    let options = RunOptions { stdin : String::from("world"), ..Default::default() };
+    ///    match wbox.run_with("python", "print('hello', input())", options).await {
+    ///        Ok(result) => println!("{}", result.program_all),
+    ///        Err(e) => println!("{}", e)
+    ///    }
+    ///}
+    /// ```
+    pub async fn run_with(&self, target : &str, code : &str, options : RunOptions) -> WandboxResult<CompilationResult> {
+        let mut builder = CompilationBuilder::new();
+        builder.target(target)
+            .code(code)
+            .stdin(options.stdin)
+            .options(options.options)
+            .runtime_options(options.runtime_options)
+            .switches(options.switches)
+            .save(options.save);
+
+        builder.build(self)?.dispatch_with(self).await
+    }
+
+    /// Compiles, runs & saves a snippet, returning the permlink it can be shared with
+    ///
+    /// When Wandbox only replies with one of the permlink id & url, the other is derived from it.
+    /// If it replies with neither, `WandboxError::MissingPermlink` is returned.
+    ///
+    /// # Arguments
+    /// * `target` - A language ('c++'), or a compiler ('gcc-head')
+    /// * `code` - The code to compile
+    /// * `options` - The rest of the request, `save` is always set
+    pub async fn share(&self, target : &str, code : &str, mut options : RunOptions) -> WandboxResult<Permlink> {
+        options.save = true;
+        let result = self.run_with(target, code, options).await?;
+
+        match Permlink::from_result(&result, &self.endpoint) {
+            Some(permlink) => Ok(permlink),
+            None => Err(WandboxError::MissingPermlink)
+        }
+    }
+
+    /// Fetches a previously saved compilation from Wandbox
+    ///
+    /// Ids with characters other than letters, digits, `_` & `-` are rejected with
+    /// `WandboxError::InvalidPermlink` rather than sent, so they can't reach another api path.
+    ///
+    /// # Arguments
+    /// * `id` - The permlink identifier Wandbox replied with when the compilation was saved
+    pub async fn get_permlink(&self, id : &str) -> WandboxResult<PermlinkResult> {
+        let id = id.trim();
+        if !is_path_segment(id) {
+            return Err(WandboxError::InvalidPermlink(id.to_string()));
+        }
+        let response = self.transport.get_json(&format!("{}/api/permlink/{}", self.endpoint, id)).await?;
+
+        read_json(response)
+    }
+
+    /// Fetches one of Wandbox's code templates, a default snippet for a compiler
+    ///
+    /// The template names available for a compiler are listed in `Compiler::templates`. Names with
+    /// characters other than letters, digits, `_` & `-` are rejected with
+    /// `WandboxError::InvalidTemplate` rather than sent.
+    ///
+    /// # Arguments
+    /// * `name` - The template name i.e "gcc"
+    pub async fn get_template(&self, name : &str) -> WandboxResult<Template> {
+        let name = name.trim();
+        if !is_path_segment(name) {
+            return Err(WandboxError::InvalidTemplate(name.to_string()));
+        }
+        let response = self.transport.get_json(&format!("{}/api/template/{}", self.endpoint, name)).await?;
+
+        let code : TemplateResponse = read_json(response)?;
+
+        Ok(Template {
+            name : name.to_string(),
+            code : code.code
+        })
+    }
+
+    /// Guesses the language of a source file from its name, i.e "main.rs" is "rust", as long as
+    /// that language is available
+    ///
+    /// # Arguments
+    /// * `name` - The file name or path
+    pub fn target_for_filename(&self, name : &str) -> Option<String> {
+        let ext = Path::new(name.trim()).extension()?.to_str()?;
+        let lang = language_for_extension(ext)?;
+        if self.is_valid_language(lang) {
+            Some(lang.to_string())
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn is_valid_language(&self, l : &str) -> bool {
+        let lock = read_lock(&self.cache);
+        lock.get(l).is_some()
+    }
+
+    /// Resolves a language name or shorthand (i.e `cpp`, `rs`) to the language key Wandbox uses
+    ///
+    /// Real language names always take precedence over aliases, and aliases pointing at a
+    /// language that is unavailable or blocked resolve to nothing.
+    ///
+    /// # Arguments
+    /// * `l` - The language name or alias to resolve
+    pub fn resolve_language(&self, l : &str) -> Option<String> {
+        let key = l.to_lowercase();
+        if self.is_valid_language(&key) {
+            return Some(key);
+        }
+
+        let lang = read_lock(&self.aliases).get(&key)?.clone();
+        if self.is_valid_language(&lang) {
+            Some(lang)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Registers a custom shorthand for a language
+    ///
+    /// # Arguments
+    /// * `alias` - The shorthand to register, i.e "pasta"
+    /// * `language` - The language it should resolve to, i.e "c++"
+    pub fn add_alias(&self, alias : &str, language : &str) -> WandboxResult<()> {
+        let alias = alias.to_lowercase();
+        if self.is_valid_language(&alias) || self.is_valid_compiler_str(&alias) {
+            return Err(WandboxError::AliasConflict(alias));
+        }
+
+        let lang = match self.resolve_language(language) {
+            Some(lang) => lang,
+            None => return Err(BuildError::UnknownTarget { input : language.to_string(), suggestions : Vec::new() }.into())
+        };
+        write_lock(&self.aliases).insert(alias, lang);
+        Ok(())
+    }
+
+    /// Returns the compiler used when a language is given as the compilation target, as chosen
+    /// by the configured `DefaultStrategy`
+    ///
+    /// # Arguments
+    /// * `l` - The language key i.e "c++"
+    pub fn get_default_compiler(&self, l : &str) -> Option<String> {
+        match &*read_lock(&self.strategy) {
+            DefaultStrategy::First => {},
+            DefaultStrategy::LatestStable => {
+                if let Some(latest) = self.get_latest_compiler(l) {
+                    return Some(latest);
+                }
+            },
+            DefaultStrategy::Named(pins) => {
+                // a pinned compiler may have been blocked or dropped by Wandbox since
+                if let Some(pin) = pins.get(l).filter(|pin| self.is_valid_compiler_str(pin)) {
+                    return Some(pin.clone());
+                }
+            }
+        }
+
+        let lock = read_lock(&self.cache);
+        lock.get(l)?.compilers.first().map(|c| c.name.clone())
+    }
+
+    /// Limits how many compilations are sent to Wandbox at once, further dispatches wait for
+    /// one to finish. This applies to every `Compilation` built from this instance, and takes
+    /// effect for dispatches started after the call.
+    ///
+    /// # Arguments
+    /// * `max` - The most compilations in flight at once, 0 lifts the limit
+    pub fn set_max_concurrency(&self, max : usize) {
+        self.throttle.set_max(max);
+    }
+
+    /// Returns how many compilations are currently in flight, not counting those waiting on the
+    /// concurrency limit
+    pub fn in_flight(&self) -> usize {
+        self.throttle.in_flight()
+    }
+
+    /// Changes the circuit breaker, see `WandboxBuilder::circuit_breaker`, `None` turns it off.
+    /// Either way the breaker starts out closed.
+    ///
+    /// # Arguments
+    /// * `breaker` - The thresholds to use from now on
+    pub fn set_circuit_breaker(&self, breaker : Option<CircuitBreaker>) {
+        self.breaker.configure(breaker);
+    }
+
+    /// Returns whether dispatches currently go through the circuit breaker, `None` when there is
+    /// no breaker
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.breaker.state()
+    }
+
+    /// Turns sharing the reply between identical compilations in flight on or off, see
+    /// `WandboxBuilder::coalesce_requests`
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether identical compilations should be coalesced
+    pub fn set_coalesce_requests(&self, enabled : bool) {
+        self.coalescer.set_enabled(enabled);
+    }
+
+    /// Answers compilations identical to a recent one with its result instead of sending them,
+    /// i.e for a snippet pasted again a few minutes later
+    ///
+    /// Up to `capacity` successful results are kept for `ttl` each, and once full the result used
+    /// longest ago makes room. Compilations are identical when every field of their request is,
+    /// stdin included, and saved compilations & errors are never cached. `DispatchInfo::cache_hit`
+    /// tells cached results apart. Calling this again drops what was cached.
+    ///
+    /// # Arguments
+    /// * `capacity` - The most results to keep, 0 keeps none
+    /// * `ttl` - How long a result may be reused for
+    pub fn enable_result_cache(&self, capacity : usize, ttl : Duration) {
+        self.results.enable(capacity, ttl);
+    }
+
+    /// Drops every result cached by `enable_result_cache`, which stays enabled
+    pub fn clear_result_cache(&self) {
+        self.results.clear();
+    }
+
+    /// Returns the size limits requests built from this instance are checked against
+    pub fn limits(&self) -> RequestLimits {
+        *read_lock(&self.limits)
+    }
+
+    /// Changes the size limits requests are checked against, i.e to accept less than Wandbox
+    /// does. This is shared by every clone & applies to requests built after the call.
+    ///
+    /// # Arguments
+    /// * `limits` - The limits to use from now on
+    pub fn set_limits(&self, limits : RequestLimits) {
+        *write_lock(&self.limits) = limits;
+    }
+
+    /// Calls `hook` with the details of every reply to a compilation, including rate limited &
+    /// failed ones, i.e to log the rate limit headers Wandbox or a CDN in front of it sends
+    ///
+    /// This replaces any hook set before, is shared by every clone & also applies to
+    /// compilations already built from them. The hook runs on the task dispatching the
+    /// compilation, so it should return quickly.
+    ///
+    /// # Arguments
+    /// * `hook` - The callback to observe replies with
+    pub fn on_response<F>(&self, hook : F) where F : Fn(&DispatchInfo) + Send + Sync + 'static {
+        write_lock(&self.hooks).on_response = Some(Arc::new(hook));
+    }
+
+    /// Calls `logger` with the exact JSON every compilation sends & receives, to see why
+    /// Wandbox rejected a request without going through a proxy
+    ///
+    /// Logging is off until this is called. Bodies are cut short to `DEFAULT_WIRE_LOG_BYTES`
+    /// unless changed with `set_wire_log_limit`, and may hold the code being compiled. Like
+    /// `on_response` this replaces any logger set before & applies to every clone.
+    ///
+    /// # Arguments
+    /// * `logger` - The callback to log requests & replies with
+    pub fn set_wire_logger<F>(&self, logger : F) where F : Fn(WireEvent) + Send + Sync + 'static {
+        write_lock(&self.hooks).wire_logger = Some(Arc::new(logger));
+    }
+
+    /// Stops logging requests & replies set up with `set_wire_logger`
+    pub fn clear_wire_logger(&self) {
+        write_lock(&self.hooks).wire_logger = None;
+    }
+
+    /// Changes how much of each body `set_wire_logger` passes on
+    ///
+    /// # Arguments
+    /// * `max_bytes` - The most bytes of a body to log, longer ones end in `TRUNCATION_MARKER`
+    pub fn set_wire_log_limit(&self, max_bytes : usize) {
+        write_lock(&self.hooks).wire_log_limit = max_bytes;
+    }
+
+    /// Changes how the default compiler of a language is chosen
+    ///
+    /// Pins given with `DefaultStrategy::Named` are checked against the cache immediately, each
+    /// must name a known language (or alias) and one of that language's compilers.
+    ///
+    /// # Arguments
+    /// * `strategy` - The strategy to use from now on
+    pub fn set_default_strategy(&self, strategy : DefaultStrategy) -> WandboxResult<()> {
+        let strategy = match strategy {
+            DefaultStrategy::Named(pins) => {
+                let mut resolved = HashMap::new();
+                for (lang, comp) in pins {
+                    let key = match self.resolve_language(&lang) {
+                        Some(key) => key,
+                        None => return Err(WandboxError::InvalidDefault { language : lang, compiler : comp })
+                    };
+                    if self.get_compiler_language_str(&comp).as_ref() != Some(&key) {
+                        return Err(WandboxError::InvalidDefault { language : lang, compiler : comp });
+                    }
+                    resolved.insert(key, comp);
+                }
+                DefaultStrategy::Named(resolved)
+            },
+            other => other
+        };
+
+        *write_lock(&self.strategy) = strategy;
+        Ok(())
+    }
+
+    /// Returns the newest released compiler for a language, skipping `-head` builds
+    ///
+    /// Versions are compared numerically where possible, i.e "10.2.0" is newer than "9.3.0",
+    /// and lexicographically otherwise. Returns `None` if the language only has `-head` builds.
+    ///
+    /// # Arguments
+    /// * `lang` - The language key i.e "c++"
+    pub fn get_latest_compiler(&self, lang : &str) -> Option<String> {
+        let lock = read_lock(&self.cache);
+        let mut latest : Option<&Compiler> = None;
+        for comp in lock.get(lang)?.compilers.iter().filter(|c| !c.is_head()) {
+            latest = match latest {
+                Some(l) if comp.cmp_version(l).is_le() => Some(l),
+                _ => Some(comp)
+            };
+        }
+        latest.map(|c| c.name.clone())
+    }
+}
+
+/// A summary of the cache, as returned by `Wandbox::stats`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of usable languages
+    pub languages : usize,
+    /// The number of usable compilers, across every language
+    pub compilers : usize,
+    /// The number of usable compilers of each language
+    pub per_language : BTreeMap<String, usize>,
+    /// When the compiler list was fetched from Wandbox
+    pub fetched_at : SystemTime,
+}
+
+/// Where the compiler list of a `Wandbox` came from, as returned by `Wandbox::new_with_fallback`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheOrigin {
+    /// The list was freshly fetched from Wandbox
+    Network,
+    /// Wandbox couldn't be reached & the list was loaded from the fallback file, `age` holds how
+    /// long ago it was fetched
+    Disk { age : Duration },
+}
+
+/// A builder for configuring how a `Wandbox` instance talks to Wandbox
+///
+/// ```edition2018
+///use std::time::Duration;
+///use wandbox::{Wandbox, WandboxBuilder};
+///
+///#[tokio::main]
+///async fn main() {
+///    let wbox : Wandbox = match WandboxBuilder::new()
+///        .connect_timeout(Duration::from_secs(5))
+///        .request_timeout(Duration::from_secs(30))
+///        .build().await {
+///        Ok(wbox) => wbox,
+///        Err(e) => return println!("{}", e)
+///    };
+///}
+/// ```
+pub struct WandboxBuilder {
+    endpoint : String,
+    filter : Filter,
+    connect_timeout : Option<Duration>,
+    request_timeout : Option<Duration>,
+    auto_refresh : Option<Duration>,
+    transport : Option<Arc<dyn WandboxTransport>>,
+    proxy : Option<String>,
+    no_proxy : bool,
+    user_agent : Option<String>,
+    rate_limit_retry : Option<Duration>,
+    breaker : Option<CircuitBreaker>,
+    coalesce : bool,
+    strict : bool,
+    limits : RequestLimits,
+}
+impl WandboxBuilder {
+    /// Creates a new WandboxBuilder targeting wandbox.org with no timeouts
+    pub fn new() -> WandboxBuilder {
+        WandboxBuilder {
+            endpoint : WANDBOX_URL.to_string(),
+            filter : Filter::default(),
+            connect_timeout : None,
+            request_timeout : None,
+            auto_refresh : None,
+            transport : None,
+            proxy : None,
+            no_proxy : false,
+            user_agent : None,
+            rate_limit_retry : None,
+            breaker : None,
+            coalesce : false,
+            strict : false,
+            limits : RequestLimits::default()
+        }
+    }
+
+    /// Sets the base url of the Wandbox instance to use
+    ///
+    /// # Arguments
+    /// * `endpoint` - The base url of the Wandbox instance i.e "https://wandbox.org"
+    pub fn endpoint(mut self, endpoint : &str) -> WandboxBuilder {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Sets the compilers the library should ignore
+    ///
+    /// # Arguments
+    /// * `comps` - A set of compiler identifiers that the library should ignore
+    pub fn blocked_compilers(mut self, comps : HashSet<String>) -> WandboxBuilder {
+        self.filter.blocked_compilers = comps;
+        self
+    }
+
+    /// Sets the languages the library should ignore
+    ///
+    /// # Arguments
+    /// * `langs` - A set of language identifiers that the library should ignore
+    pub fn blocked_languages(mut self, langs : HashSet<String>) -> WandboxBuilder {
+        self.filter.blocked_languages = langs;
+        self
+    }
+
+    /// Restricts the library to the given compilers, dropping every compiler not listed here or
+    /// in `allowed_languages`. Blocked compilers stay blocked.
+    ///
+    /// # Arguments
+    /// * `comps` - A set of compiler identifiers that the library should expose
+    pub fn allowed_compilers(mut self, comps : HashSet<String>) -> WandboxBuilder {
+        self.filter.allowed_compilers = Some(comps);
+        self
+    }
+
+    /// Restricts the library to the compilers of the given languages, dropping every compiler not
+    /// belonging to one of them or listed in `allowed_compilers`. Blocked compilers stay blocked.
+    ///
+    /// # Arguments
+    /// * `langs` - A set of language identifiers that the library should expose
+    pub fn allowed_languages(mut self, langs : HashSet<String>) -> WandboxBuilder {
+        self.filter.allowed_languages = Some(langs);
+        self
+    }
+
+    /// Sets how long to wait for a connection to Wandbox to be established
+    ///
+    /// # Arguments
+    /// * `timeout` - The maximum time to spend connecting
+    pub fn connect_timeout(mut self, timeout : Duration) -> WandboxBuilder {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for a request to Wandbox to complete, including reading the response.
+    /// This applies to fetching the compiler list, compiling, and streamed compilations.
+    ///
+    /// # Arguments
+    /// * `timeout` - The maximum time to spend on a single request
+    pub fn request_timeout(mut self, timeout : Duration) -> WandboxBuilder {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Sends every request through the given proxy, for http & https alike
+    ///
+    /// # Arguments
+    /// * `url` - The url of the proxy i.e "http://proxy.example.com:8080"
+    pub fn proxy(mut self, url : &str) -> WandboxBuilder {
+        self.proxy = Some(url.to_string());
+        self
+    }
+
+    /// Connects to Wandbox directly, ignoring any proxy set through the environment such as
+    /// `HTTPS_PROXY`
+    pub fn no_proxy(mut self) -> WandboxBuilder {
+        self.no_proxy = true;
+        self
+    }
+
+    /// Sets the User-Agent header sent with every request
+    ///
+    /// # Arguments
+    /// * `user_agent` - The value of the header
+    pub fn user_agent(mut self, user_agent : &str) -> WandboxBuilder {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Fails `build` & `Wandbox::refresh` when any compiler in the list can't be understood,
+    /// instead of skipping it with a warning in `Wandbox::load_warnings`
+    ///
+    /// # Arguments
+    /// * `strict` - Whether a single malformed compiler should fail the whole list
+    pub fn strict(mut self, strict : bool) -> WandboxBuilder {
+        self.strict = strict;
+        self
+    }
+
+    /// Retries a compilation once when Wandbox rate limits it, after waiting as long as its
+    /// Retry-After header asks. Without this, or when the wait would exceed `max_wait`,
+    /// `WandboxError::RateLimited` is returned so the caller can queue the request instead.
+    ///
+    /// # Arguments
+    /// * `max_wait` - The longest to wait before retrying, also used when Wandbox doesn't say
+    pub fn retry_rate_limited(mut self, max_wait : Duration) -> WandboxBuilder {
+        self.rate_limit_retry = Some(max_wait);
+        self
+    }
+
+    /// Stops dispatching for a while once Wandbox keeps failing, so callers aren't each left
+    /// waiting for a timeout while it's down
+    ///
+    /// After `failures` consecutive failures within `window`, dispatches fail right away with
+    /// `WandboxError::CircuitOpen` until `cool_down` has passed. Then a single dispatch is let
+    /// through to probe Wandbox: success closes the breaker, failure opens it for another
+    /// cool-down. Only network errors, timeouts, 5xx replies & unreadable replies count as
+    /// failures, and any other reply resets the count.
+    ///
+    /// # Arguments
+    /// * `breaker` - The thresholds, i.e `CircuitBreaker::default()`
+    pub fn circuit_breaker(mut self, breaker : CircuitBreaker) -> WandboxBuilder {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Sends a compilation only once when identical ones are dispatched while it's in flight,
+    /// i.e the same snippet pasted by several users at once. The others get a clone of its
+    /// result instead.
+    ///
+    /// Compilations are identical when their request bodies are, and saved compilations are
+    /// never coalesced as each gets its own permlink. When the compilation in flight fails the
+    /// others are sent after all, as errors can't be shared.
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether identical compilations should be coalesced
+    pub fn coalesce_requests(mut self, enabled : bool) -> WandboxBuilder {
+        self.coalesce = enabled;
+        self
+    }
+
+    /// Limits the size of the code of a request, additional source files included. Larger
+    /// requests fail to build with `BuildError::TooLarge`.
+    ///
+    /// # Arguments
+    /// * `max` - The most bytes of code, `DEFAULT_MAX_CODE_BYTES` unless set
+    pub fn max_code_bytes(mut self, max : usize) -> WandboxBuilder {
+        self.limits.max_code_bytes = max;
+        self
+    }
+
+    /// Limits the size of the stdin of a request. Larger requests fail to build with
+    /// `BuildError::TooLarge`.
+    ///
+    /// # Arguments
+    /// * `max` - The most bytes of stdin, `DEFAULT_MAX_STDIN_BYTES` unless set
+    pub fn max_stdin_bytes(mut self, max : usize) -> WandboxBuilder {
+        self.limits.max_stdin_bytes = max;
+        self
+    }
+
+    /// Sends every request through an existing client instead of building one. The client's own
+    /// settings apply, so `connect_timeout`, `request_timeout`, `proxy`, `no_proxy` & `user_agent`
+    /// are ignored.
+    ///
+    /// # Arguments
+    /// * `client` - The client used to reach Wandbox
+    pub fn client(mut self, client : reqwest::Client) -> WandboxBuilder {
+        self.transport = Some(Arc::new(ReqwestTransport::new(client)));
+        self
+    }
+
+    /// Sends every request through a transport of your own instead of reqwest, i.e a
+    /// `MemoryTransport` in tests. As with `client`, `connect_timeout`, `request_timeout`, `proxy`,
+    /// `no_proxy` & `user_agent` are ignored.
+    ///
+    /// # Arguments
+    /// * `transport` - The transport used to reach Wandbox
+    pub fn transport(mut self, transport : Box<dyn WandboxTransport>) -> WandboxBuilder {
+        self.transport = Some(Arc::from(transport));
+        self
+    }
+
+    /// Periodically re-fetches the compiler list in the background, as with `Wandbox::refresh`
+    ///
+    /// A failed refresh keeps the previous cache; the outcome of each attempt is reported through
+    /// `Wandbox::refresh_status`. The background task stops when the `Wandbox` is dropped. This
+    /// requires the `Wandbox` to be built within a tokio runtime.
+    ///
+    /// # Arguments
+    /// * `interval` - How long to wait between refreshes
+    pub fn auto_refresh(mut self, interval : Duration) -> WandboxBuilder {
+        self.auto_refresh = Some(interval);
+        self
+    }
+
+    /// Fetches the compiler list & initializes the cache for Wandbox requests
+    pub async fn build(self) -> WandboxResult<Wandbox> {
+        let (endpoint, transport) = self.connect()?;
+
+        let (compilers, warnings, validators) = cache::load(transport.as_ref(), &endpoint, self.strict).await?;
+        let wbox = self.finish(build_cache(compilers), endpoint, transport, SystemTime::now());
+        *write_lock(&wbox.load_warnings) = warnings;
+        *write_lock(&wbox.validators) = validators;
+        Ok(wbox)
+    }
+
+    /// Fetches the compiler list like `build`, loading it from `path` instead when Wandbox can't
+    /// be reached
+    ///
+    /// See `Wandbox::new_with_fallback`, configuration errors such as an invalid endpoint are
+    /// returned without trying the file.
+    ///
+    /// # Arguments
+    /// * `path` - The file the compiler list is saved to & loaded from
+    pub async fn build_with_fallback<P : AsRef<Path>>(self, path : P) -> WandboxResult<(Wandbox, CacheOrigin)> {
+        let (endpoint, transport) = self.connect()?;
+
+        let network = match cache::load(transport.as_ref(), &endpoint, self.strict).await {
+            Ok((compilers, warnings, validators)) => {
+                let fetched_at = SystemTime::now();
+                // saved before filtering, so compilers unblocked later are still there
+                let source = build_cache(compilers);
+                cache::write(path.as_ref(), &source, fetched_at)?;
+
+                let wbox = self.finish(source, endpoint, transport, fetched_at);
+                *write_lock(&wbox.load_warnings) = warnings;
+                *write_lock(&wbox.validators) = validators;
+                return Ok((wbox, CacheOrigin::Network));
+            },
+            Err(e) => e
+        };
+
+        match cache::read(path.as_ref()) {
+            Ok((source, fetched_at)) => {
+                let age = SystemTime::now().duration_since(fetched_at).unwrap_or_default();
+                Ok((self.finish(source, endpoint, transport, fetched_at), CacheOrigin::Disk { age }))
+            },
+            Err(e) => Err(WandboxError::FallbackFailed {
+                network : Box::new(network),
+                cache : Box::new(e)
+            })
+        }
+    }
+
+    /// Validates the endpoint & sets up the transport, without contacting Wandbox
+    fn connect(&self) -> WandboxResult<(String, Arc<dyn WandboxTransport>)> {
+        let endpoint = normalize_endpoint(&self.endpoint)?;
+
+        let transport : Arc<dyn WandboxTransport> = match &self.transport {
+            Some(transport) => transport.clone(),
+            None => {
+                let mut client = reqwest::Client::builder();
+                if let Some(timeout) = self.connect_timeout {
+                    client = client.connect_timeout(timeout);
+                }
+                if let Some(timeout) = self.request_timeout {
+                    client = client.timeout(timeout);
+                }
+                if self.no_proxy {
+                    client = client.no_proxy();
+                }
+                if let Some(proxy) = self.reqwest_proxy()? {
+                    client = client.proxy(proxy);
+                }
+                if let Some(user_agent) = &self.user_agent {
+                    client = client.user_agent(user_agent.as_str());
+                }
+                match client.build() {
+                    Ok(client) => Arc::new(ReqwestTransport::new(client)),
+                    Err(e) => return Err(WandboxError::from(e))
+                }
+            }
+        };
+
+        Ok((endpoint, transport))
+    }
+
+    /// The proxy set with `proxy`, shared by the async & blocking clients
+    fn reqwest_proxy(&self) -> WandboxResult<Option<reqwest::Proxy>> {
+        match &self.proxy {
+            Some(url) => match reqwest::Proxy::all(url.as_str()) {
+                Ok(proxy) => Ok(Some(proxy)),
+                Err(_e) => Err(WandboxError::InvalidProxy(url.clone()))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Initializes the cache from a compiler list, with the builder's settings applied
+    fn finish(self, source : CompilerCache, endpoint : String, transport : Arc<dyn WandboxTransport>, fetched_at : SystemTime) -> Wandbox {
+        let mut wbox = Wandbox::assemble(source, endpoint, transport, fetched_at, self.filter);
+        wbox.rate_limit_retry = self.rate_limit_retry;
+        wbox.breaker.configure(self.breaker);
+        wbox.coalescer.set_enabled(self.coalesce);
+        wbox.strict = self.strict;
+        *write_lock(&wbox.limits) = self.limits;
+        if let Some(interval) = self.auto_refresh {
+            wbox.refresher = Some(Arc::new(RefreshTask::spawn(wbox.detached(), interval)));
+        }
+        wbox
+    }
+}
+
+/// Fetches Wandbox's compiler list without building a `Wandbox`, i.e to inspect or diff it
+///
+/// Compilers listed in a shape this crate doesn't understand are skipped. Pass the result to
+/// `build_cache` to organize it by language.
+///
+/// # Arguments
+/// * `base_url` - The base url of the Wandbox instance, wandbox.org if not given
+/// * `client` - The client used to reach Wandbox, a default one if not given
+/// # Example
+/// ```edition2018
+///use wandbox::{build_cache, fetch_compiler_list};
+///
+///#[tokio::main]
+///async fn main() {
+///    let compilers = match fetch_compiler_list(None, None).await {
+///        Ok(compilers) => compilers,
+///        Err(e) => return println!("{}", e)
+///    };
+///    let cache = build_cache(compilers);
+///    println!("{} languages", cache.len());
+///}
+/// ```
+pub async fn fetch_compiler_list(base_url : Option<&str>, client : Option<&reqwest::Client>) -> WandboxResult<Vec<Compiler>> {
+    let endpoint = normalize_endpoint(base_url.unwrap_or(WANDBOX_URL))?;
+    let transport = ReqwestTransport::new(client.cloned().unwrap_or_default());
+
+    let (compilers, _warnings, _validators) = cache::load(&transport, &endpoint, false).await?;
+    Ok(compilers)
+}
+
+/// The size of a request's code & additional files, in bytes
+fn code_bytes(code : &str, codes : &[CodeFile]) -> usize {
+    code.len() + codes.iter().map(|c| c.code.len()).sum::<usize>()
+}
+
+/// Waits before a retry, on the tokio runtime if there is one or else by blocking the thread, as
+/// `blocking::Wandbox` drives requests without a runtime
+async fn pause(duration : Duration) {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(duration).await;
+    }
+    else {
+        std::thread::sleep(duration);
+    }
+}
+
+/// The transport used when none is given, a plain reqwest client
+fn default_transport() -> Arc<dyn WandboxTransport> {
+    Arc::new(ReqwestTransport::default())
+}
+
+/// A saved compilation as returned by `Wandbox::share`, both fields are always filled in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permlink {
+    /// The permlink identifier, as accepted by `Wandbox::get_permlink`
+    pub id : String,
+    /// The url the compilation can be shared with
+    pub url : String,
+}
+impl Permlink {
+    /// Fills in whichever of the id & url Wandbox left out, the url being `{endpoint}/permlink/{id}`
+    fn from_result(result : &CompilationResult, endpoint : &str) -> Option<Permlink> {
+        let id = match (&result.permlink, &result.url) {
+            (Some(id), _) => id.trim().to_string(),
+            (None, Some(url)) => url.trim_end_matches('/').rsplit('/').next()?.to_string(),
+            (None, None) => return None
+        };
+        if id.is_empty() {
+            return None;
+        }
+
+        let url = match &result.url {
+            Some(url) => url.clone(),
+            None => format!("{}/permlink/{}", endpoint, id)
+        };
+        Some(Permlink { id, url })
+    }
+}
+
+/// The optional parts of a request sent with `Wandbox::run_with`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    /// Sent to the program's standard input
+    pub stdin : String,
+    /// Raw compiler options i.e ["-Wall", "-Werror"]
+    pub options : Vec<String>,
+    /// Raw runtime options, passed to the executed program
+    pub runtime_options : Vec<String>,
+    /// Wandbox switch names i.e ["c++2a", "warning"]
+    pub switches : Vec<String>,
+    /// Whether to save the compilation & get a permlink
+    pub save : bool,
+}
+
+/// How `Wandbox::get_compilers_sorted` orders compilers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Alphabetically by compiler identifier
+    Name,
+    /// Newest version first, with `-head` builds either before or after every other compiler
+    Version { head_first : bool },
+}
+
+/// The most bytes of code a request carries by default
+pub const DEFAULT_MAX_CODE_BYTES : usize = 1024 * 1024;
+
+/// The most bytes of stdin a request carries by default
+pub const DEFAULT_MAX_STDIN_BYTES : usize = 1024 * 1024;
+
+/// How large a request `CompilationBuilder::build` accepts, so oversized requests fail before
+/// they're sent rather than with an opaque error from Wandbox
+///
+/// The defaults stay within what wandbox.org accepts, tighten them with `Wandbox::set_limits`
+/// i.e for a bot that never relays more than 100 KB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// The most bytes of code, additional source files included
+    pub max_code_bytes : usize,
+    /// The most bytes of stdin
+    pub max_stdin_bytes : usize,
+}
+impl Default for RequestLimits {
+    fn default() -> RequestLimits {
+        RequestLimits {
+            max_code_bytes : DEFAULT_MAX_CODE_BYTES,
+            max_stdin_bytes : DEFAULT_MAX_STDIN_BYTES
+        }
+    }
+}
+
+/// When `WandboxBuilder::circuit_breaker` stops dispatching to Wandbox
+///
+/// The defaults open the breaker after 5 failures within a minute, for 30 seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// How many consecutive failures open the breaker
+    pub failures : u32,
+    /// How recent those failures must be, older ones are forgotten
+    pub window : Duration,
+    /// How long the breaker stays open before a probe is let through
+    pub cool_down : Duration,
+}
+impl Default for CircuitBreaker {
+    fn default() -> CircuitBreaker {
+        CircuitBreaker {
+            failures : 5,
+            window : Duration::from_secs(60),
+            cool_down : Duration::from_secs(30)
+        }
+    }
+}
+
+/// The state of the circuit breaker, as returned by `Wandbox::circuit_state`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Dispatches go through, `failures` counts the recent failures since the last success
+    Closed { failures : u32 },
+    /// Dispatches fail with `WandboxError::CircuitOpen` until `retry_at`
+    Open { retry_at : SystemTime },
+    /// The cool-down is over, the next dispatch probes whether Wandbox is back while others
+    /// keep failing fast
+    HalfOpen,
+}
+
+/// Compilers of a language sharing a toolchain, as returned by `Wandbox::get_compiler_families`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompilerFamily {
+    /// The toolchain, i.e "gcc" or "clang"
+    pub name : String,
+    /// The toolchain's compilers, newest first with `-head` builds leading
+    pub compilers : Vec<Compiler>,
+}
+
+/// How `Wandbox::format_compilers` lays out a list of compilers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListStyle {
+    /// One compiler per line as `display name — version`
+    Plain,
+    /// A table of display names & versions within a code block, so it lines up in chat clients
+    /// that don't render tables
+    Markdown,
+}
+
+/// An optimization level for `CompilationBuilder::optimization`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OptLevel {
+    /// No optimization, `-O0`
+    O0,
+    /// `-O1`
+    O1,
+    /// `-O2`
+    O2,
+    /// `-O3`
+    O3,
+    /// Optimize for size, `-Os`
+    Os,
+}
+impl OptLevel {
+    /// The gcc & clang flag for the level, i.e "-O2"
+    pub fn flag(&self) -> &'static str {
+        match self {
+            OptLevel::O0 => "-O0",
+            OptLevel::O1 => "-O1",
+            OptLevel::O2 => "-O2",
+            OptLevel::O3 => "-O3",
+            OptLevel::Os => "-Os",
+        }
+    }
+}
+
+/// How `Wandbox::get_default_compiler` picks a compiler for a language
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DefaultStrategy {
+    /// The first compiler Wandbox lists for the language, often a `-head` build
+    #[default]
+    First,
+    /// The newest compiler that isn't a `-head` build, see `Wandbox::get_latest_compiler`
+    LatestStable,
+    /// Explicit compilers per language, i.e "c++" to "gcc-10.1.0", other languages use `First`
+    Named(HashMap<String, String>),
+}
+
+/// The outcome of the most recent background refresh
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefreshStatus {
+    /// No refresh has been attempted yet
+    Idle,
+    /// The compiler list was re-fetched and the cache replaced
+    Refreshed(CacheDiff),
+    /// The compiler list could not be fetched, the previous cache is still in use
+    Failed(String),
+}
+
+/// A background task that refreshes the cache, stopped when dropped
+struct RefreshTask {
+    handle : JoinHandle<()>,
+    status : watch::Receiver<RefreshStatus>,
+}
+
+impl RefreshTask {
+    fn spawn(wbox : Wandbox, interval : Duration) -> RefreshTask {
+        let (sender, status) = watch::channel(RefreshStatus::Idle);
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let status = match wbox.refresh().await {
+                    Ok(diff) => RefreshStatus::Refreshed(diff),
+                    Err(e) => RefreshStatus::Failed(e.to_string())
+                };
+                if sender.send(status).is_err() {
+                    return;
+                }
+            }
+        });
+
+        RefreshTask {
+            handle,
+            status
+        }
+    }
+}
+
+impl Drop for RefreshTask {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl Default for WandboxBuilder {
+    fn default() -> Self {
+        WandboxBuilder::new()
+    }
+}
+
+/// Locks for reading, carrying on if a writer panicked while holding the lock
+///
+/// Every write replaces the guarded value in a single assignment, so a poisoned lock still holds
+/// consistent data.
+pub(crate) fn read_lock<T>(lock : &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Locks for writing, carrying on if another writer panicked while holding the lock
+pub(crate) fn write_lock<T>(lock : &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Validates a Wandbox base url, stripping any trailing slashes
+fn normalize_endpoint(endpoint : &str) -> WandboxResult<String> {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    let host = match endpoint.strip_prefix("https://").or_else(|| endpoint.strip_prefix("http://")) {
+        Some(host) => host,
+        None => return Err(WandboxError::InvalidEndpoint(endpoint.to_string()))
+    };
+
+    if host.is_empty() {
+        return Err(WandboxError::InvalidEndpoint(endpoint.to_string()));
+    }
+    Ok(endpoint.to_string())
+}
+
+/// Whether a permlink id or template name can go in a url path as is, only letters, digits, `_`
+/// & `-` are allowed so it can't escape into another path
+fn is_path_segment(segment : &str) -> bool {
+    !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Representation of a compiler
+#[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Compiler {
+    #[serde(rename = "compiler-option-raw")]
+    pub compiler_option_raw : bool,
+    /// Shared with every other compiler listed with the same command
+    #[serde(rename = "display-compile-command", with = "crate::intern")]
+    pub display_compile_command : Arc<str>,
+    #[serde(rename = "runtime-option-raw")]
+    pub runtime_option_raw : bool,
+
+    pub version : String,
+    /// Shared with the compiler's `Language::name` & every other compiler of the language
+    #[serde(with = "crate::intern")]
+    pub language : Arc<str>,
+    pub name : String,
+    /// A human friendly name such as "gcc HEAD", the cache fills this in with `name` when
+    /// Wandbox omits it
+    #[serde(rename = "display-name", default)]
+    pub display_name : String,
+    #[serde(default)]
+    pub templates : Vec<String>,
+    #[serde(default)]
+    pub switches : Vec<Switch>,
+    #[serde(flatten)]
+    extra : HashMap<String, serde_json::Value>,
+}
+impl Compiler {
+    /// Fields Wandbox sent that this crate does not know about
+    pub fn raw_extra(&self) -> &HashMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    /// Parses the version number out of `version`, `None` when it doesn't contain one
+    pub fn parsed_version(&self) -> Option<Version> {
+        Version::parse(&self.version)
+    }
+
+    /// Orders two compilers by version, compilers without a version number come first
+    ///
+    /// # Arguments
+    /// * `other` - The compiler to compare against
+    pub fn cmp_version(&self, other : &Compiler) -> Ordering {
+        version::compare(&self.version, &other.version)
+    }
+
+    /// Whether this is a build of the compiler's development branch, i.e "gcc-head"
+    pub fn is_head(&self) -> bool {
+        self.name.contains("head")
+    }
+
+    /// The switches Wandbox enables by default, the default of each select group & every boolean
+    /// switch that is on by default, i.e "warning" & "gnu++2a" for gcc
+    pub fn default_switches(&self) -> Vec<&str> {
+        self.switches.iter()
+            .filter_map(|s| match s {
+                Switch::Single { name, default : true, .. } => Some(name.as_str()),
+                Switch::Select { default, .. } if !default.is_empty() => Some(default.as_str()),
+                _ => None
+            })
+            .collect()
+    }
+}
+// unknown fields can't be hashed, equal compilers still hash equally without them
+impl Hash for Compiler {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.compiler_option_raw.hash(state);
+        self.display_compile_command.hash(state);
+        self.runtime_option_raw.hash(state);
+        self.version.hash(state);
+        self.language.hash(state);
+        self.name.hash(state);
+        self.display_name.hash(state);
+        self.templates.hash(state);
+        self.switches.hash(state);
+    }
+}
+impl fmt::Debug for Compiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{} ({}) {}] : {}", self.name, self.display_name, self.version, self.language)
+    }
+}
+
+/// A selectable option Wandbox advertises for a compiler
+#[derive(Hash, Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Switch {
+    /// A switch that is either on or off, such as warnings
+    Single {
+        name : String,
+        #[serde(default)]
+        default : bool,
+        #[serde(rename = "display-name", default)]
+        display_name : String,
+        #[serde(rename = "display-flags", default)]
+        display_flags : String,
+    },
+    /// A group of mutually exclusive switches, such as the language standard
+    Select {
+        default : String,
+        options : Vec<SwitchOption>,
+    },
+    /// A kind of switch this crate does not know about yet
+    #[serde(other)]
+    Unknown,
+}
+
+impl Switch {
+    /// Returns the switch names that may be passed to `CompilationBuilder::switches`
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Switch::Single { name, .. } => vec![name.as_str()],
+            Switch::Select { options, .. } => options.iter().map(|o| o.name.as_str()).collect(),
+            Switch::Unknown => Vec::new()
+        }
+    }
+}
+
+/// One of the choices within a `Switch::Select` group
+#[derive(Hash, Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct SwitchOption {
+    pub name : String,
+    #[serde(rename = "display-name", default)]
+    pub display_name : String,
+    #[serde(rename = "display-flags", default)]
+    pub display_flags : String,
+}
+
+/// A builder to allow you to easily build requests
+///
+/// ```edition2018
+///use std::collections::HashSet;
+///use tokio::macros::*;
+///use std::error::Error;
+///use wandbox::{Wandbox, CompilationBuilder};
+///#[tokio::main]
+///async fn main() {
+///    let wbox : Wandbox = match Wandbox::new(None, None).await {
+///        Ok(wbox) => wbox,
+///        Err(e) => return println!("{}", e)
+///    };
+///    let mut builder = CompilationBuilder::new();
+///    builder.target("gcc-6.3.0");
+///    builder.options(vec!["-Wall", "-Werror"]);
+///    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+///    let compilation = match builder.build(&wbox) {
+///        Ok(compilation) => compilation,
+///        Err(e) => return println!("{}", e)
+///    };
+///    let result = compilation.dispatch().await;
+///}
+/// ```
+///
+/// Setters return the builder, so they can also be chained
+///
+/// ```edition2018
+///use wandbox::{Wandbox, CompilationBuilder};
+///
+///let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None).unwrap();
+///let mut builder = CompilationBuilder::new();
+///builder.target("c++")
+///    .code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}")
+///    .options(vec!["-O2", "-Wall"]);
+///let compilation = builder.build(&wbox).unwrap();
+///assert_eq!(compilation.lang(), "c++");
+/// ```
+///
+/// Builders can be cloned to send variations of a request, and their `Debug` output shows the
+/// first line of the code rather than all of it.
+///
+/// Unless switches are set, requests get the compiler's default switches as on the Wandbox
+/// website, where they used to be sent without any. Use `no_default_switches` to send none.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompilationBuilder {
+    target : String,
+    code : String,
+    codes : Vec<CodeFile>,
+    stdin : String,
+    options : Vec<String>,
+    runtime_options : Vec<String>,
+    switches : Vec<String>,
+    save : bool,
+    lenient : bool,
+    auto_detect : bool,
+    no_default_switches : bool,
+    warnings_as_errors : bool,
+    optimization : Option<OptLevel>,
+    standard : Option<String>,
+    // set by `compiler_unchecked`, the language sent along with an unresolved target
+    language : Option<String>,
+    // carried over into `Compilation::warnings`, i.e from `from_permlink`
+    warnings : Vec<String>
+}
+impl CompilationBuilder {
+    /// Creates a new CompilationBuilder with default values to be filled in later
+    pub fn new() -> CompilationBuilder {
+        CompilationBuilder { ..Default::default()}
+    }
+
+    /// Fills a builder with the parameters of a saved compilation, i.e to run it again with tweaks
+    ///
+    /// The code, additional files, stdin, compiler, raw options & switches are restored. When the
+    /// compiler is no longer available, the default compiler of its language is used instead and
+    /// switches it doesn't offer are dropped, each noted in `warnings`. The language is guessed
+    /// from the code, or else from the compiler id.
+    ///
+    /// # Arguments
+    /// * `wb` - An instance of the Wandbox cache to fetch the permlink through
+    /// * `id` - The permlink identifier, or its url i.e "https://wandbox.org/permlink/AbCdEfGh"
+    pub async fn from_permlink(wb : &Wandbox, id : &str) -> WandboxResult<CompilationBuilder> {
+        let id = match id.trim().rsplit_once("/permlink/") {
+            Some((_base, id)) => id.trim_end_matches('/'),
+            None => id.trim()
+        };
+        let saved = wb.get_permlink(id).await?.request;
+
+        let lines = |raw : &str| raw.lines().filter(|l| !l.is_empty()).map(|l| l.to_string()).collect::<Vec<String>>();
+        let mut builder = CompilationBuilder {
+            target : saved.compiler.clone(),
+            code : saved.code,
+            codes : saved.codes,
+            stdin : saved.stdin,
+            options : lines(&saved.compiler_options_raw),
+            runtime_options : lines(&saved.runtime_options_raw),
+            switches : saved.switches.split(',').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        // an empty list was sent on purpose, the defaults weren't applied then either
+        builder.no_default_switches = builder.switches.is_empty();
+        if wb.is_valid_compiler_str(&saved.compiler) {
+            return Ok(builder);
+        }
+
+        let family = version::family(&saved.compiler);
+        let lang = detect_language(&builder.code).and_then(|l| wb.resolve_language(l)).or_else(|| {
+            let mut langs : Vec<String> = Vec::new();
+            wb.for_each_compiler(|l, c| {
+                if version::family(&c.name) == family {
+                    langs.push(l.to_string());
+                }
+            });
+            langs.into_iter().min()
+        });
+        let comp = match lang.as_ref().and_then(|l| wb.get_default_compiler(l)).and_then(|c| wb.get_compiler(&c)) {
+            Some(comp) => comp,
+            None => return Err(BuildError::UnknownTarget { input : saved.compiler, suggestions : Vec::new() }.into())
+        };
+
+        builder.warnings.push(format!("{} is no longer available, using {} instead", saved.compiler, comp.name));
+        let (kept, dropped) : (Vec<String>, Vec<String>) = builder.switches.into_iter()
+            .partition(|s| comp.switches.iter().any(|offered| offered.names().contains(&s.as_str())));
+        if !dropped.is_empty() {
+            builder.warnings.push(format!("{} does not offer the switches {}, dropped", comp.name, dropped.join(", ")));
+        }
+        builder.switches = kept;
+        builder.target = comp.name.clone();
+        Ok(builder)
+    }
+
+    /// Problems found while filling the builder, i.e by `from_permlink`, which `build` carries
+    /// over into `Compilation::warnings`
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Sets the target of the compilation
+    ///
+    /// # Arguments
+    /// * `target` - The target of a compilation, this can be a language ('c++'), or a compiler ('gcc-head')
+    pub fn target(&mut self, target : impl AsRef<str>) -> &mut CompilationBuilder {
+        self.target = target.as_ref().trim().to_string();
+        self
+    }
+
+    /// Sets the code to be compiled, exactly as given
+    ///
+    /// # Arguments
+    /// * `code` - String of code to be compiled
+    pub fn code(&mut self, code : impl Into<String>) -> &mut CompilationBuilder {
+        self.code = code.into();
+        self
+    }
+
+    /// Sets the code to be compiled, stripping leading & trailing whitespace
+    ///
+    /// # Arguments
+    /// * `code` - String of code to be compiled
+    pub fn code_trimmed(&mut self, code : impl AsRef<str>) -> &mut CompilationBuilder {
+        self.code(code.as_ref().trim())
+    }
+
+    /// Sets the code to be compiled from the contents of a file
+    ///
+    /// If no target was set yet, the language is guessed from the file extension, i.e "main.cpp"
+    /// targets "c++". Files that aren't valid UTF-8 are rejected with `ErrorKind::InvalidData`.
+    ///
+    /// # Arguments
+    /// * `path` - The file to read the code from
+    pub fn code_file<P : AsRef<Path>>(&mut self, path : P) -> io::Result<&mut CompilationBuilder> {
+        let path = path.as_ref();
+        let code = match String::from_utf8(std::fs::read(path)?) {
+            Ok(code) => code,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("{} is not valid UTF-8: {}", path.display(), e.utf8_error())))
+        };
+
+        if self.target.is_empty() {
+            if let Some(lang) = path.extension().and_then(|e| e.to_str()).and_then(alias::language_for_extension) {
+                self.target = lang.to_string();
+            }
+        }
+        Ok(self.code(code))
+    }
+
+    /// Adds an additional source file to the compilation, alongside the primary `code`
+    ///
+    /// This is useful for programs split across multiple translation units, or for supplying
+    /// a local header that the primary source includes.
+    ///
+    /// # Arguments
+    /// * `file` - The file name as seen by the compiler i.e "util.h"
+    /// * `code` - Contents of the file
+    pub fn add_file(&mut self, file : impl AsRef<str>, code : impl Into<String>) -> WandboxResult<&mut CompilationBuilder> {
+        let file = file.as_ref().trim();
+        if file.is_empty() {
+            return Err(WandboxError::EmptyFileName);
+        }
+        if self.codes.iter().any(|f| f.file == file) {
+            return Err(WandboxError::DuplicateFile(file.to_string()));
+        }
+
+        self.codes.push(CodeFile {
+            file : file.to_string(),
+            code : code.into()
+        });
+        Ok(self)
+    }
+
+    /// Sets the stdin to directed towards the application, exactly as given
+    ///
+    /// # Arguments
+    /// * `stdin` - program input
+    pub fn stdin(&mut self, stdin : impl Into<String>) -> &mut CompilationBuilder {
+        self.stdin = stdin.into();
+        self
+    }
+
+    /// Appends a line to the stdin directed towards the application, terminated by a newline
+    ///
+    /// # Arguments
+    /// * `line` - A line of program input, without its newline
+    pub fn append_stdin(&mut self, line : impl AsRef<str>) -> &mut CompilationBuilder {
+        self.stdin.push_str(line.as_ref());
+        self.stdin.push('\n');
+        self
+    }
+
+    /// Determines whether or not Wandbox saves the compilation & replies with a link for you
+    ///
+    /// # Arguments
+    /// * `save` - true if Wandbox should save this compilation
+    pub fn save(&mut self, save : bool) -> &mut CompilationBuilder {
+        self.save = save;
+        self
+    }
+
+    /// Sets the list of compilation options. Useful for languages like c++ to pass linker/optimization
+    /// flags.
+    ///
+    /// # Arguments
+    /// * `options` - A list of compiler options i.e ["-Wall", "-Werror"], as `&str`s or `String`s
+    pub fn options(&mut self, options : impl IntoIterator<Item = impl Into<String>>) -> &mut CompilationBuilder {
+        self.options = options.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the list of compilation options. Useful for languages like c++ to pass linker/optimization
+    /// flags.
+    ///
+    /// This version allows you to pass a `Vec<&str>`
+    ///
+    /// # Arguments
+    /// * `options` - A list of compiler options i.e ["-Wall", "-Werror"]
+    #[deprecated(note = "`options` accepts a `Vec<&str>` as well")]
+    pub fn options_str(&mut self, options : Vec<&str>) -> &mut CompilationBuilder {
+        self.options(options)
+    }
+
+    /// Appends a single compilation option to those already set
+    ///
+    /// # Arguments
+    /// * `option` - A compiler option i.e "-Wall"
+    pub fn add_option(&mut self, option : impl Into<String>) -> &mut CompilationBuilder {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Sets the list of compilation options from a single command line, split the way a shell
+    /// would split it
+    ///
+    /// Quotes & backslashes group and escape as in a POSIX shell, without expanding variables
+    /// or globs. Malformed quoting, i.e an unterminated quote, fails with
+    /// `BuildError::MalformedOptions` and leaves the options unchanged.
+    ///
+    /// # Arguments
+    /// * `options` - The compiler options i.e `-O2 -Wall "-DNAME=hello world"`
+    pub fn options_from_str(&mut self, options : &str) -> WandboxResult<&mut CompilationBuilder> {
+        match shell::split(options) {
+            Ok(split) => self.options = split,
+            Err(reason) => return Err(BuildError::MalformedOptions { input : options.to_string(), reason }.into())
+        }
+        Ok(self)
+    }
+
+    /// Sets the list of runtime options. These are passed to the executed program, i.e argv for a
+    /// compiled binary or flags for an interpreter.
+    ///
+    /// # Arguments
+    /// * `options` - A list of runtime options i.e ["--verbose", "input.txt"], as `&str`s or `String`s
+    pub fn runtime_options(&mut self, options : impl IntoIterator<Item = impl Into<String>>) -> &mut CompilationBuilder {
+        self.runtime_options = options.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the list of runtime options. These are passed to the executed program, i.e argv for a
+    /// compiled binary or flags for an interpreter.
+    ///
+    /// This version allows you to pass a `Vec<&str>`
+    ///
+    /// # Arguments
+    /// * `options` - A list of runtime options i.e ["--verbose", "input.txt"]
+    #[deprecated(note = "`runtime_options` accepts a `Vec<&str>` as well")]
+    pub fn runtime_options_str(&mut self, options : Vec<&str>) -> &mut CompilationBuilder {
+        self.runtime_options(options)
+    }
+
+    /// Sets the list of Wandbox compiler switches. These are the selectable options Wandbox
+    /// advertises for each compiler, such as a language standard or warnings.
+    ///
+    /// These are independent of the raw options set with `options`. Setting any switches replaces
+    /// the compiler's default switches entirely, see `no_default_switches`.
+    ///
+    /// # Arguments
+    /// * `switches` - A list of Wandbox switch names i.e ["c++2a", "warning"]
+    pub fn switches(&mut self, switches : impl IntoIterator<Item = impl Into<String>>) -> &mut CompilationBuilder {
+        self.switches = switches.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sends no switches unless set with `switches`
+    ///
+    /// By default a request without switches gets the compiler's default switches, as the Wandbox
+    /// website applies them, so results match what users see there. See
+    /// `Compiler::default_switches`.
+    pub fn no_default_switches(&mut self) -> &mut CompilationBuilder {
+        self.no_default_switches = true;
+        self
+    }
+
+    /// Turns warnings into errors, with `-Werror`
+    ///
+    /// Like `optimization` & `cpp_standard` this is applied once `build` has resolved the
+    /// compiler, and only gcc & clang compilers support it, others fail with
+    /// `BuildError::FlagUnsupported`.
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether warnings should be errors
+    pub fn warnings_as_errors(&mut self, enabled : bool) -> &mut CompilationBuilder {
+        self.warnings_as_errors = enabled;
+        self
+    }
+
+    /// Sets the optimization level, sent as a raw option such as `-O2`
+    ///
+    /// Only gcc & clang compilers support it, others fail `build` with
+    /// `BuildError::FlagUnsupported`.
+    ///
+    /// # Arguments
+    /// * `level` - The optimization level i.e `OptLevel::O2`
+    pub fn optimization(&mut self, level : OptLevel) -> &mut CompilationBuilder {
+        self.optimization = Some(level);
+        self
+    }
+
+    /// Sets the language standard to compile against
+    ///
+    /// When the resolved compiler advertises a switch of that name it's selected in place of the
+    /// rest of its group, i.e the default standard, otherwise `-std=` with the standard is sent
+    /// as a raw option. Only gcc & clang C++ compilers support it, others, including gcc & clang
+    /// C compilers, fail `build` with `BuildError::FlagUnsupported`.
+    ///
+    /// # Arguments
+    /// * `standard` - The standard as gcc names it i.e "c++17" or "gnu++2a"
+    pub fn cpp_standard(&mut self, standard : impl Into<String>) -> &mut CompilationBuilder {
+        self.standard = Some(standard.into());
+        self
+    }
+
+    /// Drops raw options the resolved compiler doesn't accept instead of failing `build`
+    ///
+    /// Each dropped set of options is recorded in `Compilation::warnings`.
+    ///
+    /// # Arguments
+    /// * `lenient` - Whether unsupported raw options should be dropped
+    pub fn lenient(&mut self, lenient : bool) -> &mut CompilationBuilder {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Guesses the language from the code with `detect_language` when no target is set, instead
+    /// of failing `build` with `BuildError::MissingTarget`
+    ///
+    /// # Arguments
+    /// * `auto_detect` - Whether a missing target should be detected
+    pub fn auto_detect(&mut self, auto_detect : bool) -> &mut CompilationBuilder {
+        self.auto_detect = auto_detect;
+        self
+    }
+
+    /// Sets the compiler & language as is, for `build_unchecked` to use without resolving them
+    ///
+    /// Useful for compilers that appeared on Wandbox after the cache was fetched, or without a
+    /// cache at all. Nothing checks that the compiler exists: Wandbox rejects unknown compilers
+    /// itself, which only shows once the request is dispatched.
+    ///
+    /// # Arguments
+    /// * `compiler` - The Wandbox compiler id i.e "gcc-head"
+    /// * `lang` - The language the compiler belongs to i.e "c++"
+    pub fn compiler_unchecked(&mut self, compiler : impl Into<String>, lang : impl Into<String>) -> &mut CompilationBuilder {
+        self.target = compiler.into();
+        self.language = Some(lang.into());
+        self
+    }
+
+    /// Finalizes the builder into a request for the compiler set with `compiler_unchecked`,
+    /// without a cache to resolve it against
+    ///
+    /// Only local checks run: the code must be set & fit the default `RequestLimits`. Options
+    /// and switches are sent as is and the compiler's default switches aren't applied, as
+    /// nothing is known about the compiler. Wandbox rejects unknown compilers server-side, so
+    /// a mistyped compiler fails on dispatch rather than here.
+    pub fn build_unchecked(mut self) -> WandboxResult<Compilation> {
+        let lang = match &self.language {
+            Some(lang) if !self.target.is_empty() => lang.clone(),
+            _ => return Err(BuildError::MissingTarget.into())
+        };
+        self.check_local(RequestLimits::default())?;
+
+        let compiler = self.target.clone();
+        self.apply_flag_helpers(&compiler, &lang, &[])?;
+        let warnings = self.warnings.clone();
+        Ok(Compilation {
+            endpoint : WANDBOX_URL.to_string(),
+            transport : default_transport(),
+            rate_limit_retry : None,
+            throttle : Arc::new(Throttle::default()),
+            breaker : Arc::new(Breaker::default()),
+            coalescer : Arc::new(Coalescer::default()),
+            results : Arc::new(ResultCache::default()),
+            hooks : Arc::new(RwLock::new(Hooks::default())),
+            lang,
+            request : self.into_request(compiler),
+            warnings
+        })
+    }
+
+    /// Finalizes the builder, resolving the target against the cache, into a request that can be
+    /// dispatched
+    ///
+    /// The request is checked before anything is sent: a target & code must be set, and raw
+    /// compiler & runtime options and switches must be supported by the resolved compiler. An
+    /// unknown target comes with the closest matches, i.e "gcc-head" for "gcc-haed". Problems
+    /// found are returned as `WandboxError::Build`.
+    ///
+    /// # Arguments
+    /// * `wb` - An instance of the Wandbox cache to resolve the compilation target
+    pub fn build(self, wb : &Wandbox) -> WandboxResult<Compilation> {
+        let span = trace::span!("wandbox::build", target = self.target.as_str(), code_len = code_bytes(&self.code, &self.codes),
+                                compiler = tracing::field::Empty, language = tracing::field::Empty);
+        span.run(|| {
+            let compilation = self.resolve(wb)?;
+            span.record("compiler", compilation.compiler());
+            span.record("language", compilation.lang());
+            Ok(compilation)
+        })
+    }
+
+    fn resolve(mut self, wb : &Wandbox) -> Result<Compilation, BuildError> {
+        if self.target.is_empty() && self.auto_detect {
+            if let Some(lang) = detect_language(&self.code) {
+                self.target = lang.to_string();
+            }
+        }
+        if self.target.is_empty() {
+            return Err(BuildError::MissingTarget);
+        }
+        self.check_local(wb.limits())?;
+        let unknown = |wb : &Wandbox, input : &str| BuildError::UnknownTarget {
+            input : input.to_string(),
+            suggestions : wb.suggest_targets(input)
+        };
+
+        // both language keys and compiler ids are lowercase on Wandbox
+        let target = self.target.to_lowercase();
+        let (lang, compiler) = if wb.is_valid_language(&target) {
+            let comp = match wb.get_default_compiler(&target) {
+                Some(def) => def,
+                None => return Err(BuildError::NoDefaultCompiler { language : self.target.clone() })
+            };
+            (target, comp)
+        }
+        else if wb.is_valid_compiler_str(&target) {
+            let lang = match wb.get_compiler_language_str(&target) {
+                Some(lang) => lang,
+                None => return Err(unknown(wb, &self.target))
+            };
+            (lang, target)
+        }
+        else if let Some(lang) = wb.resolve_language(&self.target) {
+            let comp = match wb.get_default_compiler(&lang) {
+                Some(def) => def,
+                None => return Err(BuildError::NoDefaultCompiler { language : lang })
+            };
+            (lang, comp)
+        }
+        else {
+            let comp = wb.resolve_prefix(&self.target)?;
+            let lang = match wb.get_compiler_language_str(&comp) {
+                Some(lang) => lang,
+                None => return Err(unknown(wb, &self.target))
+            };
+            (lang, comp)
+        };
+
+        let warnings = match wb.get_compiler(&compiler) {
+            Some(comp) => {
+                if self.switches.is_empty() && !self.no_default_switches {
+                    self.switches = comp.default_switches().into_iter().map(|s| s.to_string()).collect();
+                }
+                self.apply_flag_helpers(&comp.name, &lang, &comp.switches)?;
+                let mut warnings = std::mem::take(&mut self.warnings);
+                warnings.extend(self.validate(&comp)?);
+                warnings
+            },
+            None => return Err(unknown(wb, &self.target))
+        };
+
+        Ok(Compilation {
+            endpoint : wb.endpoint.clone(),
+            transport : wb.transport.clone(),
+            rate_limit_retry : wb.rate_limit_retry,
+            throttle : wb.throttle.clone(),
+            breaker : wb.breaker.clone(),
+            coalescer : wb.coalescer.clone(),
+            results : wb.results.clone(),
+            hooks : wb.hooks.clone(),
+            lang,
+            request : self.into_request(compiler),
+            warnings
+        })
+    }
+
+    /// The checks that need no cache, shared by `build` & `build_unchecked`
+    fn check_local(&self, limits : RequestLimits) -> Result<(), BuildError> {
+        if self.code.trim().is_empty() {
+            return Err(BuildError::EmptyCode);
+        }
+        let size = code_bytes(&self.code, &self.codes);
+        if size > limits.max_code_bytes {
+            return Err(BuildError::TooLarge { field : String::from("code"), size, limit : limits.max_code_bytes });
+        }
+        if self.stdin.len() > limits.max_stdin_bytes {
+            return Err(BuildError::TooLarge { field : String::from("stdin"), size : self.stdin.len(), limit : limits.max_stdin_bytes });
+        }
+        Ok(())
+    }
+
+    /// Turns `warnings_as_errors`, `optimization` & `cpp_standard` into switches & raw options
+    /// for the compiler, `cpp_standard` only applies to C++ compilers
+    fn apply_flag_helpers(&mut self, compiler : &str, lang : &str, switches : &[Switch]) -> Result<(), BuildError> {
+        let unsupported = |flag : &str| Err(BuildError::FlagUnsupported { compiler : compiler.to_string(), flag : flag.to_string() });
+        if !matches!(version::family(compiler), "gcc" | "clang") {
+            let requested = [
+                ("warnings_as_errors", self.warnings_as_errors),
+                ("optimization", self.optimization.is_some()),
+                ("cpp_standard", self.standard.is_some())
+            ];
+            return match requested.iter().find(|(_helper, set)| *set) {
+                Some((helper, _set)) => unsupported(helper),
+                None => Ok(())
+            };
+        }
+        // gcc-*-c & clang-*-c compilers would take a C++ standard as a raw `-std=` & fail on it
+        if self.standard.is_some() && !lang.eq_ignore_ascii_case("c++") {
+            return unsupported("cpp_standard");
+        }
+
+        if self.warnings_as_errors {
+            self.options.push(String::from("-Werror"));
+        }
+        if let Some(level) = self.optimization {
+            self.options.push(level.flag().to_string());
+        }
+        if let Some(standard) = &self.standard {
+            let group = switches.iter().find_map(|s| match s {
+                Switch::Select { options, .. } if options.iter().any(|o| &o.name == standard) => Some(options),
+                _ => None
+            });
+            match group {
+                Some(options) => {
+                    self.switches.retain(|s| !options.iter().any(|o| &o.name == s));
+                    self.switches.push(standard.clone());
+                },
+                None => self.options.push(format!("-std={}", standard))
+            }
+        }
+        Ok(())
+    }
+
+    fn into_request(self, compiler : String) -> CompilationRequest {
+        CompilationRequest {
+            compiler,
+            code : self.code,
+            codes : self.codes,
+            stdin : self.stdin,
+            compiler_options_raw : self.options.join("\n"),
+            runtime_options_raw : self.runtime_options.join("\n"),
+            switches : self.switches.join(","),
+            save : self.save
+        }
+    }
+
+    /// Builds a copy of the request & returns what `dispatch` would send, without contacting
+    /// Wandbox
+    ///
+    /// Fails with the same errors as `build`.
+    ///
+    /// # Arguments
+    /// * `wb` - An instance of the Wandbox cache to resolve the compilation target
+    pub fn dry_run(&self, wb : &Wandbox) -> WandboxResult<DryRun> {
+        Ok(self.clone().build(wb)?.dry_run())
+    }
+
+    /// Saves every setting of the builder as versioned JSON, i.e to queue the request for later
+    ///
+    /// Unlike `Compilation::to_json`, which is the body Wandbox receives, this keeps the target,
+    /// options & switches as they were given. Restore it with `from_json`.
+    pub fn to_json(&self) -> String {
+        let saved = SavedBuilder {
+            version : BUILDER_FORMAT_VERSION,
+            builder : self
+        };
+        serde_json::to_string(&saved).expect("CompilationBuilder always serializes")
+    }
+
+    /// Restores a builder saved with `to_json`
+    ///
+    /// Builders saved by earlier versions of the crate load with the settings they didn't have
+    /// left at their defaults. Builders saved in a newer format are rejected with
+    /// `WandboxError::UnsupportedBuilderVersion`.
+    ///
+    /// # Arguments
+    /// * `json` - The builder, as returned by `to_json`
+    pub fn from_json(json : &str) -> WandboxResult<CompilationBuilder> {
+        // check the version before committing to the layout
+        let version : serde_json::Value = serde_json::from_str(json)?;
+        let version = version.get("version").and_then(|v| v.as_u64());
+        match version {
+            Some(v) if (1..=BUILDER_FORMAT_VERSION as u64).contains(&v) => {},
+            found => return Err(WandboxError::UnsupportedBuilderVersion { found, expected : BUILDER_FORMAT_VERSION })
+        }
+
+        let saved : SavedBuilder<CompilationBuilder> = serde_json::from_str(json)?;
+        Ok(saved.builder)
+    }
+
+    /// Approximates the command line the compiler will be run with, i.e to debug options
+    ///
+    /// The compiler's display command is adjusted for the switches the request would be sent
+    /// with, defaults included, and the raw compiler options are appended with arguments holding
+    /// spaces shell-quoted. This is best-effort, Wandbox may differ in details. Fails with the same
+    /// errors as `build`.
+    ///
+    /// # Arguments
+    /// * `wb` - An instance of the Wandbox cache to resolve the compilation target
+    /// # Example
+    /// ```edition2018
+    ///use wandbox::{CompilationBuilder, Wandbox};
+    ///
+    ///let wbox = Wandbox::from_embedded_snapshot(None, None).unwrap();
+    ///let mut builder = CompilationBuilder::new();
+    ///builder.target("gcc-head").code("int main() {}").switches(vec!["c++17"]).options(vec!["-O2"]);
+    ///assert_eq!(builder.preview_command(&wbox).unwrap(), "g++ prog.cc -std=c++17 -O2");
+    /// ```
+    pub fn preview_command(&self, wb : &Wandbox) -> WandboxResult<String> {
+        let compilation = self.clone().build(wb)?;
+        let request = compilation.request();
+        let comp = match wb.get_compiler(&request.compiler) {
+            Some(comp) => comp,
+            None => return Err(BuildError::UnknownTarget { input : request.compiler.clone(), suggestions : Vec::new() }.into())
+        };
+
+        let switches : Vec<&str> = request.switches.split(',').filter(|s| !s.is_empty()).collect();
+        let options : Vec<&str> = request.compiler_options_raw.split('\n').filter(|o| !o.is_empty()).collect();
+        Ok(preview::command(&comp, &switches, &options))
+    }
+
+    /// Checks the options & switches against what the resolved compiler supports, returning a
+    /// warning for each set of options dropped in lenient mode & each select group given several
+    /// options
+    fn validate(&mut self, comp : &Compiler) -> Result<Vec<String>, BuildError> {
+        let mut warnings = Vec::new();
+        if !self.options.is_empty() && !comp.compiler_option_raw {
+            if !self.lenient {
+                return Err(BuildError::RawOptionsUnsupported { compiler : comp.name.clone() });
+            }
+            warnings.push(format!("{} does not accept raw compiler options, dropped: {}", comp.name, self.options.join(" ")));
+            self.options.clear();
+        }
+        if !self.runtime_options.is_empty() && !comp.runtime_option_raw {
+            if !self.lenient {
+                return Err(BuildError::RuntimeOptionsUnsupported { compiler : comp.name.clone() });
+            }
+            warnings.push(format!("{} does not accept raw runtime options, dropped: {}", comp.name, self.runtime_options.join(" ")));
+            self.runtime_options.clear();
+        }
+
+        // switches of a type we don't understand could carry any name
+        let unknown_types = comp.switches.contains(&Switch::Unknown);
+        for switch in &self.switches {
+            if !unknown_types && !comp.switches.iter().any(|s| s.names().contains(&switch.as_str())) {
+                return Err(BuildError::UnknownSwitch {
+                    compiler : comp.name.clone(),
+                    switch : switch.clone(),
+                    valid : valid_switches(comp, switch)
+                });
+            }
+        }
+
+        // only one option of a select group applies, the last one given wins
+        for group in comp.switches.iter().filter(|s| matches!(s, Switch::Select { .. })) {
+            let names = group.names();
+            let chosen : Vec<&str> = self.switches.iter().map(|s| s.as_str()).filter(|s| names.contains(s)).collect();
+            let last = match chosen.last() {
+                Some(last) => last.to_string(),
+                None => continue
+            };
+            if chosen.iter().any(|s| *s != last) {
+                warnings.push(format!("{} takes only one of {}, using {}", comp.name, chosen.join(", "), last));
+            }
+
+            let keep = self.switches.iter().rposition(|s| names.contains(&s.as_str()));
+            let mut i = 0;
+            self.switches.retain(|s| {
+                let retained = !names.contains(&s.as_str()) || Some(i) == keep;
+                i += 1;
+                retained
+            });
+        }
+        Ok(warnings)
+    }
+}
+
+/// The switches to offer in place of an unknown one, the options of the select group closest to
+/// it or else every switch of the compiler
+fn valid_switches(comp : &Compiler, switch : &str) -> Vec<String> {
+    let names : Vec<&str> = comp.switches.iter().flat_map(|s| s.names()).collect();
+    let group = suggest::closest(switch, names.iter().copied()).first()
+        .and_then(|closest| comp.switches.iter().find(|s| matches!(s, Switch::Select { .. }) && s.names().contains(&closest.as_str())));
+
+    match group {
+        Some(group) => group.names().into_iter().map(|n| n.to_string()).collect(),
+        None => names.into_iter().map(|n| n.to_string()).collect()
+    }
+}
+
+impl fmt::Debug for CompilationBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let files : Vec<(&str, CodeSummary)> = self.codes.iter()
+            .map(|c| (c.file.as_str(), CodeSummary(&c.code)))
+            .collect();
+
+        f.debug_struct("CompilationBuilder")
+            .field("target", &self.target)
+            .field("code", &CodeSummary(&self.code))
+            .field("codes", &files)
+            .field("stdin", &CodeSummary(&self.stdin))
+            .field("options", &self.options)
+            .field("runtime_options", &self.runtime_options)
+            .field("switches", &self.switches)
+            .field("save", &self.save)
+            .field("lenient", &self.lenient)
+            .field("auto_detect", &self.auto_detect)
+            .field("no_default_switches", &self.no_default_switches)
+            .field("warnings_as_errors", &self.warnings_as_errors)
+            .field("optimization", &self.optimization)
+            .field("standard", &self.standard)
+            .field("language", &self.language)
+            .field("warnings", &self.warnings)
+            .finish()
+    }
+}
+
+/// The version of the `CompilationBuilder::to_json` format, bumped whenever the layout changes
+pub const BUILDER_FORMAT_VERSION : u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SavedBuilder<B> {
+    version : u32,
+    builder : B,
+}
+
+/// Shows multi-line text as its first line along with its size, i.e "#include<iostream>" (5 lines, 52 bytes)
+struct CodeSummary<'a>(&'a str);
+impl fmt::Debug for CodeSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.split_once('\n') {
+            Some((first, _rest)) => write!(f, "{:?} ({} lines, {} bytes)", first, self.0.lines().count(), self.0.len()),
+            None => write!(f, "{:?}", self.0)
+        }
+    }
+}
+
+/// A request finalized by `CompilationBuilder::build`, ready to be dispatched to Wandbox
+///
+/// Only a built request can be dispatched
+/// ```compile_fail
+///use wandbox::CompilationBuilder;
+///
+///let mut builder = CompilationBuilder::new();
+///builder.target("c++");
+///let result = builder.dispatch();
+/// ```
+#[derive(Clone)]
+pub struct Compilation {
+    endpoint : String,
+    transport : Arc<dyn WandboxTransport>,
+    rate_limit_retry : Option<Duration>,
+    throttle : Arc<Throttle>,
+    breaker : Arc<Breaker>,
+    coalescer : Arc<Coalescer>,
+    results : Arc<ResultCache>,
+    hooks : Arc<RwLock<Hooks>>,
+    lang : String,
+    request : CompilationRequest,
+    warnings : Vec<String>
+}
+impl Compilation {
+    /// The language the target resolved to, i.e "c++"
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+
+    /// The compiler the target resolved to, i.e "gcc-head"
+    pub fn compiler(&self) -> &str {
+        &self.request.compiler
+    }
+
+    /// The body that will be sent to Wandbox
+    pub fn request(&self) -> &CompilationRequest {
+        &self.request
+    }
+
+    /// The JSON body that will be sent to Wandbox, useful for debugging rejected requests
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.request).expect("CompilationRequest always serializes")
+    }
+
+    /// Problems `CompilationBuilder::build` worked around in lenient mode, i.e dropped options
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Returns what `dispatch` would send, without contacting Wandbox
+    pub fn dry_run(&self) -> DryRun {
+        DryRun {
+            url : format!("{}/api/compile.json", self.endpoint),
+            body : serde_json::to_string_pretty(&self.request).expect("CompilationRequest always serializes")
+        }
+    }
+
+    /// Dispatches the request to Wandbox
+    pub async fn dispatch(&self) -> WandboxResult<CompilationResult> {
+        self.dispatch_detailed().await.map(|(result, _info)| result)
+    }
+
+    /// Dispatches the request to Wandbox like `dispatch`, along with how long it took & what
+    /// Wandbox replied with
+    pub async fn dispatch_detailed(&self) -> WandboxResult<(CompilationResult, DispatchInfo)> {
+        self.results.run(&self.request, self.coalescer.run(&self.request, async {
+            let ticket = self.breaker.admit()?;
+            let _permit = self.throttle.acquire().await;
+            let result = self.send(self.transport.as_ref(), &self.endpoint, self.rate_limit_retry, &self.hooks).await;
+            ticket.record(&result);
+            result
+        })).await
+    }
+
+    /// Dispatches the request to Wandbox through the connection pool of `wb`
+    ///
+    /// # Arguments
+    /// * `wb` - The Wandbox instance whose transport and endpoint should be used
+    pub async fn dispatch_with(&self, wb : &Wandbox) -> WandboxResult<CompilationResult> {
+        let result = wb.results.run(&self.request, wb.coalescer.run(&self.request, async {
+            let ticket = wb.breaker.admit()?;
+            let _permit = wb.throttle.acquire().await;
+            let result = self.send(wb.transport.as_ref(), &wb.endpoint, wb.rate_limit_retry, &wb.hooks).await;
+            ticket.record(&result);
+            result
+        })).await;
+        result.map(|(result, _info)| result)
+    }
+
+    /// Dispatches the request to Wandbox, with a handle to cancel it while it's in flight
+    ///
+    /// Cancelling drops the request & resolves the future with `WandboxError::Cancelled`,
+    /// cancelling after the future has resolved does nothing.
+    pub fn dispatch_cancellable(&self) -> (CancelHandle, impl Future<Output = WandboxResult<CompilationResult>>) {
+        let (sender, mut receiver) = watch::channel(false);
+        let compilation = self.clone();
+        let future = async move {
+            tokio::select! {
+                result = compilation.dispatch() => result,
+                // a dropped handle can't cancel anymore, so only a sent cancellation counts
+                Ok(_) = receiver.wait_for(|cancelled| *cancelled) => Err(WandboxError::Cancelled)
+            }
+        };
+        (CancelHandle { sender : Arc::new(sender) }, future)
+    }
+
+    async fn send(&self, transport : &dyn WandboxTransport, endpoint : &str, rate_limit_retry : Option<Duration>,
+                  hooks : &RwLock<Hooks>) -> WandboxResult<(CompilationResult, DispatchInfo)> {
+        let span = trace::span!("wandbox::dispatch", compiler = self.request.compiler.as_str(),
+                                code_len = code_bytes(&self.request.code, &self.request.codes),
+                                status = tracing::field::Empty, retries = tracing::field::Empty);
+        span.run_async(async {
+            let start = Instant::now();
+            let (response, retries) = match (self.post(transport, endpoint, hooks, &span, start, 0).await, rate_limit_retry) {
+                (Err(WandboxError::RateLimited { retry_after }), Some(max_wait)) if retry_after.unwrap_or(max_wait) <= max_wait => {
+                    pause(retry_after.unwrap_or(max_wait)).await;
+                    (self.post(transport, endpoint, hooks, &span, start, 1).await?, 1)
+                }
+                (result, _) => (result?, 0)
+            };
+            span.record("retries", retries);
+
+            let info = DispatchInfo::of(&response, start, retries);
+            Ok((read_json(response)?, info))
+        }).await
+    }
+
+    async fn post(&self, transport : &dyn WandboxTransport, endpoint : &str, hooks : &RwLock<Hooks>,
+                  span : &trace::Span, start : Instant, retries : u32) -> WandboxResult<TransportResponse> {
+        // cloned out of the lock so hooks may replace themselves
+        let hooks = read_lock(hooks).clone();
+        let url = format!("{}/api/compile.json", endpoint);
+        let body = self.to_json();
+        if let Some(logger) = &hooks.wire_logger {
+            logger(WireEvent::Request { url : url.clone(), body : hooks.clip(&body) });
+        }
+
+        let response = transport.post_json(&url, body).await?;
+        span.record("status", response.status);
+        hooks.report(&response, start, retries);
+        if !response.is_success() {
+            return Err(read_error(response));
+        }
+        Ok(response)
+    }
+
+    /// Dispatches the request to Wandbox, streaming events as the compilation progresses
+    ///
+    /// The stream ends after Wandbox reports the compilation has finished. Malformed events are
+    /// yielded as errors. Like `dispatch`, the request waits for the concurrency limit, goes
+    /// through the circuit breaker & is retried once when rate limited. The stream counts as in
+    /// flight until it ends or is dropped, the hooks hear of the reply then.
+    pub fn dispatch_stream(&self) -> impl Stream<Item = WandboxResult<CompileEvent>> {
+        let compilation = self.clone();
+        stream::events(Box::pin(async move { compilation.open_stream().await }))
+    }
+
+    /// Sends the request to the streaming endpoint, handing back its body as it arrives
+    async fn open_stream(&self) -> WandboxResult<ChunkStream> {
+        let ticket = self.breaker.admit()?;
+        let permit = self.throttle.acquire().await;
+        // cloned out of the lock so hooks may replace themselves
+        let hooks = read_lock(&self.hooks).clone();
+
+        let start = Instant::now();
+        let (response, retries) = match (self.post_stream(&hooks, start, 0).await, self.rate_limit_retry) {
+            (Err(WandboxError::RateLimited { retry_after }), Some(max_wait)) if retry_after.unwrap_or(max_wait) <= max_wait => {
+                pause(retry_after.unwrap_or(max_wait)).await;
+                (self.post_stream(&hooks, start, 1).await, 1)
+            }
+            (result, _) => (result, 0)
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                let failed = Err(e);
+                ticket.record(&failed);
+                return failed;
+            }
+        };
+
+        let watch = StreamWatch {
+            ticket : Some(ticket),
+            _permit : permit,
+            hooks,
+            status : response.status,
+            headers : response.headers,
+            start,
+            retries,
+            logged : Vec::new(),
+            length : 0
+        };
+        Ok(watch.watch(response.chunks))
+    }
+
+    async fn post_stream(&self, hooks : &Hooks, start : Instant, retries : u32) -> WandboxResult<StreamResponse> {
+        let url = format!("{}/api/compile.ndjson", self.endpoint);
+        let body = self.to_json();
+        if let Some(logger) = &hooks.wire_logger {
+            logger(WireEvent::Request { url : url.clone(), body : hooks.clip(&body) });
+        }
+
+        let response = self.transport.post_stream(&url, body).await?;
+        if response.is_success() {
+            return Ok(response);
+        }
+        // unsuccessful replies are read in full & reported as they would be by `dispatch`
+        let response = response.read_all().await?;
+        hooks.report(&response, start, retries);
+        Err(read_error(response))
+    }
+}
+
+/// A streamed reply being read, reporting it to the circuit breaker & the hooks once it ends or
+/// is dropped, & holding its place under the concurrency limit until then
+struct StreamWatch {
+    ticket : Option<Ticket>,
+    _permit : Permit,
+    hooks : Hooks,
+    status : u16,
+    headers : Vec<(String, String)>,
+    start : Instant,
+    retries : u32,
+    // the start of the body, kept for the wire logger
+    logged : Vec<u8>,
+    length : usize,
+}
+impl StreamWatch {
+    fn watch(self, chunks : ChunkStream) -> ChunkStream {
+        Box::pin(stream_util::unfold((chunks, self), |(mut chunks, mut watch)| async move {
+            let chunk = chunks.next().await?;
+            match &chunk {
+                Ok(bytes) => watch.read(bytes),
+                Err(_e) => if let Some(ticket) = watch.ticket.take() {
+                    ticket.record(&chunk);
+                }
+            }
+            Some((chunk, (chunks, watch)))
+        }))
+    }
+
+    fn read(&mut self, bytes : &[u8]) {
+        self.length += bytes.len();
+        if self.hooks.wire_logger.is_some() {
+            // a few bytes past the limit so a character cut in two can still be clipped whole
+            let room = (self.hooks.wire_log_limit + 4).saturating_sub(self.logged.len());
+            self.logged.extend_from_slice(&bytes[..room.min(bytes.len())]);
+        }
+    }
+}
+impl Drop for StreamWatch {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            ticket.record(&Ok::<(), WandboxError>(()));
+        }
+        if let Some(logger) = &self.hooks.wire_logger {
+            logger(WireEvent::Response { status : self.status, body : self.hooks.clip(&String::from_utf8_lossy(&self.logged)) });
+        }
+        if let Some(hook) = &self.hooks.on_response {
+            hook(&DispatchInfo::new(self.status, &self.headers, self.length, self.start, self.retries));
+        }
+    }
+}
+
+/// Details of a dispatch returned by `Compilation::dispatch_detailed`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DispatchInfo {
+    /// How long it took from sending the request to reading the reply, including any retry but
+    /// not the wait for the concurrency limit
+    pub elapsed : Duration,
+    /// The HTTP status code of the final reply
+    pub status : u16,
+    /// The length of the final reply's body in bytes
+    pub content_length : usize,
+    /// How many times the request was retried after being rate limited
+    pub retries : u32,
+    /// The headers of the final reply as (name, value) pairs in the order they were received, up
+    /// to `MAX_CAPTURED_HEADER_BYTES` of them
+    pub headers : Vec<(String, String)>,
+    /// Whether the result came from `Wandbox::enable_result_cache` rather than Wandbox, the
+    /// reply details are then those of the cached reply with no time elapsed
+    pub cache_hit : bool,
+}
+impl DispatchInfo {
+    /// Returns the value of a header of the reply, names are compared case insensitively
+    ///
+    /// # Arguments
+    /// * `name` - The header name i.e "x-ratelimit-remaining"
+    pub fn header(&self, name : &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _value)| n.eq_ignore_ascii_case(name)).map(|(_name, value)| value.as_str())
+    }
+
+    fn of(response : &TransportResponse, start : Instant, retries : u32) -> DispatchInfo {
+        DispatchInfo::new(response.status, &response.headers, response.body.len(), start, retries)
+    }
+
+    fn new(status : u16, headers : &[(String, String)], content_length : usize, start : Instant, retries : u32) -> DispatchInfo {
+        // headers past the budget are dropped whole, so a huge cookie can't push out the rest
+        let mut budget = MAX_CAPTURED_HEADER_BYTES;
+        let headers = headers.iter()
+            .filter(|(name, value)| match budget.checked_sub(name.len() + value.len()) {
+                Some(left) => {
+                    budget = left;
+                    true
+                },
+                None => false
+            })
+            .cloned()
+            .collect();
+
+        DispatchInfo {
+            elapsed : start.elapsed(),
+            status,
+            content_length,
+            retries,
+            headers,
+            cache_hit : false
+        }
+    }
+}
+
+/// A compilation request or reply as sent over the wire, passed to the logger set with
+/// `Wandbox::set_wire_logger`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WireEvent {
+    /// A compilation about to be sent, `body` is its JSON
+    Request { url : String, body : String },
+    /// Wandbox's reply to it, whether successful or not
+    Response { status : u16, body : String },
+}
+
+/// A callback set with `Wandbox::on_response`
+type ResponseHook = Arc<dyn Fn(&DispatchInfo) + Send + Sync>;
+/// A callback set with `Wandbox::set_wire_logger`
+type WireLogger = Arc<dyn Fn(WireEvent) + Send + Sync>;
+
+/// The callbacks observing compilations, shared by a `Wandbox`, its clones & the compilations
+/// built from them
+#[derive(Clone)]
+struct Hooks {
+    on_response : Option<ResponseHook>,
+    wire_logger : Option<WireLogger>,
+    wire_log_limit : usize,
+}
+impl Default for Hooks {
+    fn default() -> Hooks {
+        Hooks {
+            on_response : None,
+            wire_logger : None,
+            wire_log_limit : DEFAULT_WIRE_LOG_BYTES
+        }
+    }
+}
+impl Hooks {
+    /// Passes a reply to the wire logger & the response hook
+    fn report(&self, response : &TransportResponse, start : Instant, retries : u32) {
+        if let Some(logger) = &self.wire_logger {
+            logger(WireEvent::Response { status : response.status, body : self.clip(&response.body) });
+        }
+        if let Some(hook) = &self.on_response {
+            hook(&DispatchInfo::of(response, start, retries));
+        }
+    }
+
+    /// A body as the wire logger gets it, cut short to `wire_log_limit`
+    fn clip(&self, body : &str) -> String {
+        let mut body = body.to_string();
+        sanitize::truncate(&mut body, self.wire_log_limit);
+        body
+    }
+}
+
+/// Cancels a compilation started with `Compilation::dispatch_cancellable`
+#[derive(Clone, Debug)]
+pub struct CancelHandle {
+    sender : Arc<watch::Sender<bool>>,
+}
+impl CancelHandle {
+    /// Aborts the compilation if it's still in flight
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+}
+
+/// The request `Compilation::dispatch` would make, as returned by a dry run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DryRun {
+    /// The url the request is POSTed to
+    pub url : String,
+    /// The pretty-printed JSON body
+    pub body : String,
+}
+
+/// The body of a compile request, exactly as Wandbox accepts it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CompilationRequest {
+    pub compiler : String,
+    pub code : String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub codes : Vec<CodeFile>,
+    pub stdin : String,
+    #[serde(rename = "compiler-option-raw")]
+    pub compiler_options_raw : String,
+    #[serde(rename = "runtime-option-raw")]
+    pub runtime_options_raw : String,
+    /// Comma separated switch names
+    #[serde(rename = "options")]
+    pub switches : String,
+    pub save : bool
+}
+
+/// An additional source file sent along with the primary code of a compilation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeFile {
+    pub file : String,
+    pub code : String,
+}
+
+/// Appended to output cut short by `CompilationResult::full_output_limited`
+pub const TRUNCATION_MARKER : &str = "… (truncated)";
+
+/// Information regarding the result of a compilation request.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompilationResult {
+    #[serde(default)]
+    pub status : String,
+    #[serde(default)]
+    pub signal : String,
+    #[serde(rename = "compiler_output", default)]
+    pub compiler_stdout : String,
+    #[serde(rename = "compiler_error", default)]
+    pub compiler_stderr : String,
+    #[serde(rename = "compiler_message", default)]
+    pub compiler_all : String,
+    #[serde(rename = "program_output", default)]
+    pub program_stdout : String,
+    #[serde(rename = "program_error", default)]
+    pub program_stderr : String,
+    #[serde(rename = "program_message", default)]
+    pub program_all : String,
+    /// The permlink identifier, only present when the request was saved
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub permlink : Option<String>,
+    /// The url of the saved compilation, only present when the request was saved
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub url : Option<String>,
+    #[serde(flatten)]
+    extra : HashMap<String, serde_json::Value>,
+}
+
+/// Treats the empty strings Wandbox sends for absent values as missing
+fn empty_as_none<'de, D : serde::Deserializer<'de>>(deserializer : D) -> Result<Option<String>, D::Error> {
+    let value : Option<String> = Option::deserialize(deserializer)?;
+    Ok(value.filter(|v| !v.is_empty()))
+}
+
+/// A default code snippet provided by Wandbox
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pub name : String,
+    pub code : String,
+}
+
+#[derive(Deserialize)]
+struct TemplateResponse {
+    code : String,
+}
+
+/// A saved compilation retrieved through its permlink
+#[derive(Default, Debug, Deserialize)]
+pub struct PermlinkResult {
+    #[serde(rename = "parameter", default)]
+    pub request : PermlinkRequest,
+    #[serde(default)]
+    pub result : CompilationResult,
+}
+
+/// The parameters a saved compilation was originally requested with
+#[derive(Default, Debug, Deserialize)]
+pub struct PermlinkRequest {
+    #[serde(default)]
+    pub compiler : String,
+    #[serde(default)]
+    pub code : String,
+    #[serde(default)]
+    pub codes : Vec<CodeFile>,
+    #[serde(rename = "options", default)]
+    pub switches : String,
+    #[serde(rename = "compiler-option-raw", default)]
+    pub compiler_options_raw : String,
+    #[serde(rename = "runtime-option-raw", default)]
+    pub runtime_options_raw : String,
+    #[serde(default)]
+    pub stdin : String,
+}
+
+impl CompilationResult {
+    /// Whether the program ran to completion with an exit code of 0
+    pub fn success(&self) -> bool {
+        self.exit_code() == Some(0) && !self.was_signaled()
+    }
+
+    /// The exit code of the program, if Wandbox reported one
+    pub fn exit_code(&self) -> Option<i32> {
+        self.status.trim().parse().ok()
+    }
+
+    /// Whether the program was terminated by a signal
+    pub fn was_signaled(&self) -> bool {
+        !self.signal.is_empty()
+    }
+
+    /// The signal that terminated the program, if any
+    pub fn signal_kind(&self) -> Option<SignalKind> {
+        if self.signal.is_empty() {
+            return None;
+        }
+
+        let kind = match self.signal.trim() {
+            "Killed" => SignalKind::Killed,
+            "Segmentation fault" => SignalKind::Segfault,
+            "Aborted" => SignalKind::Aborted,
+            "Floating point exception" => SignalKind::FloatingPointException,
+            other => SignalKind::Other(other.to_string())
+        };
+        Some(kind)
+    }
+
+    /// Fields Wandbox sent that this crate does not know about
+    pub fn raw_extra(&self) -> &HashMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    /// How the compilation ended, derived from which of `status` & `signal` Wandbox filled in
+    ///
+    /// A status is `Exited` whatever the compiler wrote & whether the program printed anything,
+    /// so warnings, even ones mentioning "error", don't change the outcome of a program that ran.
+    /// A response with neither a status nor a signal is a `CompileError`, since nothing ran.
+    pub fn outcome(&self) -> Outcome {
+        if self.was_signaled() {
+            return Outcome::Signaled(self.signal.clone());
+        }
+
+        match self.exit_code() {
+            Some(code) => Outcome::Exited(code),
+            None => Outcome::CompileError
+        }
+    }
+
+    /// The url the saved compilation can be shared with, if the request was saved
+    pub fn share_url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// The compiler's output followed by the program's, each with stdout & stderr merged
+    pub fn full_output(&self) -> String {
+        format!("{}{}", self.compiler_all, self.program_all)
+    }
+
+    /// The same as `full_output`, cut short to at most `max_bytes` on a character boundary
+    ///
+    /// When the output is cut short it ends with `TRUNCATION_MARKER`, which counts towards
+    /// `max_bytes`. A limit smaller than the marker yields only the marker.
+    ///
+    /// # Arguments
+    /// * `max_bytes` - The maximum length of the output in bytes
+    pub fn full_output_limited(&self, max_bytes : usize) -> String {
+        let mut output = self.full_output();
+        sanitize::truncate(&mut output, max_bytes);
+        output
+    }
+
+    /// Cleaned up copies of the compiler & program output, i.e to relay to plain text clients
+    ///
+    /// Nothing is changed unless asked for in `options`. Escape characters that don't start a
+    /// complete ANSI sequence are kept, as the program may have printed them on purpose.
+    ///
+    /// # Arguments
+    /// * `options` - What to clean up
+    pub fn sanitized(&self, options : Sanitize) -> SanitizedResult {
+        SanitizedResult::new(self, options)
+    }
+
+    /// Whether compilation finished without the compiler reporting errors
+    ///
+    /// This is decided from which fields Wandbox filled in, never from the text of the compiler's
+    /// output, as warnings are written to stderr too. Wandbox only sends a status or a signal once
+    /// the program has run, whatever its exit code, so a reply with neither didn't compile.
+    pub fn compiled(&self) -> bool {
+        self.exit_code().is_some() || self.was_signaled()
+    }
+}
+
+/// How a compilation ended, as returned by `CompilationResult::outcome`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program ran & exited with this code
+    Exited(i32),
+    /// The program was terminated by a signal, see `CompilationResult::signal_kind`
+    Signaled(String),
+    /// The code failed to compile, so no program ran
+    CompileError,
+}
+
+/// A signal Wandbox reports for a terminated program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignalKind {
+    /// SIGKILL, which Wandbox sends to programs that exceed the time or memory limit
+    Killed,
+    /// SIGSEGV
+    Segfault,
+    /// SIGABRT, i.e a failed assertion
+    Aborted,
+    /// SIGFPE, i.e an integer division by zero
+    FloatingPointException,
+    /// A signal this crate does not know about yet, as Wandbox describes it
+    Other(String),
+}
+
+impl fmt::Debug for CompilationResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{} {}] {}: {}", self.status, self.signal, self.compiler_all, self.program_all)
+    }
+}
+
+
+/// A representation of a language with a list of it's compilers
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Language {
+    /// Shared with the `Compiler::language` of each of its compilers
+    #[serde(with = "crate::intern")]
+    pub name : Arc<str>,
+    /// Shared between every copy of the language, cloning it doesn't copy the compilers
+    pub compilers : Arc<[Compiler]>
+}
+
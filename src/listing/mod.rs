@@ -0,0 +1,62 @@
+use crate::{Compiler, ListStyle};
+
+/// Formats compilers for a chat message, showing at most `max_len` of them
+///
+/// Longer lists keep compilers from both ends, the middle is replaced by a "… and N more" line.
+/// `max_len` is never exceeded, so a `max_len` of 1 keeps only the first compiler & 0 leaves
+/// just the "… and N more" line.
+pub fn format(compilers : &[Compiler], style : ListStyle, max_len : usize) -> String {
+    let rows : Vec<(&str, &str)> = compilers.iter()
+        .map(|c| (c.display_name.as_str(), c.version.as_str()))
+        .collect();
+
+    let (head, elided, tail) = if rows.len() > max_len {
+        let tail = max_len / 2;
+        (&rows[..max_len - tail], rows.len() - max_len, &rows[rows.len() - tail..])
+    } else {
+        (&rows[..], 0, &rows[rows.len()..])
+    };
+    let more = format!("… and {} more", elided);
+
+    match style {
+        ListStyle::Plain => {
+            let line = |(name, version) : &(&str, &str)| {
+                if version.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{} — {}", name, version)
+                }
+            };
+
+            let mut lines : Vec<String> = head.iter().map(line).collect();
+            if elided > 0 {
+                lines.push(more);
+            }
+            lines.extend(tail.iter().map(line));
+            lines.join("\n")
+        },
+        ListStyle::Markdown => {
+            let shown = || head.iter().chain(tail.iter());
+            let names = shown().map(|(name, _v)| name.chars().count()).fold("Compiler".len(), usize::max);
+            let versions = shown().map(|(_n, version)| version.chars().count()).fold("Version".len(), usize::max);
+            let row = |name : &str, version : &str| {
+                format!("| {}{} | {}{} |",
+                        name, " ".repeat(names - name.chars().count()),
+                        version, " ".repeat(versions - version.chars().count()))
+            };
+
+            let mut lines = vec![
+                String::from("```"),
+                row("Compiler", "Version"),
+                format!("|{}|{}|", "-".repeat(names + 2), "-".repeat(versions + 2))
+            ];
+            lines.extend(head.iter().map(|(name, version)| row(name, version)));
+            if elided > 0 {
+                lines.push(more);
+            }
+            lines.extend(tail.iter().map(|(name, version)| row(name, version)));
+            lines.push(String::from("```"));
+            lines.join("\n")
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::coalesce::Outcome;
+use crate::{read_lock, write_lock, CompilationRequest, WandboxError};
+
+/// Keeps the results of recent compilations to answer identical ones with, see
+/// `Wandbox::enable_result_cache`
+#[derive(Default)]
+pub(crate) struct ResultCache {
+    // `None` until enabled
+    store : RwLock<Option<Store>>,
+}
+
+struct Store {
+    capacity : usize,
+    ttl : Duration,
+    entries : HashMap<String, Cached>,
+    // bumped on every use, the entry used longest ago is evicted first
+    clock : u64,
+}
+
+struct Cached {
+    outcome : Outcome,
+    stored_at : Instant,
+    used : u64,
+}
+
+impl ResultCache {
+    /// Starts caching up to `capacity` results for `ttl`, dropping what was cached before
+    pub fn enable(&self, capacity : usize, ttl : Duration) {
+        *write_lock(&self.store) = Some(Store { capacity, ttl, entries : HashMap::new(), clock : 0 });
+    }
+
+    pub fn clear(&self) {
+        if let Some(store) = &mut *write_lock(&self.store) {
+            store.entries.clear();
+        }
+    }
+
+    /// Answers with a cached result when there is one for the request, and otherwise runs
+    /// `dispatch` & caches its result if it succeeded. Saved compilations are never cached, as
+    /// each gets its own permlink.
+    pub async fn run<F>(&self, request : &CompilationRequest, dispatch : F) -> Result<Outcome, WandboxError>
+        where F : Future<Output = Result<Outcome, WandboxError>> {
+        if request.save || read_lock(&self.store).is_none() {
+            return dispatch.await;
+        }
+
+        // the whole body is the key, so requests differing in any field are kept apart
+        let key = serde_json::to_string(request).expect("CompilationRequest always serializes");
+        if let Some(store) = &mut *write_lock(&self.store) {
+            if let Some(mut outcome) = store.get(&key) {
+                outcome.1.elapsed = Duration::ZERO;
+                outcome.1.retries = 0;
+                outcome.1.cache_hit = true;
+                return Ok(outcome);
+            }
+        }
+
+        let result = dispatch.await;
+        if let (Ok(outcome), Some(store)) = (&result, &mut *write_lock(&self.store)) {
+            store.insert(key, outcome.clone());
+        }
+        result
+    }
+}
+
+impl Store {
+    fn get(&mut self, key : &str) -> Option<Outcome> {
+        let ttl = self.ttl;
+        match self.entries.get(key) {
+            Some(cached) if cached.stored_at.elapsed() >= ttl => {
+                self.entries.remove(key);
+                None
+            },
+            Some(_cached) => {
+                self.clock += 1;
+                let cached = self.entries.get_mut(key)?;
+                cached.used = self.clock;
+                Some(cached.outcome.clone())
+            },
+            None => None
+        }
+    }
+
+    fn insert(&mut self, key : String, outcome : Outcome) {
+        if self.capacity == 0 {
+            return;
+        }
+        let ttl = self.ttl;
+        self.entries.retain(|_key, cached| cached.stored_at.elapsed() < ttl);
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter().min_by_key(|(_key, cached)| cached.used).map(|(key, _cached)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, Cached { outcome, stored_at : Instant::now(), used : self.clock });
+    }
+}
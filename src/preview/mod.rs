@@ -0,0 +1,54 @@
+use crate::{Compiler, Switch};
+
+/// Approximates the command line Wandbox runs for a compiler, with the given switches & raw
+/// options in place of the defaults its display command was written with
+///
+/// The flags of the default switches are taken out of the display command, then the flags of
+/// the selected switches are appended in the order the compiler lists them, followed by the raw
+/// options.
+pub fn command(comp : &Compiler, switches : &[&str], options : &[&str]) -> String {
+    let mut args : Vec<String> = comp.display_compile_command.split_whitespace().map(|a| a.to_string()).collect();
+    for default in comp.default_switches() {
+        remove_sequence(&mut args, &flags(comp, default));
+    }
+
+    for switch in &comp.switches {
+        for name in switch.names() {
+            if switches.contains(&name) {
+                args.extend(flags(comp, name).into_iter().map(|f| f.to_string()));
+            }
+        }
+    }
+    args.extend(options.iter().map(|o| quote(o)));
+    args.join(" ")
+}
+
+/// The flags a switch adds to the command line
+fn flags<'a>(comp : &'a Compiler, name : &str) -> Vec<&'a str> {
+    let display_flags = comp.switches.iter().find_map(|s| match s {
+        Switch::Single { name : n, display_flags, .. } if n == name => Some(display_flags.as_str()),
+        Switch::Select { options, .. } => options.iter().find(|o| o.name == name).map(|o| o.display_flags.as_str()),
+        _ => None
+    });
+    display_flags.unwrap_or_default().split_whitespace().collect()
+}
+
+/// Removes the first run of `args` matching `sequence`
+fn remove_sequence(args : &mut Vec<String>, sequence : &[&str]) {
+    if sequence.is_empty() || sequence.len() > args.len() {
+        return;
+    }
+    let start = (0..=args.len() - sequence.len())
+        .find(|i| args[*i..*i + sequence.len()].iter().zip(sequence).all(|(a, s)| a == s));
+    if let Some(start) = start {
+        args.drain(start..start + sequence.len());
+    }
+}
+
+/// Quotes an argument for a POSIX shell when it holds whitespace, quotes or is empty
+pub fn quote(arg : &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c : char| c.is_whitespace() || c == '\'' || c == '"') {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
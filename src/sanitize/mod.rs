@@ -0,0 +1,122 @@
+use crate::{CompilationResult, TRUNCATION_MARKER};
+
+/// How `CompilationResult::sanitized` cleans up output before it's relayed, i.e to a chat
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sanitize {
+    /// Removes ANSI escape sequences such as colors, leaving any other escape character as is
+    pub strip_ansi : bool,
+    /// Turns `\r\n` line endings into `\n`
+    pub normalize_crlf : bool,
+    /// Cuts each field short to at most this many bytes, ending it with `TRUNCATION_MARKER`
+    pub max_len : Option<usize>,
+}
+
+/// Cleaned up copies of the output of a `CompilationResult`, see `CompilationResult::sanitized`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SanitizedResult {
+    pub compiler_stdout : String,
+    pub compiler_stderr : String,
+    pub compiler_all : String,
+    pub program_stdout : String,
+    pub program_stderr : String,
+    pub program_all : String,
+}
+impl SanitizedResult {
+    pub(crate) fn new(result : &CompilationResult, options : Sanitize) -> SanitizedResult {
+        let clean = |output : &str| {
+            let mut output = if options.strip_ansi {
+                strip_ansi(output)
+            } else {
+                output.to_string()
+            };
+            if options.normalize_crlf {
+                output = output.replace("\r\n", "\n");
+            }
+            if let Some(max_len) = options.max_len {
+                truncate(&mut output, max_len);
+            }
+            output
+        };
+
+        SanitizedResult {
+            compiler_stdout : clean(&result.compiler_stdout),
+            compiler_stderr : clean(&result.compiler_stderr),
+            compiler_all : clean(&result.compiler_all),
+            program_stdout : clean(&result.program_stdout),
+            program_stderr : clean(&result.program_stderr),
+            program_all : clean(&result.program_all)
+        }
+    }
+
+    /// The compiler's output followed by the program's, each with stdout & stderr merged
+    pub fn full_output(&self) -> String {
+        format!("{}{}", self.compiler_all, self.program_all)
+    }
+}
+
+/// Removes CSI sequences i.e colors, and OSC sequences i.e hyperlinks
+///
+/// An escape character that doesn't start a complete sequence is kept, so programs printing one
+/// on purpose aren't mangled.
+pub fn strip_ansi(text : &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('\x1b') {
+        stripped.push_str(&rest[..i]);
+        let sequence = &rest[i..];
+        match escape_len(sequence.as_bytes()) {
+            Some(len) => rest = &sequence[len..],
+            None => {
+                stripped.push('\x1b');
+                rest = &sequence[1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// The length of the escape sequence `sequence` starts with, if it's a complete one
+fn escape_len(sequence : &[u8]) -> Option<usize> {
+    match sequence.get(1)? {
+        // parameters, then intermediates, then a single final byte
+        b'[' => {
+            let mut i = 2;
+            while sequence.get(i).is_some_and(|b| (0x30..=0x3f).contains(b)) {
+                i += 1;
+            }
+            while sequence.get(i).is_some_and(|b| (0x20..=0x2f).contains(b)) {
+                i += 1;
+            }
+            match sequence.get(i) {
+                Some(b) if (0x40..=0x7e).contains(b) => Some(i + 1),
+                _ => None
+            }
+        },
+        // terminated by BEL or ST (ESC \)
+        b']' => {
+            let end = 2 + sequence[2..].iter().position(|b| *b == 0x07 || *b == 0x1b)?;
+            match &sequence[end..] {
+                [0x07, ..] => Some(end + 1),
+                [0x1b, b'\\', ..] => Some(end + 2),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+/// Cuts `output` short to at most `max_bytes` on a character boundary, ending it with
+/// `TRUNCATION_MARKER`
+pub fn truncate(output : &mut String, max_bytes : usize) {
+    if output.len() <= max_bytes {
+        return;
+    }
+
+    let mut end = max_bytes.saturating_sub(TRUNCATION_MARKER.len());
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    output.truncate(end);
+    output.push_str(TRUNCATION_MARKER);
+}
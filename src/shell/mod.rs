@@ -0,0 +1,67 @@
+/// Splits a command line into arguments the way a POSIX shell would, without expanding anything
+///
+/// Whitespace separates arguments unless quoted or escaped. Single quotes keep everything up to
+/// the closing quote as is, double quotes allow `\"`, `\\`, `\$` & `` \` `` escapes, and a
+/// backslash outside of quotes escapes the next character. Returns why the input is malformed,
+/// i.e an unterminated quote, instead of guessing what was meant.
+pub fn split(input : &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // tells `''` apart from no argument at all
+    let mut in_arg = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            },
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(String::from("unterminated single quote"))
+                    }
+                }
+            },
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            },
+                            None => return Err(String::from("unterminated double quote"))
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(String::from("unterminated double quote"))
+                    }
+                }
+            },
+            '\\' => {
+                in_arg = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => return Err(String::from("trailing backslash"))
+                }
+            },
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
@@ -0,0 +1,117 @@
+use futures_core::Stream;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, StreamExt};
+use serde::Deserialize;
+
+use crate::WandboxError;
+use crate::transport::ChunkStream;
+
+/// A single event emitted by Wandbox while a streamed compilation runs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompileEvent {
+    /// Lifecycle markers such as "Start" and "Finish"
+    Control(String),
+    /// Output the compiler wrote to stdout
+    CompilerStdOut(String),
+    /// Output the compiler wrote to stderr
+    CompilerStdErr(String),
+    /// Output the program wrote to stdout
+    StdOut(String),
+    /// Output the program wrote to stderr
+    StdErr(String),
+    /// The exit code of the program
+    ExitCode(i32),
+    /// The signal that terminated the program, i.e "Killed"
+    Signal(String),
+    /// An event type this crate does not know about yet
+    Other { kind : String, data : String },
+}
+
+impl CompileEvent {
+    /// Whether this event marks the end of the stream
+    pub fn is_finish(&self) -> bool {
+        matches!(self, CompileEvent::Control(data) if data == "Finish")
+    }
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    #[serde(rename = "type")]
+    kind : String,
+    #[serde(default)]
+    data : String,
+}
+
+/// Parses one line of the compile.ndjson response into an event
+pub(crate) fn parse_event(line : &str) -> Result<CompileEvent, WandboxError> {
+    let raw : RawEvent = match serde_json::from_str(line) {
+        Ok(raw) => raw,
+        Err(e) => return Err(WandboxError::Deserialize(e))
+    };
+
+    let event = match raw.kind.as_str() {
+        "Control" => CompileEvent::Control(raw.data),
+        "CompilerMessageS" => CompileEvent::CompilerStdOut(raw.data),
+        "CompilerMessageE" => CompileEvent::CompilerStdErr(raw.data),
+        "StdOut" => CompileEvent::StdOut(raw.data),
+        "StdErr" => CompileEvent::StdErr(raw.data),
+        "ExitCode" => match raw.data.trim().parse() {
+            Ok(code) => CompileEvent::ExitCode(code),
+            Err(_e) => return Err(WandboxError::Deserialize(serde::de::Error::custom(format!("invalid exit code {}", raw.data))))
+        },
+        "Signal" => CompileEvent::Signal(raw.data),
+        _ => CompileEvent::Other { kind : raw.kind, data : raw.data }
+    };
+    Ok(event)
+}
+
+enum State {
+    Pending(BoxFuture<'static, Result<ChunkStream, WandboxError>>),
+    Reading(ChunkStream, Vec<u8>),
+    Done,
+}
+
+/// Yields the events of a compile.ndjson reply as they arrive, once `open` has sent the request
+pub(crate) fn events(open : BoxFuture<'static, Result<ChunkStream, WandboxError>>) -> impl Stream<Item = Result<CompileEvent, WandboxError>> {
+    stream::unfold(State::Pending(open), |state| async move {
+        let (mut response, mut buffer) = match state {
+            State::Pending(open) => match open.await {
+                Ok(chunks) => (chunks, Vec::new()),
+                Err(e) => return Some((Err(e), State::Done))
+            },
+            State::Reading(response, buffer) => (response, buffer),
+            State::Done => return None
+        };
+
+        loop {
+            // hand out a complete line if we've buffered one
+            if let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line : Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                return match parse_event(line.trim()) {
+                    Ok(event) if event.is_finish() => Some((Ok(event), State::Done)),
+                    Ok(event) => Some((Ok(event), State::Reading(response, buffer))),
+                    Err(e) => Some((Err(e), State::Reading(response, buffer)))
+                };
+            }
+
+            match response.next().await {
+                Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                None => {
+                    // the last event may not be newline terminated, the reply isn't polled again
+                    // as it has ended
+                    let line = String::from_utf8_lossy(&buffer);
+                    if line.trim().is_empty() {
+                        return None;
+                    }
+                    return Some((parse_event(line.trim()), State::Done));
+                }
+                Some(Err(e)) => return Some((Err(e), State::Done))
+            }
+        }
+    })
+}
@@ -0,0 +1,50 @@
+use std::collections::BTreeSet;
+
+/// The most suggestions offered for an unknown target
+pub const MAX_SUGGESTIONS : usize = 3;
+
+/// Picks the candidates closest to `input`, closest first, ignoring case
+///
+/// Only candidates within a third of the input's length in edits are kept, but always within at
+/// least one edit, so "c+" still suggests "c++".
+pub fn closest<'a, I : IntoIterator<Item = &'a str>>(input : &str, candidates : I) -> Vec<String> {
+    let input = input.trim().to_lowercase();
+    let limit = (input.chars().count() / 3).max(1);
+
+    let candidates : BTreeSet<String> = candidates.into_iter().map(|c| c.to_lowercase()).collect();
+    let mut scored : Vec<(usize, String)> = candidates.into_iter()
+        .filter(|c| *c != input)
+        .map(|c| (distance(&input, &c), c))
+        .filter(|(d, _c)| *d <= limit)
+        .collect();
+    scored.sort();
+
+    scored.into_iter().take(MAX_SUGGESTIONS).map(|(_d, c)| c).collect()
+}
+
+/// The number of insertions, deletions, substitutions & swaps of neighbouring characters it takes
+/// to turn `a` into `b`, so "gcc-haed" is a single edit away from "gcc-head"
+fn distance(a : &str, b : &str) -> usize {
+    let a : Vec<char> = a.chars().collect();
+    let b : Vec<char> = b.chars().collect();
+
+    // rows for the two previous prefixes of `a` & the current one
+    let mut before : Vec<usize> = vec![0; b.len() + 1];
+    let mut previous : Vec<usize> = (0..=b.len()).collect();
+    let mut current : Vec<usize> = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
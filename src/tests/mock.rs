@@ -0,0 +1,169 @@
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A request received by the mock server
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    pub method : String,
+    pub path : String,
+    pub headers : Vec<(String, String)>,
+    pub body : String,
+}
+
+impl MockRequest {
+    pub fn header(&self, name : &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _v)| k.eq_ignore_ascii_case(name))
+            .map(|(_k, v)| v.as_str())
+    }
+}
+
+/// The canned reply the mock server sends back
+pub struct MockResponse {
+    pub status : u16,
+    pub headers : Vec<(String, String)>,
+    pub body : String,
+    pub delay : Option<Duration>,
+}
+
+impl MockResponse {
+    pub fn json(body : &str) -> MockResponse {
+        MockResponse {
+            status : 200,
+            headers : vec![(String::from("Content-Type"), String::from("application/json"))],
+            body : body.to_string(),
+            delay : None
+        }
+    }
+
+    pub fn status(status : u16, body : &str) -> MockResponse {
+        MockResponse {
+            status,
+            headers : Vec::new(),
+            body : body.to_string(),
+            delay : None
+        }
+    }
+}
+
+/// A tiny HTTP/1.1 server that answers every request through a handler
+pub struct MockServer {
+    url : String,
+    requests : Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler : F) -> MockServer
+        where F : Fn(&MockRequest) -> MockResponse + Send + Sync + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let handler = Arc::new(handler);
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                thread::spawn(move || {
+                    if let Some(request) = read_request(&stream) {
+                        recorded.lock().unwrap().push(request.clone());
+                        write_response(stream, handler(&request));
+                    }
+                });
+            }
+        });
+
+        MockServer {
+            url,
+            requests
+        }
+    }
+
+    /// Serves list.json from the test fixture, and `compile` for every other request
+    pub fn wandbox(compile : &'static str) -> MockServer {
+        MockServer::start(move |req| {
+            if req.path == "/api/list.json" {
+                MockResponse::json(LIST_FIXTURE)
+            }
+            else {
+                MockResponse::json(compile)
+            }
+        })
+    }
+
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub const LIST_FIXTURE : &str = include_str!("fixtures/list.json");
+pub const STREAM_FIXTURE : &str = include_str!("fixtures/compile.ndjson");
+pub const PERMLINK_FIXTURE : &str = include_str!("fixtures/permlink.json");
+
+/// Whether the opt-in tests against wandbox.org should run, enabled with WANDBOX_LIVE_TESTS=1
+pub fn live_tests_enabled() -> bool {
+    std::env::var("WANDBOX_LIVE_TESTS").map(|v| v == "1").unwrap_or(false)
+}
+
+fn read_request(stream : &TcpStream) -> Option<MockRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            headers.push((k.trim().to_string(), v.trim().to_string()));
+        }
+    }
+
+    let length = headers.iter()
+        .find(|(k, _v)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_k, v)| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(MockRequest {
+        method,
+        path,
+        headers,
+        body : String::from_utf8_lossy(&body).to_string()
+    })
+}
+
+fn write_response(mut stream : TcpStream, response : MockResponse) {
+    if let Some(delay) = response.delay {
+        thread::sleep(delay);
+    }
+
+    let mut head = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+    for (k, v) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+    let _ = stream.flush();
+}
@@ -1,2 +1,5 @@
 #[cfg(test)]
-pub mod tests;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod tests;
+#[cfg(test)]
+pub mod mock;
@@ -2,22 +2,30 @@ use crate::Wandbox;
 use std::error::Error;
 use std::collections::HashSet;
 
+use crate::tests::mock::MockServer;
+
+/// The body of the `i`th request the mock server received, as JSON
+fn sent(server : &MockServer, i : usize) -> serde_json::Value {
+    serde_json::from_str(&server.requests()[i].body).expect("requests are sent as JSON")
+}
+
 #[tokio::test]
 async fn is_valid_language() -> Result<(), Box<dyn Error>> {
-    let wbox : Wandbox = Wandbox::new(None, None).await?;
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
 
-    let cache = wbox.cache.clone();
-    let lock = cache.read().unwrap();
-    for (_k, v) in &*lock {
-        assert!(wbox.get_default_compiler(&v.name).is_some());
+    for lang in wbox.get_languages() {
+        assert!(wbox.is_valid_language(&lang.name));
     }
+    assert!(!wbox.is_valid_language("cobol"));
 
     Ok(())
 }
 
 #[tokio::test]
 async fn get_default_controller() -> Result<(), Box<dyn Error>> {
-    let wbox : Wandbox = Wandbox::new(None, None).await?;
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
 
     let cache = wbox.cache.clone();
     let lock = cache.read().unwrap();
@@ -30,8 +38,8 @@ async fn get_default_controller() -> Result<(), Box<dyn Error>> {
 
 #[tokio::test]
 async fn is_valid_compiler_str() -> Result<(), Box<dyn Error>> {
-
-    let wbox : Wandbox = Wandbox::new(None, None).await?;
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
 
     assert!(wbox.is_valid_compiler_str("gcc-head"));
     Ok(())
@@ -43,7 +51,8 @@ async fn ignore_broken_compiler() -> Result<(), Box<dyn Error>> {
     let mut set : HashSet<String> = HashSet::new();
     set.insert(String::from("gcc-head"));
 
-    let wbox : Wandbox = Wandbox::new(Some(set), None).await?;
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), Some(set), None).await?;
 
     assert!(!wbox.is_valid_compiler_str("gcc-head"));
     Ok(())
@@ -51,33 +60,3600 @@ async fn ignore_broken_compiler() -> Result<(), Box<dyn Error>> {
 
 #[tokio::test]
 async fn compilation_builder_lang() -> Result<(), Box<dyn Error>> {
-    let wbox : Wandbox = Wandbox::new(None, None).await?;
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
 
     let mut builder = crate::CompilationBuilder::new();
     builder.target("c++");
-    builder.options_str(vec!["-Wall", "-Werror"]);
+    builder.options(vec!["-Wall", "-Werror"]);
     builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
-    builder.build(&wbox)?;
+    let compilation = builder.build(&wbox)?;
 
-    let res = builder.dispatch().await.expect("Failed to lookup");
+    let res = compilation.dispatch().await.expect("Failed to lookup");
     assert_eq!(res.program_all, "test");
 
+    let sent = sent(&server, 1);
+    assert_eq!(sent["compiler"], "gcc-head");
+    assert_eq!(sent["compiler-option-raw"], "-Wall\n-Werror");
+
     Ok(())
 }
 
 
 #[tokio::test]
 async fn compilation_builder_compiler() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-9.3.0");
+    builder.options(vec!["-Wall", "-Werror"]);
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.lang(), "c++");
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all, "test");
+    assert_eq!(sent(&server, 1)["compiler"], "gcc-9.3.0");
+
+    Ok(())
+}
+
+
+#[tokio::test]
+async fn compilation_builder_files() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.add_file("message.h", "#define MESSAGE \"test\"\n")?;
+    builder.code("#include<iostream>\n#include \"message.h\"\nint main()\n{\nstd::cout<<MESSAGE;\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all, "test");
+
+    let sent = sent(&server, 1);
+    assert_eq!(sent["codes"][0]["file"], "message.h");
+    assert_eq!(sent["codes"][0]["code"], "#define MESSAGE \"test\"\n");
+
+    Ok(())
+}
+
+#[test]
+fn compilation_builder_rejects_bad_files() {
+    use crate::WandboxError;
+
+    let mut builder = crate::CompilationBuilder::new();
+    assert!(matches!(builder.add_file("", "int x;"), Err(WandboxError::EmptyFileName)));
+    assert!(builder.add_file("util.h", "int x;").is_ok());
+    assert!(matches!(builder.add_file("util.h", "int y;"), Err(WandboxError::DuplicateFile(f)) if f == "util.h"));
+}
+
+#[tokio::test]
+async fn compilation_builder_switches() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"1"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.switches(vec!["c++2a", "warning"]);
+    builder.code("#include<iostream>\nconsteval int value() { return 1; }\nint main()\n{\nstd::cout<<value();\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all, "1");
+    assert_eq!(sent(&server, 1)["options"], "c++2a,warning");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compilation_builder_whitespace() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"3\n"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let code = "\n\nprint(open(__file__).read().count('\\n'))\n";
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("python");
+    builder.code(code);
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all.trim(), "3");
+    assert_eq!(sent(&server, 1)["code"], code);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compilation_builder_stdin() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"11"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c");
+    builder.stdin("  padded  \n");
+    builder.code("#include<stdio.h>\n#include<string.h>\nint main()\n{\nchar line[64];\nfgets(line, sizeof line, stdin);\nprintf(\"%zu\", strlen(line));\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all, "11");
+    assert_eq!(sent(&server, 1)["stdin"], "  padded  \n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compilation_builder_runtime_options() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"first;second;"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c");
+    builder.runtime_options(vec!["first", "second"]);
+    builder.code("#include<stdio.h>\nint main(int argc, char** argv)\n{\nfor (int i = 1; i < argc; i++) printf(\"%s;\", argv[i]);\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all, "first;second;");
+    assert_eq!(sent(&server, 1)["runtime-option-raw"], "first\nsecond");
+
+    Ok(())
+}
+
+#[test]
+fn builder_setters_accept_owned_strings() -> Result<(), Box<dyn Error>> {
+    use crate::CompilationBuilder;
+
+    let mut borrowed = CompilationBuilder::new();
+    borrowed.target(" c++ ")
+        .code("int main() {}")
+        .stdin("1 2")
+        .append_stdin("3")
+        .options(vec!["-Wall", "-O2"])
+        .add_option("-g")
+        .runtime_options(["first"])
+        .switches(vec!["warning"])
+        .cpp_standard("c++17");
+    borrowed.add_file("util.h", "#pragma once")?;
+
+    let mut owned = CompilationBuilder::new();
+    owned.target(String::from(" c++ "))
+        .code(String::from("int main() {}"))
+        .stdin(String::from("1 2"))
+        .append_stdin(String::from("3"))
+        .options(vec![String::from("-Wall"), String::from("-O2")])
+        .add_option(String::from("-g"))
+        .runtime_options([String::from("first")])
+        .switches(vec![String::from("warning")])
+        .cpp_standard(String::from("c++17"));
+    owned.add_file(String::from("util.h"), String::from("#pragma once"))?;
+
+    assert_eq!(borrowed, owned);
+
+    // the deprecated `_str` setters still set the same options
+    #[allow(deprecated)]
+    let legacy = {
+        let mut b = CompilationBuilder::new();
+        b.options_str(vec!["-Wall", "-O2"]).runtime_options_str(vec!["first"]);
+        b
+    };
+    let mut generic = CompilationBuilder::new();
+    generic.options(["-Wall", "-O2"]).runtime_options(["first"]);
+    assert_eq!(legacy, generic);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn permlink_roundtrip() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE, PERMLINK_FIXTURE};
+
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/api/list.json" => MockResponse::json(LIST_FIXTURE),
+        "/api/permlink/abc123" => MockResponse::json(PERMLINK_FIXTURE),
+        "/api/compile.json" => MockResponse::json(r#"{"status":"0","program_message":"test","permlink":"abc123"}"#),
+        _ => MockResponse::status(404, "Not Found")
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.save(true);
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(sent(&server, 1)["save"], true);
+    let saved = wbox.get_permlink(res.permlink.as_deref().expect("saved compilations have a permlink")).await?;
+    assert_eq!(saved.result.program_all, res.program_all);
+    assert_eq!(saved.request.compiler, compilation.compiler());
+    assert!(saved.request.code.contains("std::cout"));
+
+    // unknown permlinks surface the 404
+    assert!(matches!(wbox.get_permlink("missing").await, Err(crate::WandboxError::Http { status : 404, .. })));
+
+    // ids that would escape the permlink path aren't sent
+    let requests = server.requests().len();
+    for id in ["../list.json", "abc/123", "abc?x=1", "abc#x", ""] {
+        assert!(matches!(wbox.get_permlink(id).await, Err(crate::WandboxError::InvalidPermlink(_))));
+    }
+    assert_eq!(server.requests().len(), requests);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compile_template() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/api/list.json" => MockResponse::json(LIST_FIXTURE),
+        "/api/template/gcc" => MockResponse::json(r#"{"code":"int main() {}\n"}"#),
+        _ => MockResponse::json(r#"{"status":"0"}"#)
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let compilers = wbox.get_compilers("c++").expect("c++ is available");
+    assert!(compilers.iter().any(|c| c.templates.iter().any(|t| t == "gcc")));
+
+    let template = wbox.get_template(" gcc ").await?;
+    assert_eq!(template.name, "gcc");
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code(&template.code);
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.status, "0");
+    assert_eq!(sent(&server, 2)["code"], "int main() {}\n");
+
+    // names that would escape the template path aren't sent
+    let requests = server.requests().len();
+    for name in ["gcc/../../list.json", "../compile.json", "gcc?x=1"] {
+        assert!(matches!(wbox.get_template(name).await, Err(crate::WandboxError::InvalidTemplate(_))));
+    }
+    assert_eq!(server.requests().len(), requests);
+
+    Ok(())
+}
+
+#[test]
+fn parse_stream_events() {
+    use crate::CompileEvent;
+    use crate::stream::parse_event;
+
+    assert_eq!(parse_event(r#"{"type":"StdOut","data":"test"}"#).unwrap(), CompileEvent::StdOut(String::from("test")));
+    assert_eq!(parse_event(r#"{"type":"ExitCode","data":"1"}"#).unwrap(), CompileEvent::ExitCode(1));
+    assert!(parse_event(r#"{"type":"Control","data":"Finish"}"#).unwrap().is_finish());
+    assert!(parse_event(r#"{"type":"ExitCode","data":"abc"}"#).is_err());
+    assert!(parse_event("<html>").is_err());
+}
+
+#[tokio::test]
+async fn compilation_builder_stream() -> Result<(), Box<dyn Error>> {
+    use futures_util::StreamExt;
+    use crate::CompileEvent;
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE, STREAM_FIXTURE};
+
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/api/list.json" => MockResponse::json(LIST_FIXTURE),
+        "/api/compile.ndjson" => MockResponse::json(STREAM_FIXTURE),
+        _ => MockResponse::status(404, "Not Found")
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let mut events = Box::pin(compilation.dispatch_stream());
+    let mut output = String::new();
+    let mut exit_code = None;
+    while let Some(event) = events.next().await {
+        match event? {
+            CompileEvent::StdOut(data) => output.push_str(&data),
+            CompileEvent::ExitCode(code) => exit_code = Some(code),
+            _ => {}
+        }
+    }
+    assert_eq!(output, "test");
+    assert_eq!(exit_code, Some(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dispatch_stream_gated() -> Result<(), Box<dyn Error>> {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::time::Duration;
+    use futures_util::StreamExt;
+    use crate::{CircuitBreaker, CircuitState, WandboxBuilder, WandboxError};
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE, STREAM_FIXTURE};
+
+    // the status the next streamed compilation gets, a 429 turns into a 200 once served
+    let next = Arc::new(AtomicU16::new(429));
+    let status = next.clone();
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/api/list.json" => MockResponse::json(LIST_FIXTURE),
+        _ => match status.load(Ordering::SeqCst) {
+            429 => {
+                status.store(200, Ordering::SeqCst);
+                let mut response = MockResponse::status(429, "Too Many Requests");
+                response.headers.push((String::from("Retry-After"), String::from("0")));
+                response
+            },
+            200 => MockResponse::json(STREAM_FIXTURE),
+            other => MockResponse::status(other, "Service Unavailable")
+        }
+    });
+    let wbox = WandboxBuilder::new()
+        .endpoint(&server.url())
+        .retry_rate_limited(Duration::from_secs(1))
+        .circuit_breaker(CircuitBreaker { failures : 2, window : Duration::from_secs(60), cool_down : Duration::from_secs(60) })
+        .build().await?;
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorder = seen.clone();
+    wbox.on_response(move |info| recorder.lock().unwrap().push((info.status, info.retries)));
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    // rate limited once then retried, the stream holds its place under the limit until dropped
+    let mut events = Box::pin(compilation.dispatch_stream());
+    assert_eq!(events.next().await.expect("an event")?, crate::CompileEvent::Control(String::from("Start")));
+    assert_eq!(wbox.in_flight(), 1);
+    drop(events);
+    assert_eq!(wbox.in_flight(), 0);
+    assert_eq!(*seen.lock().unwrap(), vec![(429, 0), (200, 1)]);
+
+    // outages open the breaker, which then stops streams before they're sent
+    next.store(503, Ordering::SeqCst);
+    for _ in 0..2 {
+        let mut events = Box::pin(compilation.dispatch_stream());
+        assert!(matches!(events.next().await, Some(Err(WandboxError::Http { status : 503, .. }))));
+    }
+    assert!(matches!(wbox.circuit_state(), Some(CircuitState::Open { .. })));
+    let sent = server.requests().len();
+    let mut events = Box::pin(compilation.dispatch_stream());
+    assert!(matches!(events.next().await, Some(Err(WandboxError::CircuitOpen { .. }))));
+    assert_eq!(server.requests().len(), sent);
+
+    Ok(())
+}
+
+// Only these run against wandbox.org itself, with WANDBOX_LIVE_TESTS=1
+
+#[tokio::test]
+async fn live_compile() -> Result<(), Box<dyn Error>> {
+    if !crate::tests::mock::live_tests_enabled() {
+        return Ok(());
+    }
     let wbox : Wandbox = Wandbox::new(None, None).await?;
 
     let mut builder = crate::CompilationBuilder::new();
-    builder.target("gcc-6.3.0");
-    builder.options_str(vec!["-Wall", "-Werror"]);
+    builder.target("c++");
+    builder.options(vec!["-Wall", "-Werror"]);
     builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
-    builder.build(&wbox)?;
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    assert_eq!(res.program_all, "test");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn live_permlink_roundtrip() -> Result<(), Box<dyn Error>> {
+    if !crate::tests::mock::live_tests_enabled() {
+        return Ok(());
+    }
+    let wbox : Wandbox = Wandbox::new(None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.save(true);
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await.expect("Failed to lookup");
+    let saved = wbox.get_permlink(res.permlink.as_deref().expect("saved compilations have a permlink")).await?;
+    assert_eq!(saved.result.program_all, res.program_all);
+    assert_eq!(saved.request.compiler, compilation.compiler());
+
+    Ok(())
+}
+
+#[test]
+fn endpoint_normalization() {
+    use crate::normalize_endpoint;
+
+    assert_eq!(normalize_endpoint("https://wandbox.org/").unwrap(), "https://wandbox.org");
+    assert_eq!(normalize_endpoint(" http://localhost:3500//").unwrap(), "http://localhost:3500");
+    assert!(normalize_endpoint("wandbox.org").is_err());
+    assert!(normalize_endpoint("https://").is_err());
+}
+
+#[tokio::test]
+async fn custom_endpoint() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&format!("{}/", server.url()), None, None).await?;
+    assert_eq!(wbox.endpoint(), server.url());
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = compilation.dispatch().await?;
+    assert_eq!(res.program_all, "test");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/api/list.json");
+    assert_eq!(requests[1].method, "POST");
+    assert_eq!(requests[1].path, "/api/compile.json");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn injected_client() -> Result<(), Box<dyn Error>> {
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox(r#"{"status":"0"}"#);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-wandbox-test", HeaderValue::from_static("injected"));
+    let client = reqwest::Client::builder().default_headers(headers).build()?;
+    let wbox = crate::WandboxBuilder::new().endpoint(&server.url()).client(client).build().await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    builder.build(&wbox)?.dispatch().await?;
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|r| r.header("x-wandbox-test") == Some("injected")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn proxy_and_user_agent() -> Result<(), Box<dyn Error>> {
+    use crate::{WandboxBuilder, WandboxError};
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // the mock stands in for the proxy, so it sees absolute urls
+    let proxy = MockServer::start(|req| {
+        if req.path.ends_with("/api/list.json") {
+            MockResponse::json(LIST_FIXTURE)
+        }
+        else {
+            MockResponse::json(r#"{"status":"0"}"#)
+        }
+    });
+    let wbox = WandboxBuilder::new()
+        .endpoint("http://wandbox.invalid")
+        .proxy(&proxy.url())
+        .user_agent("wandbox-tests/1.0")
+        .build().await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    builder.build(&wbox)?.dispatch().await?;
+
+    let requests = proxy.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "http://wandbox.invalid/api/list.json");
+    assert_eq!(requests[1].path, "http://wandbox.invalid/api/compile.json");
+    assert!(requests.iter().all(|r| r.header("user-agent") == Some("wandbox-tests/1.0")));
+
+    // a bad proxy fails before anything is sent
+    let result = WandboxBuilder::new().proxy("http://[bad").build().await;
+    match result.err() {
+        Some(WandboxError::InvalidProxy(url)) => assert_eq!(url, "http://[bad"),
+        other => panic!("expected an invalid proxy, got {:?}", other)
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn clone_shares_cache() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let clone = wbox.clone();
+
+    let task = tokio::spawn(async move {
+        assert!(clone.is_valid_compiler_str("gcc-head"));
+        clone.block_compiler("gcc-head");
+        clone.get_compiler_language_str("clang-head")
+    });
+    assert_eq!(task.await?, Some(String::from("c++")));
 
-    let res = builder.dispatch().await.expect("Failed to lookup");
+    // the clone's blocklist change is visible here too
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(wbox.is_valid_compiler_str("clang-head"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dispatch_with_shared_client() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+
+    for _ in 0..3 {
+        let res = compilation.dispatch_with(&wbox).await?;
+        assert_eq!(res.program_all, "test");
+    }
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/api/compile.json").count(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn one_shot_run() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, RunOptions, WandboxError};
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let res = wbox.run("C++", "int main() {}").await?;
+    assert_eq!(res.program_all, "test");
+    let sent_run = sent(&server, 1);
+    assert_eq!(sent_run["compiler"], "gcc-head");
+    assert_eq!(sent_run["code"], "int main() {}");
+
+    let options = RunOptions {
+        stdin : String::from("input"),
+        options : vec![String::from("-O2")],
+        switches : vec![String::from("warning")],
+        save : true,
+        ..Default::default()
+    };
+    wbox.run_with("gcc-10.1.0", "int main() {}", options).await?;
+    let sent_run = sent(&server, 2);
+    assert_eq!(sent_run["compiler"], "gcc-10.1.0");
+    assert_eq!(sent_run["stdin"], "input");
+    assert_eq!(sent_run["compiler-option-raw"], "-O2");
+    assert_eq!(sent_run["options"], "warning");
+    assert_eq!(sent_run["save"], true);
+
+    // problems with the request are caught before anything is sent
+    assert!(matches!(wbox.run("gcc-haed", "int main() {}").await, Err(WandboxError::Build(BuildError::UnknownTarget { .. }))));
+    assert_eq!(server.requests().len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn share() -> Result<(), Box<dyn Error>> {
+    use crate::{Permlink, RunOptions, WandboxError};
+
+    // only the id, the url is derived from the endpoint
+    let server = MockServer::wandbox(r#"{"status":"0","permlink":"abc123","url":""}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let permlink = wbox.share("c++", "int main() {}", RunOptions::default()).await?;
+    assert_eq!(permlink, Permlink { id : String::from("abc123"), url : format!("{}/permlink/abc123", server.url()) });
+    assert_eq!(sent(&server, 1)["save"], true);
+
+    // only the url, the id is parsed out of it
+    let server = MockServer::wandbox(r#"{"status":"0","url":"https://wandbox.org/permlink/xyz789"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let permlink = wbox.share("c++", "int main() {}", RunOptions::default()).await?;
+    assert_eq!(permlink.id, "xyz789");
+    assert_eq!(permlink.url, "https://wandbox.org/permlink/xyz789");
+
+    // neither is a dedicated error rather than empty strings
+    let server = MockServer::wandbox(r#"{"status":"0","permlink":"","url":""}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    assert!(matches!(wbox.share("c++", "int main() {}", RunOptions::default()).await, Err(WandboxError::MissingPermlink)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dispatch_detailed() -> Result<(), Box<dyn Error>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::WandboxBuilder;
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    const BODY : &str = r#"{"status":"0","program_message":"test"}"#;
+
+    // rate limited once, then slow to reply
+    let compiles = AtomicUsize::new(0);
+    let server = MockServer::start(move |req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        if compiles.fetch_add(1, Ordering::SeqCst) == 0 {
+            let mut response = MockResponse::status(429, "");
+            response.headers.push((String::from("Retry-After"), String::from("0")));
+            return response;
+        }
+        MockResponse { delay : Some(Duration::from_millis(100)), ..MockResponse::json(BODY) }
+    });
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).retry_rate_limited(Duration::from_secs(1)).build().await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    let (result, info) = compilation.dispatch_detailed().await?;
+    assert_eq!(result.program_all, "test");
+    assert!(info.elapsed >= Duration::from_millis(100), "{:?}", info);
+    assert_eq!(info.status, 200);
+    assert_eq!(info.content_length, BODY.len());
+    assert_eq!(info.retries, 1);
+
+    // the plain dispatch is unchanged
+    assert_eq!(compilation.dispatch().await?.program_all, "test");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn builder_clone_and_debug() -> Result<(), Box<dyn Error>> {
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").options(vec!["-Wall"]);
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    builder.add_file("message.h", "#define MESSAGE 1\n")?;
+
+    let debug = format!("{:?}", builder);
+    assert!(debug.contains(r##"code: "#include<iostream>" (5 lines, 52 bytes)"##), "{}", debug);
+    assert!(debug.contains(r##"("message.h", "#define MESSAGE 1" (1 lines, 18 bytes))"##), "{}", debug);
+    assert!(debug.contains(r#"target: "c++""#));
+
+    // retry a copy with tweaked options, leaving the original untouched
+    let mut retry = builder.clone();
+    assert_eq!(retry, builder);
+    retry.options(vec!["-O2"]);
+    assert_ne!(retry, builder);
+
+    let res = retry.build(&wbox)?.dispatch().await?;
     assert_eq!(res.program_all, "test");
+    builder.build(&wbox)?.dispatch().await?;
+
+    assert_eq!(sent(&server, 1)["compiler-option-raw"], "-O2");
+    assert_eq!(sent(&server, 2)["compiler-option-raw"], "-Wall");
+    assert_eq!(sent(&server, 1)["codes"], sent(&server, 2)["codes"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unparseable_responses() -> Result<(), Box<dyn Error>> {
+    use crate::{MemoryTransport, TransportResponse, WandboxBuilder, WandboxError};
+    use crate::tests::mock::LIST_FIXTURE;
+
+    const PAGE : &str = "<html><body><h1>502 Bad Gateway</h1></body></html>";
+
+    // an error page from a proxy keeps its body
+    let transport = MemoryTransport::new();
+    transport.respond("/api/list.json", TransportResponse::new(200, LIST_FIXTURE))
+        .respond("/api/compile.json", TransportResponse::new(502, PAGE));
+    let wbox = WandboxBuilder::new().transport(Box::new(transport.clone())).build().await?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    match builder.build(&wbox)?.dispatch().await {
+        Err(e @ WandboxError::Http { .. }) => {
+            assert!(e.to_string().contains("502 Bad Gateway"));
+        }
+        other => panic!("expected an http error, got {:?}", other)
+    }
+
+    // a successful reply that isn't json
+    transport.respond("/api/compile.json", TransportResponse::new(200, PAGE));
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    match builder.build(&wbox)?.dispatch().await {
+        Err(WandboxError::InvalidResponse { status, body, .. }) => {
+            assert_eq!(status, 200);
+            assert_eq!(body, PAGE);
+        }
+        other => panic!("expected an invalid response, got {:?}", other)
+    }
+
+    // the same goes for list.json, and long bodies are cut down
+    let long = "é".repeat(4096);
+    transport.respond("/api/list.json", TransportResponse::new(200, &long));
+    let result = WandboxBuilder::new().transport(Box::new(transport)).build().await;
+    match result.err() {
+        Some(WandboxError::InvalidResponse { body, .. }) => {
+            assert!(!body.is_empty() && body.len() <= 1024);
+            assert!(body.chars().all(|c| c == 'é'));
+        }
+        other => panic!("expected an invalid response, got {:?}", other)
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_concurrency() -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    let current = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (c, p) = (current.clone(), peak.clone());
+    let server = MockServer::start(move |req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        let now = c.fetch_add(1, Ordering::SeqCst) + 1;
+        p.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(100));
+        c.fetch_sub(1, Ordering::SeqCst);
+        MockResponse::json(r#"{"status":"0"}"#)
+    });
+
+    let wbox = Arc::new(Wandbox::with_endpoint(&server.url(), None, None).await?);
+    wbox.set_max_concurrency(2);
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    let mut handles = Vec::new();
+    for _ in 0..10 {
+        let (wbox, compilation) = (wbox.clone(), compilation.clone());
+        handles.push(tokio::spawn(async move { compilation.dispatch_with(&wbox).await }));
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(wbox.in_flight() <= 2);
+    for handle in handles {
+        handle.await??;
+    }
+
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    assert_eq!(wbox.in_flight(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dispatch_all() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // echoes the compiler back
+    let server = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        let request : serde_json::Value = serde_json::from_str(&req.body).unwrap();
+        MockResponse::json(&serde_json::json!({ "status" : "0", "program_message" : request["compiler"] }).to_string())
+    });
+    let wbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    wbox.set_max_concurrency(1);
+
+    let requests : Vec<CompilationBuilder> = ["gcc-head", "not-a-compiler", "clang-head"].iter().map(|target| {
+        let mut builder = CompilationBuilder::new();
+        builder.target(target);
+        builder.code("int main() {}");
+        builder
+    }).collect();
+
+    let results = wbox.dispatch_all(requests).await;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().program_all, "gcc-head");
+    assert!(matches!(&results[1], Err(WandboxError::Build(BuildError::UnknownTarget { input, .. })) if input == "not-a-compiler"));
+    assert_eq!(results[2].as_ref().unwrap().program_all, "clang-head");
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/api/compile.json").count(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn run_matrix() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // echoes the compiler & options back
+    let server = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        let request : serde_json::Value = serde_json::from_str(&req.body).unwrap();
+        MockResponse::json(&serde_json::json!({
+            "status" : "0",
+            "program_message" : request["compiler"],
+            "compiler_message" : request["compiler-option-raw"]
+        }).to_string())
+    });
+    let wbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let matrix = wbox.run_matrix("cpp", "int main() {}", vec![String::from("-O2")]).await;
+    let names : Vec<&str> = matrix.iter().map(|(c, _r)| c.name.as_str()).collect();
+    assert_eq!(names, vec!["gcc-head", "gcc-10.1.0", "gcc-9.3.0", "clang-head", "clang-10.0.0"]);
+    for (compiler, result) in &matrix {
+        let result = result.as_ref().unwrap();
+        assert_eq!(result.program_all, compiler.name);
+        assert_eq!(result.compiler_all, "-O2");
+    }
+
+    // python doesn't take raw options, so nothing runs unless there are none
+    assert!(wbox.run_matrix("python", "print(1)", vec![String::from("-O")]).await.is_empty());
+    assert_eq!(wbox.run_matrix("python", "print(1)", Vec::new()).await.len(), 2);
+    assert!(wbox.run_matrix("cobol", "", Vec::new()).await.is_empty());
+
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/api/compile.json").count(), 7);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancellable_dispatch() -> Result<(), Box<dyn Error>> {
+    use std::time::{Duration, Instant};
+    use crate::WandboxError;
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    let server = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        MockResponse {
+            delay : Some(Duration::from_secs(5)),
+            ..MockResponse::json(r#"{"status":"0"}"#)
+        }
+    });
+    let wbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    let (handle, future) = compilation.dispatch_cancellable();
+    let started = Instant::now();
+    let canceller = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.cancel();
+        handle
+    });
+    assert!(matches!(future.await, Err(WandboxError::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    // cancelling once it's done does nothing
+    canceller.await?.cancel();
+
+    // nor does dropping the handle cancel anything
+    let fast = MockServer::wandbox(r#"{"status":"0"}"#);
+    let wbox = Wandbox::with_endpoint(&fast.url(), None, None).await?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    let (handle, future) = builder.build(&wbox)?.dispatch_cancellable();
+    drop(handle);
+    assert!(future.await?.success());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limiting() -> Result<(), Box<dyn Error>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::{WandboxBuilder, WandboxError};
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // rate limits the first compile, asking for `delay` seconds
+    fn limited_once(delay : &'static str) -> MockServer {
+        let compiles = AtomicUsize::new(0);
+        MockServer::start(move |req| {
+            if req.path == "/api/list.json" {
+                return MockResponse::json(LIST_FIXTURE);
+            }
+            if compiles.fetch_add(1, Ordering::SeqCst) == 0 {
+                let mut response = MockResponse::status(429, "<html>Too Many Requests</html>");
+                response.headers.push((String::from("Retry-After"), String::from(delay)));
+                return response;
+            }
+            MockResponse::json(r#"{"status":"0","program_message":"ok"}"#)
+        })
+    }
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+
+    // surfaced to the caller by default
+    let server = limited_once("120");
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).build().await?;
+    match builder.clone().build(&wbox)?.dispatch().await {
+        Err(e @ WandboxError::RateLimited { .. }) => {
+            assert!(e.is_rate_limited());
+            assert!(!e.to_string().contains("outage"));
+            assert!(matches!(e, WandboxError::RateLimited { retry_after : Some(d) } if d == Duration::from_secs(120)));
+        }
+        other => panic!("expected a rate limit, got {:?}", other)
+    }
+
+    // retried once when opted in
+    let server = limited_once("0");
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).retry_rate_limited(Duration::from_secs(1)).build().await?;
+    let result = builder.clone().build(&wbox)?.dispatch().await?;
+    assert_eq!(result.program_all, "ok");
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/api/compile.json").count(), 2);
+
+    // but not when Wandbox asks for longer than we're willing to wait
+    let server = limited_once("120");
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).retry_rate_limited(Duration::from_secs(1)).build().await?;
+    let result = builder.build(&wbox)?.dispatch().await;
+    assert!(result.is_err_and(|e| e.is_rate_limited()));
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/api/compile.json").count(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn malformed_compilers_are_skipped() -> Result<(), Box<dyn Error>> {
+    use crate::{WandboxBuilder, WandboxError};
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // clang-head's switches & version are in a shape we don't understand
+    let mut list : Vec<serde_json::Value> = serde_json::from_str(LIST_FIXTURE)?;
+    let broken = list.iter_mut().find(|c| c["name"] == "clang-head").unwrap();
+    broken["switches"] = serde_json::Value::from("-Wall");
+    broken["version"] = serde_json::Value::from(12);
+    let list = serde_json::to_string(&list)?;
+    let server = MockServer::start(move |_req| MockResponse::json(&list));
+
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).build().await?;
+    assert!(!wbox.is_valid_compiler_str("clang-head"));
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+    assert!(wbox.is_valid_compiler_str("clang-10.0.0"));
+    let warnings = wbox.load_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("clang-head"));
+
+    let strict = WandboxBuilder::new().endpoint(&server.url()).strict(true).build().await;
+    match strict.err() {
+        Some(WandboxError::InvalidResponse { status : 200, .. }) => (),
+        other => panic!("expected an invalid response, got {:?}", other)
+    }
+
+    // a well formed list has nothing to report
+    let server = MockServer::wandbox("{}");
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).strict(true).build().await?;
+    assert!(wbox.load_warnings().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn request_timeouts() -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+    use crate::WandboxBuilder;
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // a slow list.json times out during construction
+    let slow_list = MockServer::start(|_req| MockResponse {
+        delay : Some(Duration::from_millis(500)),
+        ..MockResponse::json(LIST_FIXTURE)
+    });
+    let result = WandboxBuilder::new()
+        .endpoint(&slow_list.url())
+        .request_timeout(Duration::from_millis(100))
+        .build().await;
+    match result {
+        Ok(_wbox) => panic!("list.json should time out"),
+        Err(e) => assert!(e.is_timeout())
+    }
+
+    // a slow compile times out during dispatch
+    let slow_compile = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            MockResponse::json(LIST_FIXTURE)
+        }
+        else {
+            MockResponse {
+                delay : Some(Duration::from_millis(500)),
+                ..MockResponse::json(r#"{"status":"0"}"#)
+            }
+        }
+    });
+    let wbox : Wandbox = WandboxBuilder::new()
+        .endpoint(&slow_compile.url())
+        .connect_timeout(Duration::from_millis(100))
+        .request_timeout(Duration::from_millis(200))
+        .build().await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    let err = compilation.dispatch().await.expect_err("compile should time out");
+    assert!(err.is_timeout());
+
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn blocking_dispatch() -> Result<(), Box<dyn Error>> {
+    use crate::WandboxBuilder;
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox = crate::blocking::Wandbox::from_builder(WandboxBuilder::new().endpoint(&server.url()))?;
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.code("#include<iostream>\nint main()\n{\nstd::cout<<\"test\";\n}");
+    let compilation = builder.build(&wbox)?;
+
+    let res = wbox.dispatch(&compilation)?;
+    assert_eq!(res.program_all, "test");
+
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn blocking_rate_limit_retry() -> Result<(), Box<dyn Error>> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use crate::WandboxBuilder;
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    // retries wait without a tokio runtime to sleep on
+    let limited = AtomicBool::new(true);
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/api/list.json" => MockResponse::json(LIST_FIXTURE),
+        _ if limited.swap(false, Ordering::SeqCst) => {
+            let mut response = MockResponse::status(429, "Too Many Requests");
+            response.headers.push((String::from("Retry-After"), String::from("0")));
+            response
+        },
+        _ => MockResponse::json(r#"{"status":"0","program_message":"test"}"#)
+    });
+    let wbox = crate::blocking::Wandbox::from_builder(WandboxBuilder::new().endpoint(&server.url()).retry_rate_limited(Duration::from_secs(1)))?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(wbox.dispatch(&compilation)?.program_all, "test");
+    assert_eq!(server.requests().len(), 3);
+
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[tokio::test]
+async fn blocking_in_runtime() {
+    use crate::{WandboxBuilder, WandboxError};
+
+    let server = MockServer::wandbox("{}");
+    assert!(matches!(crate::blocking::Wandbox::from_builder(WandboxBuilder::new().endpoint(&server.url())), Err(WandboxError::BlockingInRuntime)));
+    assert!(server.requests().is_empty());
+
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn blocking_auto_refresh() {
+    use std::time::Duration;
+    use crate::{WandboxBuilder, WandboxError};
+
+    let server = MockServer::wandbox("{}");
+    let refreshing = WandboxBuilder::new().endpoint(&server.url()).auto_refresh(Duration::from_secs(60));
+    assert!(matches!(crate::blocking::Wandbox::from_builder(refreshing), Err(WandboxError::AutoRefreshUnsupported)));
+}
+
+/// Fetches an https url from a port nothing listens on, so only the TLS backend decides how it
+/// fails, without TLS the connector turns the url away as "scheme is not http"
+async fn https_error() -> reqwest::Error {
+    use crate::{ReqwestTransport, WandboxError, WandboxTransport};
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let transport = ReqwestTransport::default();
+    match transport.get_json(&format!("https://127.0.0.1:{}/api/list.json", port)).await {
+        Err(WandboxError::Network(e)) => e,
+        other => panic!("expected a network error, got {:?}", other.map(|r| r.status))
+    }
+}
+
+// CI runs the tests under each TLS backend & none, with the default features,
+// `--no-default-features --features rustls` & `--no-default-features`
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+#[tokio::test]
+async fn tls_backend_reaches_https() {
+    // the connection is attempted, it's only refused because nothing is listening
+    let err = https_error().await;
+    assert!(err.is_connect() && !err.to_string().contains("scheme is not http"), "{}", err);
+}
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+#[tokio::test]
+async fn no_tls_backend_refuses_https() {
+    let err = https_error().await;
+    assert!(err.to_string().contains("scheme is not http"), "{}", err);
+}
+
+#[tokio::test]
+async fn cache_file_roundtrip() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let path = std::env::temp_dir().join(format!("wandbox-cache-{}.json", std::process::id()));
+    wbox.save_cache(&path)?;
+    let loaded = Wandbox::from_cache_file(&path);
+    std::fs::remove_file(&path)?;
+    let loaded = loaded?;
+
+    assert!(loaded.is_valid_compiler_str("gcc-head"));
+    assert_eq!(loaded.get_compiler_language_str("gcc-head"), Some(String::from("c++")));
+    assert_eq!(loaded.get_languages().len(), wbox.get_languages().len());
+
+    // timestamps are stored with second precision
+    let drift = wbox.fetched_at().duration_since(loaded.fetched_at())?;
+    assert!(drift.as_secs() < 1);
+
+    Ok(())
+}
+
+#[test]
+fn cache_file_rejects_unknown_version() {
+    let path = std::env::temp_dir().join(format!("wandbox-cache-version-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"version":0,"fetched_at":0,"languages":{}}"#).unwrap();
+    let loaded = Wandbox::from_cache_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    match loaded {
+        Ok(_wbox) => panic!("version 0 is not a supported cache format"),
+        Err(e) => assert!(matches!(e, crate::WandboxError::UnsupportedCacheVersion { found : Some(0), .. }))
+    }
+}
+
+#[test]
+fn cache_json_roundtrip() -> Result<(), Box<dyn Error>> {
+    use crate::{CompilerCache, Language, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    // languages & the cache round-trip on their own
+    let languages = wbox.get_languages();
+    let json = serde_json::to_string(&languages)?;
+    assert_eq!(serde_json::from_str::<Vec<Language>>(&json)?, languages);
+    let cache : CompilerCache = wbox.with_cache(|cache| cache.clone());
+    let json = serde_json::to_string(&cache)?;
+    assert_eq!(serde_json::from_str::<CompilerCache>(&json)?, cache);
+
+    // and with the versioned wrapper
+    let json = wbox.cache_json()?;
+    let value : serde_json::Value = serde_json::from_str(&json)?;
+    assert_eq!(value["version"], 1);
+    assert!(value["fetched_at"].is_u64());
+    let loaded = Wandbox::from_cache_json(&json)?;
+    assert_eq!(loaded.with_cache(|cache| cache.clone()), cache);
+
+    let err = Wandbox::from_cache_json(r#"{"version":2,"fetched_at":0,"languages":{}}"#).err().expect("version 2 is unsupported");
+    assert!(matches!(err, WandboxError::UnsupportedCacheVersion { found : Some(2), expected : 1 }));
+    assert_eq!(err.to_string(), "Cache file has format version 2, expected 1");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refresh_reapplies_blocklists() -> Result<(), Box<dyn Error>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::WandboxBuilder;
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    // the second fetch drops clang-head and adds gcc-11.1.0
+    let mut list : Vec<serde_json::Value> = serde_json::from_str(LIST_FIXTURE)?;
+    list.retain(|c| c["name"] != "clang-head");
+    let mut added = list[0].clone();
+    added["name"] = serde_json::Value::from("gcc-11.1.0");
+    list.push(added);
+    let refreshed = serde_json::to_string(&list)?;
+
+    let fetches = AtomicUsize::new(0);
+    let server = MockServer::start(move |_req| {
+        if fetches.fetch_add(1, Ordering::SeqCst) == 0 {
+            MockResponse::json(LIST_FIXTURE)
+        }
+        else {
+            MockResponse::json(&refreshed)
+        }
+    });
+
+    let mut blocked = HashSet::new();
+    blocked.insert(String::from("gcc-head"));
+    let wbox : Wandbox = WandboxBuilder::new()
+        .endpoint(&server.url())
+        .blocked_compilers(blocked)
+        .build().await?;
+    assert!(wbox.is_valid_compiler_str("clang-head"));
+
+    let diff = wbox.refresh().await?;
+    assert_eq!(diff.added.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["gcc-11.1.0"]);
+    assert_eq!(diff.removed.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["clang-head"]);
+    assert!(diff.changed.is_empty());
+    assert!(wbox.is_valid_compiler_str("gcc-11.1.0"));
+    assert!(!wbox.is_valid_compiler_str("clang-head"));
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn conditional_refresh() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let server = MockServer::start(|req| {
+        if req.path != "/api/list.json" {
+            return MockResponse::status(404, "");
+        }
+        if req.header("If-None-Match") == Some("\"v1\"") {
+            return MockResponse::status(304, "");
+        }
+        let mut response = MockResponse::json(LIST_FIXTURE);
+        response.headers.push((String::from("ETag"), String::from("\"v1\"")));
+        response.headers.push((String::from("Last-Modified"), String::from("Wed, 21 Oct 2015 07:28:00 GMT")));
+        response
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let before = wbox.stats();
+
+    let diff = wbox.refresh().await?;
+    assert!(diff.not_modified);
+    assert!(diff.is_empty());
+    assert_eq!(wbox.stats().compilers, before.compilers);
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("If-None-Match"), None);
+    assert_eq!(requests[1].header("If-None-Match"), Some("\"v1\""));
+    assert_eq!(requests[1].header("If-Modified-Since"), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+
+    // without validators every refresh downloads the list
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    assert!(!wbox.refresh().await?.not_modified);
+    assert_eq!(server.requests()[1].header("If-None-Match"), None);
+    assert_eq!(server.requests()[1].header("If-Modified-Since"), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn auto_refresh() -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+    use crate::{WandboxBuilder, RefreshStatus};
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = WandboxBuilder::new()
+        .endpoint(&server.url())
+        .auto_refresh(Duration::from_millis(50))
+        .build().await?;
+
+    let mut status = wbox.refresh_status().expect("auto refresh is enabled");
+    assert_eq!(*status.borrow(), RefreshStatus::Idle);
+    tokio::time::timeout(Duration::from_secs(5), status.changed()).await??;
+    assert_eq!(*status.borrow(), RefreshStatus::Refreshed(Default::default()));
+
+    // the task stops fetching once the instance is gone
+    drop(wbox);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let fetches = server.requests().len();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(server.requests().len(), fetches);
+
+    Ok(())
+}
+
+#[test]
+fn embedded_snapshot() -> Result<(), Box<dyn Error>> {
+    let mut set : HashSet<String> = HashSet::new();
+    set.insert(String::from("gcc-head"));
+    let mut langs : HashSet<String> = HashSet::new();
+    langs.insert(String::from("rill"));
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(Some(set), Some(langs))?;
+    assert!(wbox.is_valid_language("c++"));
+    assert!(wbox.is_valid_compiler_str("gcc-10.1.0"));
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(!wbox.is_valid_language("rill"));
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("python");
+    builder.code("print('test')");
+    builder.build(&wbox)?;
+
+    Ok(())
+}
+
+#[test]
+fn offline_construction() -> Result<(), Box<dyn Error>> {
+    use crate::CompilerCache;
+    use crate::tests::mock::LIST_FIXTURE;
+
+    let check = |wbox : &Wandbox| -> Result<(), Box<dyn Error>> {
+        assert_eq!(wbox.get_languages().len(), 6);
+        for lang in wbox.get_languages() {
+            assert!(wbox.get_default_compiler(&lang.name).is_some());
+            assert!(lang.compilers.iter().all(|c| c.language == lang.name));
+        }
+        assert!(wbox.is_valid_compiler_str("gcc-head"));
+        assert!(wbox.is_valid_language("bash script"));
+        assert_eq!(wbox.get_compiler_language_str("clang-head"), Some(String::from("c++")));
+        assert_eq!(wbox.get_display_name("gcc-head"), Some(String::from("gcc HEAD")));
+
+        let mut builder = crate::CompilationBuilder::new();
+        builder.target("C++");
+        builder.code("int main() {}");
+        assert_eq!(builder.build(wbox)?.compiler(), "gcc-head");
+        Ok(())
+    };
+
+    let wbox = Wandbox::from_list_json(LIST_FIXTURE, None, None)?;
+    check(&wbox)?;
+
+    let mut blocked : HashSet<String> = HashSet::new();
+    blocked.insert(String::from("gcc-head"));
+    let wbox = Wandbox::from_list_json(LIST_FIXTURE, Some(blocked.clone()), None)?;
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+
+    // a hand built cache is grouped by lowercase language as it's filled
+    let compilers : Vec<crate::Compiler> = serde_json::from_str(LIST_FIXTURE)?;
+    let mut cache = CompilerCache::new();
+    for c in compilers {
+        cache.insert_compiler(c);
+    }
+    assert!(cache.contains_key("c++"));
+    check(&Wandbox::from_cache(cache.clone(), None, None))?;
+    assert!(!Wandbox::from_cache(cache, Some(blocked), None).is_valid_compiler_str("gcc-head"));
+
+    assert!(Wandbox::from_list_json("{}", None, None).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn get_language_of() -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let lang = wbox.get_language_of("clang-head").expect("clang-head is available");
+    assert_eq!(&*lang.name, "c++");
+    assert!(lang.compilers.iter().any(|c| c.name == "clang-head"));
+    assert!(Arc::ptr_eq(&lang.compilers, &wbox.get_compilers("c++").unwrap()));
+
+    assert!(wbox.get_language_of("not-a-compiler").is_none());
+    wbox.block_compiler("clang-head");
+    assert!(wbox.get_language_of("clang-head").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn target_for_filename() -> Result<(), Box<dyn Error>> {
+    use crate::language_for_extension;
+
+    assert_eq!(language_for_extension("cpp"), Some("c++"));
+    assert_eq!(language_for_extension(".RS"), Some("rust"));
+    assert_eq!(language_for_extension("py"), Some("python"));
+    assert_eq!(language_for_extension("docx"), None);
+    assert_eq!(language_for_extension(""), None);
+
+    let mut blocked : HashSet<String> = HashSet::new();
+    blocked.insert(String::from("python"));
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, Some(blocked))?;
+    assert_eq!(wbox.target_for_filename("main.rs"), Some(String::from("rust")));
+    assert_eq!(wbox.target_for_filename("src/Foo.CPP"), Some(String::from("c++")));
+    assert_eq!(wbox.target_for_filename("run.sh"), Some(String::from("bash script")));
+    assert_eq!(wbox.target_for_filename("script.py"), None);
+    assert_eq!(wbox.target_for_filename("notes.docx"), None);
+    assert_eq!(wbox.target_for_filename("Makefile"), None);
+
+    // every mapped language is one wandbox has
+    let unblocked = Wandbox::from_embedded_snapshot(None, None)?;
+    for ext in ["c", "h", "cs", "go", "hs", "java", "js", "ts", "rb", "php", "pl", "lua", "swift", "scala",
+        "d", "nim", "cr", "ml", "r", "sql", "groovy", "jl", "ex", "erl", "lisp", "pas", "vim", "rill"] {
+        let lang = language_for_extension(ext).expect("extension is mapped");
+        assert!(unblocked.is_valid_language(lang), "{} maps to unknown language {}", ext, lang);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn detect_language() -> Result<(), Box<dyn Error>> {
+    use crate::detect_language;
+
+    let cases : &[(&str, Option<&str>)] = &[
+        ("#include <iostream>\nint main() { std::cout << 1; }", Some("c++")),
+        ("#include <stdio.h>\nint main() { printf(\"1\"); }", Some("c")),
+        ("use std::io;\n\nfn main() {\n    println!(\"1\");\n}", Some("rust")),
+        ("package main\n\nimport \"fmt\"\n\nfunc main() {\n\tfmt.Println(1)\n}", Some("go")),
+        ("def main():\n    print(1)\n\nif __name__ == '__main__':\n    main()", Some("python")),
+        ("#!/usr/bin/env python3\nprint(1)", Some("python")),
+        ("#!/bin/bash\necho 1", Some("bash script")),
+        ("public class Main {\n    public static void main(String[] args) {\n        System.out.println(1);\n    }\n}", Some("java")),
+        ("using System;\nclass Program { static void Main() { Console.WriteLine(1); } }", Some("c#")),
+        ("const x = 1;\nconsole.log(x);", Some("javascript")),
+        ("interface Point { x: number }\nconst p: Point = { x: 1 };\nconsole.log(p);", Some("typescript")),
+        ("def greet\n  puts 'hi'\nend", Some("ruby")),
+        ("<?php\necho 1;", Some("php")),
+        ("main :: IO ()\nmain = putStrLn \"1\"", Some("haskell")),
+        ("use strict;\nmy $x = 1;\nprint $x;", Some("perl")),
+        ("local x = 1\nprint(x)", Some("lua")),
+        // too little to go on, or hints for several languages
+        ("print(1)", None),
+        ("", None),
+        ("fn main() {}\ndef helper():\n    pass", None),
+        ("#!/usr/bin/weird-shell\nfoo", None),
+    ];
+    for (code, expected) in cases {
+        assert_eq!(detect_language(code), *expected, "detected the wrong language for {:?}", code);
+    }
+
+    // builds can fall back to detection
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.code("fn main() {\n    println!(\"1\");\n}");
+    assert!(matches!(builder.clone().build(&wbox), Err(crate::WandboxError::Build(crate::BuildError::MissingTarget))));
+    builder.auto_detect(true);
+    assert_eq!(builder.build(&wbox)?.lang(), "rust");
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.code("print(1)");
+    builder.auto_detect(true);
+    assert!(matches!(builder.build(&wbox), Err(crate::WandboxError::Build(crate::BuildError::MissingTarget))));
+
+    Ok(())
+}
+
+#[test]
+fn no_panics_on_empty_languages_or_poisoned_locks() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    for c in wbox.get_compilers("python").expect("python is available").iter() {
+        wbox.block_compiler(&c.name);
+    }
+    assert!(!wbox.is_valid_language("python"));
+    assert!(wbox.get_languages().iter().all(|l| &*l.name != "python"));
+    assert_eq!(wbox.get_default_compiler("python"), None);
+    assert_eq!(wbox.get_latest_compiler("python"), None);
+    assert!(wbox.get_compilers("python").is_none());
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("python");
+    builder.code("print(1)");
+    assert!(builder.build(&wbox).is_err());
+
+    // a panic while the cache is locked for writing doesn't take the instance down with it
+    let cache = wbox.cache.clone();
+    let poisoned = std::thread::spawn(move || {
+        let _lock = cache.write().unwrap();
+        panic!("poisoning the cache");
+    }).join();
+    assert!(poisoned.is_err());
+    assert!(wbox.cache.is_poisoned());
+    assert!(wbox.is_valid_language("c++"));
+    wbox.block_language("c++");
+    assert!(!wbox.is_valid_language("c++"));
+
+    Ok(())
+}
+
+#[test]
+fn shared_compilers() -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let first = wbox.get_compilers("c++").expect("c++ is available");
+    let second = wbox.get_compilers("c++").expect("c++ is available");
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(first.iter().all(|c| &*c.language == "c++"));
+
+    // a filter that keeps every compiler keeps sharing them too
+    let languages = wbox.get_languages();
+    let cpp = languages.iter().find(|l| &*l.name == "c++").expect("c++ is available");
+    assert!(Arc::ptr_eq(&cpp.compilers, &first));
+
+    Ok(())
+}
+
+/// A rough estimate of the heap bytes held by the language names of a cache, counting each
+/// shared copy once, along with what they'd take if every compiler owned its own
+fn language_heap_estimate(cache : &crate::CompilerCache) -> (usize, usize) {
+    use std::collections::HashSet;
+
+    // the strong & weak counts in front of every Arc<str>
+    const ARC_HEADER : usize = 2 * std::mem::size_of::<usize>();
+    // allocators hand out memory in chunks of at least this many bytes
+    let allocation = |len : usize| len.div_ceil(16) * 16;
+
+    let mut seen = HashSet::new();
+    let mut shared = 0;
+    let mut owned = 0;
+    let names = cache.values()
+        .flat_map(|l| std::iter::once(&l.name).chain(l.compilers.iter().map(|c| &c.language)));
+    for name in names {
+        owned += allocation(name.len());
+        if seen.insert(name.as_ptr()) {
+            shared += allocation(ARC_HEADER + name.len());
+        }
+    }
+    (shared, owned)
+}
+
+#[test]
+fn interned_languages() -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    wbox.with_cache(|cache| {
+        for lang in cache.values() {
+            assert!(lang.compilers.iter().all(|c| Arc::ptr_eq(&c.language, &lang.name)));
+        }
+
+        let (shared, owned) = language_heap_estimate(cache);
+        println!("language names: {} bytes interned, {} bytes with a copy per compiler", shared, owned);
+        assert!(shared < owned);
+    });
+
+    // a cache read back from json shares its names too
+    let wbox = Wandbox::from_cache_json(&wbox.cache_json()?)?;
+    wbox.with_cache(|cache| {
+        for lang in cache.values() {
+            assert!(lang.compilers.iter().all(|c| Arc::ptr_eq(&c.language, &lang.name)));
+        }
+    });
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn interned_while_parsing() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use crate::fetch_compiler_list;
+
+    // shared as list.json is read, before the compilers are grouped by language
+    let server = MockServer::wandbox("{}");
+    let compilers = fetch_compiler_list(Some(&server.url()), None).await?;
+    let find = |name : &str| compilers.iter().find(|c| c.name == name).expect("the fixture lists it");
+    let (head, other) = (find("gcc-head"), find("gcc-10.1.0"));
+    assert!(Arc::ptr_eq(&head.language, &other.language));
+    assert!(Arc::ptr_eq(&head.display_compile_command, &other.display_compile_command));
+    assert!(!Arc::ptr_eq(&head.language, &find("gcc-head-c").language));
+
+    // as is a cache read back from json
+    let wbox = Wandbox::from_cache_json(&Wandbox::from_embedded_snapshot(None, None)?.cache_json()?)?;
+    let mut commands : HashMap<String, Vec<Arc<str>>> = HashMap::new();
+    wbox.for_each_compiler(|_lang, c| commands.entry(c.display_compile_command.to_string()).or_default().push(c.display_compile_command.clone()));
+    assert!(commands.values().any(|shared| shared.len() > 1));
+    assert!(commands.values().all(|shared| shared.iter().all(|c| Arc::ptr_eq(c, &shared[0]))));
+
+    Ok(())
+}
+
+#[test]
+fn cache_visitors() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let expected : usize = wbox.get_languages().iter().map(|l| l.compilers.len()).sum();
+
+    let mut count = 0;
+    wbox.for_each_compiler(|lang, c| {
+        assert_eq!(lang, &*c.language);
+        count += 1;
+    });
+    assert_eq!(count, expected);
+
+    assert_eq!(wbox.with_cache(|cache| cache.values().map(|l| l.compilers.len()).sum::<usize>()), expected);
+    assert!(wbox.with_cache(|cache| cache.contains_key("c++")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sorted_listings() -> Result<(), Box<dyn Error>> {
+    use crate::SortBy;
+    use crate::tests::mock::MockServer;
+
+    let names = |wbox : &Wandbox| wbox.get_languages().into_iter().map(|l| l.name.to_string()).collect::<Vec<String>>();
+    let first = Wandbox::from_embedded_snapshot(None, None)?;
+    let second = Wandbox::from_embedded_snapshot(None, None)?;
+    assert_eq!(names(&first), names(&second));
+    let mut sorted = names(&first);
+    sorted.sort();
+    assert_eq!(names(&first), sorted);
+
+    let server = MockServer::wandbox(r#"{"status":"0"}"#);
+    let wbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let sorted = |sort| wbox.get_compilers_sorted("c++", sort).unwrap().into_iter().map(|c| c.name).collect::<Vec<String>>();
+    assert_eq!(sorted(SortBy::Name), vec!["clang-10.0.0", "clang-head", "gcc-10.1.0", "gcc-9.3.0", "gcc-head"]);
+    assert_eq!(sorted(SortBy::Version { head_first : false }), vec!["gcc-10.1.0", "clang-10.0.0", "gcc-9.3.0", "clang-head", "gcc-head"]);
+    assert_eq!(sorted(SortBy::Version { head_first : true })[2..], ["gcc-10.1.0", "clang-10.0.0", "gcc-9.3.0"]);
+    assert!(wbox.get_compilers_sorted("cobol", SortBy::Name).is_none());
+
+    // the cache keeps Wandbox's order
+    assert_eq!(wbox.get_compilers("c++").unwrap()[0].name, "gcc-head");
+    assert_eq!(wbox.get_default_compiler("c++"), Some(String::from("gcc-head")));
+
+    Ok(())
+}
+
+#[test]
+fn search_compilers() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let clang = wbox.search_compilers("clang");
+    assert!(!clang.is_empty());
+    assert!(clang.iter().all(|c| c.name.contains("clang") || c.display_name.to_lowercase().contains("clang")));
+    // prefix matches come before compilers that only mention clang
+    let prefixed = clang.iter().take_while(|c| c.name.starts_with("clang")).count();
+    assert!(prefixed > 0);
+    assert!(clang[prefixed..].iter().all(|c| !c.name.starts_with("clang")));
+
+    let head = wbox.search_compilers("HEAD");
+    assert!(head.iter().any(|c| c.name == "gcc-head"));
+    assert!(head.iter().all(|c| c.name.contains("head") || c.display_name.contains("HEAD")));
+
+    let python = wbox.search_compilers("python 3");
+    let names : Vec<&str> = python.iter().map(|c| c.name.as_str()).collect();
+    assert!(names.contains(&"cpython-3.9.3"));
+    assert!(names.contains(&"pypy-3.7-v7.3.4"));
+    assert!(!names.contains(&"cpython-2.7.17"));
+
+    assert!(wbox.search_compilers("").is_empty());
+    assert!(wbox.search_compilers("   ").is_empty());
+    assert!(wbox.search_compilers("no such compiler").is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn structured_errors() -> Result<(), Box<dyn Error>> {
+    use std::error::Error as _;
+    use crate::WandboxError;
+    use crate::tests::mock::{MockServer, MockResponse, LIST_FIXTURE};
+
+    let server = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            MockResponse::json(LIST_FIXTURE)
+        }
+        else {
+            MockResponse::status(500, "Internal Server Error")
+        }
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("not-a-compiler").code("int main() {}");
+    match builder.build(&wbox) {
+        Err(crate::WandboxError::Build(crate::BuildError::UnknownTarget { input : target, .. })) => assert_eq!(target, "not-a-compiler"),
+        _ => panic!("expected an unknown target")
+    }
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++");
+    builder.code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+    match compilation.dispatch().await {
+        Err(WandboxError::Http { status, body }) => {
+            assert_eq!(status, 500);
+            assert_eq!(body, "Internal Server Error");
+        }
+        _ => panic!("expected an http error")
+    }
+
+    let unreachable = Wandbox::with_endpoint("http://127.0.0.1:1", None, None).await;
+    match unreachable {
+        Ok(_wbox) => panic!("nothing listens on port 1"),
+        Err(e) => {
+            assert!(matches!(e, WandboxError::Network(_)));
+            assert!(e.source().is_some());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn compiler_switches() -> Result<(), Box<dyn Error>> {
+    use crate::{Compiler, Switch};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let switches = wbox.get_switches("gcc-head").expect("gcc-head is available");
+    assert!(switches.iter().any(|s| matches!(s, Switch::Single { name, default : true, .. } if name == "warning")));
+    assert!(switches.iter().any(|s| matches!(s, Switch::Select { .. }) && s.names().contains(&"c++2a")));
+    assert!(wbox.get_switches("not-a-compiler").is_none());
+
+    // new fields and switch kinds don't break deserialization
+    let compiler : Compiler = serde_json::from_str(r#"{
+        "compiler-option-raw": true, "display-compile-command": "g++ prog.cc", "runtime-option-raw": false,
+        "version": "10.1.0", "language": "C++", "name": "gcc-10.1.0",
+        "switches": [
+            {"type": "single", "name": "warning", "default": true, "display-name": "Warnings", "display-flags": "-Wall", "new-field": 1},
+            {"type": "multiple", "name": "sanitizers"}
+        ]
+    }"#)?;
+    assert_eq!(compiler.switches.len(), 2);
+    assert_eq!(compiler.switches[1], Switch::Unknown);
+
+    Ok(())
+}
+
+#[test]
+fn compiler_display_name() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    assert_eq!(wbox.get_display_name("gcc-head"), Some(String::from("gcc HEAD")));
+    assert_eq!(wbox.get_display_name("not-a-compiler"), None);
+
+    // caches written before display names existed fall back to the id
+    let (cache, _warnings) = crate::cache::parse(r#"[{"compiler-option-raw": true, "display-compile-command": "g++ prog.cc",
+        "runtime-option-raw": false, "version": "10.1.0", "language": "C++", "name": "gcc-10.1.0"}]"#, false)?;
+    assert_eq!(cache["c++"].compilers[0].display_name, "gcc-10.1.0");
+    assert_eq!(format!("{:?}", cache["c++"].compilers[0]), "[gcc-10.1.0 (gcc-10.1.0) 10.1.0] : c++");
+
+    Ok(())
+}
+
+#[test]
+fn get_compiler() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let compiler = wbox.get_compiler("clang-10.0.0").expect("clang-10.0.0 is available");
+    assert_eq!(compiler.name, "clang-10.0.0");
+    assert_eq!(compiler.version, "10.0.0");
+    assert_eq!(&*compiler.language, "c++");
+    assert!(compiler.compiler_option_raw);
+
+    assert!(wbox.get_compiler("CLANG-10.0.0").is_none());
+    assert!(wbox.get_compiler("clang-0.0.0").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn compiler_index() -> Result<(), Box<dyn Error>> {
+    let mut blocked = HashSet::new();
+    blocked.insert(String::from("gcc-head"));
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(Some(blocked), None)?;
+
+    // positions past the removed compiler must still point at the right entry
+    let index = wbox.index.read().unwrap().clone();
+    assert!(!index.contains_key("gcc-head"));
+    for (name, (lang, _i)) in index.iter() {
+        assert_eq!(wbox.get_compiler(name).map(|c| c.name), Some(name.clone()));
+        assert_eq!(wbox.get_compiler_language_str(name).as_ref(), Some(lang));
+    }
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(wbox.is_valid_compiler_str("gcc-11.1.0"));
+    assert_eq!(wbox.get_compiler_language_str("cpython-3.8.0"), Some(String::from("python")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn language_aliases() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, WandboxError};
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    assert_eq!(wbox.resolve_language("cpp"), Some(String::from("c++")));
+    assert_eq!(wbox.resolve_language("Python"), Some(String::from("python")));
+    assert_eq!(wbox.resolve_language("cobol"), None);
+
+    // aliases can't shadow real languages or compiler ids
+    assert!(matches!(wbox.add_alias("c", "c++"), Err(WandboxError::AliasConflict(_))));
+    assert!(matches!(wbox.add_alias("bash", "c++"), Err(WandboxError::AliasConflict(_))));
+    assert!(matches!(wbox.add_alias("pasta", "cobol"), Err(WandboxError::Build(BuildError::UnknownTarget { .. }))));
+    wbox.add_alias("pasta", "cpp")?;
+
+    for (target, lang, compiler) in [("cpp", "c++", "gcc-head"), ("rs", "rust", "rust-head"), ("pasta", "c++", "gcc-head")] {
+        let mut builder = crate::CompilationBuilder::new();
+        builder.target(target);
+        builder.code("fn main() {}");
+        let compilation = builder.build(&wbox)?;
+        assert_eq!(compilation.lang(), lang);
+        assert_eq!(compilation.compiler(), compiler);
+
+        let res = compilation.dispatch().await?;
+        assert_eq!(res.program_all, "test");
+    }
+
+    let requests = server.requests();
+    assert!(requests[3].body.contains("\"compiler\":\"gcc-head\""));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn case_insensitive_targets() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    for (target, lang, compiler) in [("C++", "c++", "gcc-head"), ("Python", "python", "cpython-3.8.0"),
+                                     ("GCC-10.1.0", "c++", "gcc-10.1.0"), ("Clang-Head", "c++", "clang-head")] {
+        let mut builder = crate::CompilationBuilder::new();
+        builder.target(target);
+        builder.code("int main() {}");
+        let compilation = builder.build(&wbox)?;
+        assert_eq!(compilation.lang(), lang);
+        assert_eq!(compilation.compiler(), compiler);
+    }
+
+    // the request carries the id exactly as Wandbox knows it
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("GCC-HEAD");
+    builder.code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+    compilation.dispatch().await?;
+    assert!(server.requests()[1].body.contains("\"compiler\":\"gcc-head\""));
+
+    Ok(())
+}
+
+#[test]
+fn resolve_compiler_prefix() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    assert_eq!(wbox.resolve_compiler("gcc-10.1.0")?, "gcc-10.1.0");
+    assert_eq!(wbox.resolve_compiler("gcc-head")?, "gcc-head");
+    assert_eq!(wbox.resolve_compiler("gcc-10")?, "gcc-10.2.0");
+    assert_eq!(wbox.resolve_compiler("GCC")?, "gcc-11.1.0");
+    assert_eq!(wbox.resolve_compiler("cpython-3")?, "cpython-3.9.3");
+    assert!(matches!(wbox.resolve_compiler("gcc-1"), Err(WandboxError::Build(BuildError::UnknownTarget { .. }))));
+
+    match wbox.resolve_compiler("clang-10") {
+        Err(WandboxError::Build(BuildError::AmbiguousTarget { candidates, .. })) => assert_eq!(candidates, vec!["clang-10.0.0", "clang-10.0.0-c"]),
+        other => panic!("expected an ambiguous target, got {:?}", other)
+    }
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("clang-11").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.compiler(), "clang-11.0.0");
+    assert_eq!(compilation.lang(), "c++");
+
+    Ok(())
+}
+
+#[test]
+fn version_ordering() {
+    use std::cmp::Ordering;
+    use crate::version::compare;
+
+    assert_eq!(compare("10.2.0", "9.3.0"), Ordering::Greater);
+    assert_eq!(compare("7.0.0 (trunk)", "7.0.0"), Ordering::Equal);
+    assert_eq!(compare("jdk-15.0.2+7", "jdk-17+21"), Ordering::Less);
+    assert_eq!(compare("devel +a0a3c9e1e5", "1.16.3"), Ordering::Less);
+}
+
+#[test]
+fn parsed_versions() -> Result<(), Box<dyn Error>> {
+    use crate::Version;
+
+    let v = |major, minor, patch| Some(Version { major, minor, patch });
+    assert_eq!(Version::parse("10.1.0"), v(10, 1, 0));
+    assert_eq!(Version::parse("7.0.0 (trunk)"), v(7, 0, 0));
+    assert_eq!(Version::parse("HEAD 12.0.0"), v(12, 0, 0));
+    assert_eq!(Version::parse("12.0.0 20210529 (experimental)"), v(12, 0, 0));
+    assert_eq!(Version::parse("13.0.0 (https://github.com/llvm/llvm-project.git 4bd7b7d3c7b6 )"), v(13, 0, 0));
+    assert_eq!(Version::parse("3.10.0a7+"), v(3, 10, 0));
+    assert_eq!(Version::parse("5.0.17(1)-release"), v(5, 0, 17));
+    assert_eq!(Version::parse("24.0-rc3"), v(24, 0, 0));
+    assert_eq!(Version::parse("jdk-17+21"), v(17, 0, 0));
+    assert_eq!(Version::parse("v2.097.0-beta.1"), v(2, 97, 0));
+    assert_eq!(Version::parse("3.7.10 [PyPy 7.3.4]"), v(3, 7, 10));
+    assert_eq!(Version::parse("devel +a0a3c9e1e5"), None);
+    assert_eq!(Version::parse("HEAD"), None);
+    assert_eq!(Version::parse(""), None);
+    assert!(Version::parse("10.1.0") > Version::parse("9.3.0"));
+    assert!(Version::parse("HEAD") < Version::parse("0.0.1"));
+    assert_eq!(Version::parse("1.54.0-nightly").unwrap().to_string(), "1.54.0");
+
+    // every compiler wandbox lists sorts, and most have a version number
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let mut unparsed = Vec::new();
+    wbox.for_each_compiler(|_lang, c| {
+        if c.parsed_version().is_none() {
+            unparsed.push(c.version.clone());
+        }
+    });
+    assert_eq!(unparsed, vec![String::from("devel +a0a3c9e1e5")]);
+
+    Ok(())
+}
+
+#[test]
+fn compilers_in_range() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let names = |compilers : Vec<crate::Compiler>| compilers.into_iter().map(|c| c.name).collect::<Vec<String>>();
+
+    // bounds are inclusive, as precise as they're written
+    assert_eq!(names(wbox.get_compilers_in_range("gcc", Some("7"), Some("10"), false)),
+        vec!["gcc-10.2.0", "gcc-10.1.0", "gcc-10.1.0-c", "gcc-9.3.0", "gcc-9.3.0-c", "gcc-8.4.0", "gcc-7.5.0"]);
+    assert_eq!(names(wbox.get_compilers_in_range("gcc", Some("7.5.0"), Some("10.1"), false)),
+        vec!["gcc-10.1.0", "gcc-10.1.0-c", "gcc-9.3.0", "gcc-9.3.0-c", "gcc-8.4.0", "gcc-7.5.0"]);
+    assert_eq!(names(wbox.get_compilers_in_range("gcc", Some("7.5.1"), Some("10.0.9"), false)),
+        vec!["gcc-9.3.0", "gcc-9.3.0-c", "gcc-8.4.0"]);
+
+    // head builds only when asked for
+    let newest = wbox.get_compilers_in_range("c++", Some("11"), None, false);
+    assert!(newest.iter().all(|c| !c.is_head()));
+    assert!(newest.iter().any(|c| c.name == "gcc-11.1.0"));
+    let newest = wbox.get_compilers_in_range("cpp", Some("11"), None, true);
+    assert!(newest.iter().any(|c| c.name == "gcc-head"));
+
+    // the family has to match a whole prefix of the id
+    assert!(wbox.get_compilers_in_range("gc", None, None, true).is_empty());
+    assert_eq!(wbox.get_compilers_in_range("gcc", None, None, true).len(), 13);
+
+    Ok(())
+}
+
+#[test]
+fn cache_stats() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let before = wbox.stats();
+    assert_eq!(before.languages, wbox.get_languages().len());
+    assert_eq!(before.compilers, before.per_language.values().sum::<usize>());
+    assert_eq!(before.fetched_at, wbox.fetched_at());
+    let python = before.per_language["python"];
+    assert!(python > 0);
+
+    wbox.block_language("python");
+    let after = wbox.stats();
+    assert_eq!(after.languages, before.languages - 1);
+    assert_eq!(after.compilers, before.compilers - python);
+    assert!(!after.per_language.contains_key("python"));
+
+    wbox.block_compiler("gcc-head");
+    assert_eq!(wbox.stats().per_language["c++"], before.per_language["c++"] - 1);
+
+    Ok(())
+}
+
+#[test]
+fn get_latest_compiler() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let latest = wbox.get_latest_compiler("c++").expect("c++ has released compilers");
+    assert!(!latest.contains("head"));
+    assert_eq!(latest, "clang-12.0.0");
+    assert_eq!(wbox.get_latest_compiler("python"), Some(String::from("cpython-3.9.3")));
+    assert_eq!(wbox.get_latest_compiler("rust"), Some(String::from("rust-1.52.1")));
+    assert_eq!(wbox.get_latest_compiler("rill"), None);
+    assert_eq!(wbox.get_latest_compiler("cobol"), None);
+
+    Ok(())
+}
+
+#[test]
+fn default_strategy() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use crate::{DefaultStrategy, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    assert_eq!(wbox.get_default_compiler("c++"), Some(String::from("gcc-head")));
+
+    wbox.set_default_strategy(DefaultStrategy::LatestStable)?;
+    assert_eq!(wbox.get_default_compiler("c++"), Some(String::from("clang-12.0.0")));
+    // languages with only head builds still have a default
+    assert_eq!(wbox.get_default_compiler("rill"), Some(String::from("rill-head")));
+
+    let mut pins = HashMap::new();
+    pins.insert(String::from("cpp"), String::from("gcc-10.1.0"));
+    wbox.set_default_strategy(DefaultStrategy::Named(pins))?;
+    assert_eq!(wbox.get_default_compiler("c++"), Some(String::from("gcc-10.1.0")));
+    assert_eq!(wbox.get_default_compiler("python"), Some(String::from("cpython-head")));
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.compiler(), "gcc-10.1.0");
+
+    // bad pins are rejected up front & leave the previous strategy in place
+    let mut pins = HashMap::new();
+    pins.insert(String::from("c++"), String::from("cpython-3.8.0"));
+    assert!(matches!(wbox.set_default_strategy(DefaultStrategy::Named(pins)), Err(WandboxError::InvalidDefault { .. })));
+    let mut pins = HashMap::new();
+    pins.insert(String::from("c++"), String::from("gcc-0.0.0"));
+    assert!(matches!(wbox.set_default_strategy(DefaultStrategy::Named(pins)), Err(WandboxError::InvalidDefault { .. })));
+    assert_eq!(wbox.get_default_compiler("c++"), Some(String::from("gcc-10.1.0")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn allowlist() -> Result<(), Box<dyn Error>> {
+    use crate::WandboxBuilder;
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox("{}");
+    let comps : HashSet<String> = ["gcc-10.1.0", "clang-head", "cpython-3.8.0"].iter().map(|c| c.to_string()).collect();
+    let langs : HashSet<String> = ["rust"].iter().map(|l| l.to_string()).collect();
+    let mut blocked = HashSet::new();
+    blocked.insert(String::from("clang-head"));
+
+    let wbox : Wandbox = WandboxBuilder::new()
+        .endpoint(&server.url())
+        .allowed_compilers(comps)
+        .allowed_languages(langs)
+        .blocked_compilers(blocked)
+        .build().await?;
+
+    let mut languages : Vec<String> = wbox.get_languages().into_iter().map(|l| l.name.to_string()).collect();
+    languages.sort();
+    assert_eq!(languages, vec!["c++", "python", "rust"]);
+
+    assert!(wbox.is_valid_compiler_str("gcc-10.1.0"));
+    assert!(wbox.is_valid_compiler_str("rust-head"));
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(!wbox.is_valid_compiler_str("clang-head"));
+    assert_eq!(wbox.get_compilers("python").map(|c| c.len()), Some(1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn block_at_runtime() -> Result<(), Box<dyn Error>> {
+    use crate::tests::mock::MockServer;
+
+    let server = MockServer::wandbox("{}");
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+
+    wbox.block_compiler("gcc-head");
+    wbox.block_language("python");
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(!wbox.is_valid_language("python"));
+    assert!(wbox.get_compiler("gcc-10.1.0").is_some());
+    assert_eq!(wbox.get_default_compiler("c++"), Some(String::from("gcc-10.1.0")));
+
+    // a refresh doesn't bring blocked entries back
+    wbox.refresh().await?;
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(!wbox.is_valid_language("python"));
+
+    wbox.unblock_compiler("gcc-head");
+    wbox.unblock_language("python");
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+    assert_eq!(wbox.get_default_compiler("python"), Some(String::from("cpython-3.8.0")));
+
+    Ok(())
+}
+
+#[test]
+fn compilation_serialization() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-10.1.0")
+        .code("int main() {}")
+        .stdin("input")
+        .options(vec!["-Wall", "-Werror"])
+        .runtime_options(vec!["first"])
+        .switches(vec!["warning", "c++17"])
+        .save(true);
+    builder.add_file("util.h", "int x;")?;
+    let compilation = builder.build(&wbox)?;
+
+    // the wire format holds exactly the fields Wandbox accepts, nothing the builder tracks
+    assert_eq!(compilation.lang(), "c++");
+    let expected = serde_json::json!({
+        "compiler" : "gcc-10.1.0",
+        "code" : "int main() {}",
+        "codes" : [{"file" : "util.h", "code" : "int x;"}],
+        "stdin" : "input",
+        "compiler-option-raw" : "-Wall\n-Werror",
+        "runtime-option-raw" : "first",
+        "options" : "warning,c++17",
+        "save" : true
+    });
+    assert_eq!(serde_json::to_value(compilation.request())?, expected);
+    assert_eq!(compilation.to_json(), r#"{"compiler":"gcc-10.1.0","code":"int main() {}","codes":[{"file":"util.h","code":"int x;"}],"stdin":"input","compiler-option-raw":"-Wall\n-Werror","runtime-option-raw":"first","options":"warning,c++17","save":true}"#);
+
+    // without additional files `codes` is left out entirely
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}").no_default_switches();
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.to_json(), r#"{"compiler":"gcc-head","code":"int main() {}","stdin":"","compiler-option-raw":"","runtime-option-raw":"","options":"","save":false}"#);
+
+    Ok(())
+}
+
+#[test]
+fn code_from_file() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let path = std::env::temp_dir().join(format!("wandbox-code-{}.cpp", std::process::id()));
+    std::fs::write(&path, "int main() {}\n")?;
+    let mut builder = crate::CompilationBuilder::new();
+    let read = builder.code_file(&path).map(|_b| ());
+    std::fs::remove_file(&path)?;
+    read?;
+
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.lang(), "c++");
+    assert_eq!(compilation.request().code, "int main() {}\n");
+
+    // an explicit target is kept, & invalid UTF-8 is reported rather than mangled
+    let path = std::env::temp_dir().join(format!("wandbox-code-{}.c", std::process::id()));
+    std::fs::write(&path, [0x69, 0x6e, 0x74, 0xff, 0xfe])?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("python").code("print(1)");
+    let read = builder.code_file(&path).map(|_b| ());
+    std::fs::remove_file(&path)?;
+    assert_eq!(read.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(builder.build(&wbox)?.lang(), "python");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn code_is_sent_verbatim() -> Result<(), Box<dyn Error>> {
+    use crate::{MemoryTransport, TransportResponse};
+    use crate::tests::mock::LIST_FIXTURE;
+
+    let transport = MemoryTransport::new();
+    transport.respond("/api/list.json", TransportResponse::new(200, LIST_FIXTURE))
+        .respond("/api/compile.json", TransportResponse::new(200, r#"{"status":"0"}"#));
+    let wbox : Wandbox = Wandbox::new_with_transport(Box::new(transport.clone()), None, None).await?;
+
+    let code = "  \n#!/bin/sh\n\techo  padded \n";
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("bash").code(code);
+    builder.build(&wbox)?.dispatch().await?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("bash").code_trimmed(code);
+    builder.build(&wbox)?.dispatch().await?;
+
+    let requests = transport.requests();
+    assert_eq!(requests[1].method, "POST");
+    assert_eq!(requests[1].url, "https://wandbox.org/api/compile.json");
+    let sent : serde_json::Value = serde_json::from_str(requests[1].body.as_deref().unwrap_or_default())?;
+    assert_eq!(sent["code"], code);
+    let sent : serde_json::Value = serde_json::from_str(requests[2].body.as_deref().unwrap_or_default())?;
+    assert_eq!(sent["code"], "#!/bin/sh\n\techo  padded");
+
+    Ok(())
+}
+
+#[test]
+fn stdin_is_kept_verbatim() -> Result<(), Box<dyn Error>> {
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c").code("int main() {}").stdin("  padded  \n");
+    assert_eq!(builder.build(&wbox)?.request().stdin, "  padded  \n");
+
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c").code("int main() {}").append_stdin("3").append_stdin(" 1 2 3");
+    assert_eq!(builder.build(&wbox)?.request().stdin, "3\n 1 2 3\n");
+
+    Ok(())
+}
+
+#[test]
+fn build_validation() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let mut builder = CompilationBuilder::new();
+    builder.code("int main() {}");
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::MissingTarget))));
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("c++").code(" \n\t");
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::EmptyCode))));
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("cpython-3.8.0").code("print(1)").options(vec!["-O"]);
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::RawOptionsUnsupported { compiler : c })) if c == "cpython-3.8.0"));
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-10.1.0").code("int main() {}").switches(vec!["warning", "c++99"]);
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::UnknownSwitch { switch, .. })) if switch == "c++99"));
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("cpython-3.8.0").code("print(1)").switches(vec!["warning"]);
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::UnknownSwitch { compiler, .. })) if compiler == "cpython-3.8.0"));
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-10.1.0").code("int main() {}").options(vec!["-O2"]).switches(vec!["warning", "c++17", "boost-1.75.0-gcc-10.1.0"]);
+    assert!(builder.build(&wbox).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn build_suggestions() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let build = |target : &str| {
+        let mut builder = CompilationBuilder::new();
+        builder.target(target).code("int main() {}");
+        builder.build(&wbox).err().and_then(|e| e.build_error().cloned())
+    };
+
+    match build("gcc-haed") {
+        Some(e @ BuildError::UnknownTarget { .. }) => {
+            assert!(e.to_string().contains("did you mean gcc-head"), "{}", e);
+            match e {
+                BuildError::UnknownTarget { input, suggestions } => {
+                    assert_eq!(input, "gcc-haed");
+                    assert_eq!(suggestions.first().map(|s| s.as_str()), Some("gcc-head"));
+                    assert!(suggestions.len() <= 3);
+                }
+                _ => unreachable!()
+            }
+        }
+        other => panic!("expected an unknown target, got {:?}", other)
+    }
+
+    match build("c+") {
+        Some(BuildError::UnknownTarget { suggestions, .. }) => {
+            assert!(suggestions.contains(&String::from("c++")), "{:?}", suggestions);
+            assert!(suggestions.contains(&String::from("c")), "{:?}", suggestions);
+        }
+        other => panic!("expected an unknown target, got {:?}", other)
+    }
+
+    // nothing close enough to suggest
+    match build("zzzzzzzzzz") {
+        Some(e @ BuildError::UnknownTarget { .. }) => {
+            assert!(matches!(&e, BuildError::UnknownTarget { suggestions, .. } if suggestions.is_empty()));
+            assert!(!e.to_string().contains("did you mean"));
+            assert!(matches!(WandboxError::from(e), WandboxError::Build(BuildError::UnknownTarget { input, .. }) if input == "zzzzzzzzzz"));
+        }
+        other => panic!("expected an unknown target, got {:?}", other)
+    }
+
+    Ok(())
+}
+
+#[test]
+fn unsupported_raw_options() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    assert!(!wbox.get_compiler("rust-1.52.1").expect("rust-1.52.1 is available").runtime_option_raw);
+    assert!(!wbox.get_compiler("cpython-3.8.0").expect("cpython-3.8.0 is available").compiler_option_raw);
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("rust-1.52.1").code("fn main() {}").runtime_options(vec!["first"]);
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::RuntimeOptionsUnsupported { compiler : c })) if c == "rust-1.52.1"));
+
+    // lenient builds drop the options & say so
+    let mut builder = CompilationBuilder::new();
+    builder.target("cpython-3.8.0").code("print(1)")
+        .options(vec!["-O"])
+        .runtime_options(vec!["first"])
+        .lenient(true);
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.warnings().len(), 1);
+    assert!(compilation.warnings()[0].contains("-O"));
+    assert_eq!(compilation.request().compiler_options_raw, "");
+    assert_eq!(compilation.request().runtime_options_raw, "first");
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-10.1.0").code("int main() {}").options(vec!["-O2"]);
+    assert!(builder.build(&wbox)?.warnings().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn dry_run() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-10.1.0").code("int main() {}").options(vec!["-O2", "-Wall"]).save(true);
+    let dry = builder.dry_run(&wbox)?;
+    assert_eq!(dry.url, "https://wandbox.org/api/compile.json");
+
+    let body : serde_json::Value = serde_json::from_str(&dry.body)?;
+    assert_eq!(body["compiler"], "gcc-10.1.0");
+    assert_eq!(body["compiler-option-raw"], "-O2\n-Wall");
+    assert_eq!(body["save"], true);
+    assert!(dry.body.contains('\n'));
+
+    // the builder is left untouched, & a dry run of the built request matches
+    assert_eq!(builder.build(&wbox)?.dry_run(), dry);
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("cpython-3.8.0").code("print(1)").options(vec!["-O"]);
+    assert!(matches!(builder.dry_run(&wbox), Err(WandboxError::Build(BuildError::RawOptionsUnsupported { .. }))));
+
+    Ok(())
+}
+
+fn result_fixture(case : &str) -> crate::CompilationResult {
+    let fixtures : serde_json::Value = serde_json::from_str(include_str!("fixtures/results.json")).unwrap();
+    serde_json::from_value(fixtures[case].clone()).unwrap()
+}
+
+#[test]
+fn compilation_result_status() {
+    let res = result_fixture("success");
+    assert!(res.success() && res.compiled() && !res.was_signaled());
+    assert_eq!(res.exit_code(), Some(0));
+
+    // Wandbox only sends a status once the program has run
+    let res = result_fixture("compile_error");
+    assert!(!res.success() && !res.compiled());
+    assert_eq!(res.exit_code(), None);
+
+    let res = result_fixture("warning");
+    assert!(res.success() && res.compiled());
+
+    // only which fields are filled in matters, not what the warnings say
+    let res = result_fixture("warning_mentions_error");
+    assert!(res.compiler_stderr.contains("error"));
+    assert!(res.success() && res.compiled());
+
+    let res = result_fixture("segfault");
+    assert!(!res.success() && res.compiled() && res.was_signaled());
+    assert_eq!(res.exit_code(), None);
+
+    let res = result_fixture("nonzero_exit");
+    assert!(!res.success() && res.compiled());
+    assert_eq!(res.exit_code(), Some(3));
+
+    // warnings & a failing program that printed nothing still compiled
+    let res = result_fixture("nonzero_exit_silent");
+    assert!(!res.success() && res.compiled());
+    assert_eq!(res.exit_code(), Some(1));
+
+    let res = result_fixture("empty");
+    assert!(!res.success() && !res.compiled() && !res.was_signaled());
+    assert_eq!(res.exit_code(), None);
+}
+
+#[test]
+fn compilation_result_signal() {
+    use crate::SignalKind;
+
+    assert_eq!(result_fixture("killed").signal_kind(), Some(SignalKind::Killed));
+    assert_eq!(result_fixture("segfault").signal_kind(), Some(SignalKind::Segfault));
+    assert_eq!(result_fixture("aborted").signal_kind(), Some(SignalKind::Aborted));
+    assert_eq!(result_fixture("fpe").signal_kind(), Some(SignalKind::FloatingPointException));
+    assert_eq!(result_fixture("success").signal_kind(), None);
+
+    let mut res = result_fixture("killed");
+    res.signal = String::from("Bus error");
+    assert_eq!(res.signal_kind(), Some(SignalKind::Other(String::from("Bus error"))));
+}
+
+#[test]
+fn compilation_result_roundtrip() -> Result<(), Box<dyn Error>> {
+    use crate::CompilationResult;
+
+    for case in ["saved", "compile_error", "segfault", "empty"] {
+        let res = result_fixture(case);
+        let json = serde_json::to_string(&res)?;
+        let back : CompilationResult = serde_json::from_str(&json)?;
+        assert_eq!(back, res);
+    }
+
+    // fields keep Wandbox's names on the way out
+    let json = serde_json::to_value(result_fixture("compile_error"))?;
+    assert_eq!(json["status"], "");
+    assert!(json["compiler_error"].as_str().unwrap().contains("error: expected ';'"));
+    assert_eq!(json["program_message"], "");
+
+    Ok(())
+}
+
+#[test]
+fn compilation_result_output() {
+    use crate::TRUNCATION_MARKER;
+
+    let res = result_fixture("warning");
+    assert_eq!(res.full_output(), "prog.cc:2:9: warning: unused variable 'x' [-Wunused-variable]\nok\n");
+    assert_eq!(res.full_output_limited(1000), res.full_output());
+
+    let mut res = result_fixture("success");
+    res.program_all = "ééééé€€€".repeat(50);
+    let full = res.full_output();
+    for max in 0..full.len() {
+        let limited = res.full_output_limited(max);
+        assert!(limited.ends_with(TRUNCATION_MARKER));
+        assert!(limited.len() <= max.max(TRUNCATION_MARKER.len()));
+        assert!(full.starts_with(limited.trim_end_matches(TRUNCATION_MARKER)));
+    }
+    assert_eq!(res.full_output_limited(full.len()), full);
+}
+
+#[test]
+fn compilation_result_share_url() -> Result<(), Box<dyn Error>> {
+    use crate::CompilationResult;
+
+    let res = result_fixture("saved");
+    assert_eq!(res.permlink.as_deref(), Some("AbCdEfGh12345678"));
+    assert_eq!(res.share_url(), Some("https://wandbox.org/permlink/AbCdEfGh12345678"));
+
+    let res = result_fixture("success");
+    assert_eq!(res.permlink, None);
+    assert_eq!(res.share_url(), None);
+
+    // empty strings & nulls are treated as missing
+    let res : CompilationResult = serde_json::from_str(r#"{"status":"0","permlink":"","url":null}"#)?;
+    assert_eq!(res.permlink, None);
+    assert_eq!(res.share_url(), None);
+    assert!(!serde_json::to_string(&res)?.contains("permlink"));
+
+    Ok(())
+}
+
+#[test]
+fn unknown_fields_are_kept() -> Result<(), Box<dyn Error>> {
+    use crate::CompilationResult;
+
+    let res : CompilationResult = serde_json::from_str(r#"{"status":"0","program_message":"test","elapsed_ms":42}"#)?;
+    assert_eq!(res.program_all, "test");
+    assert_eq!(res.raw_extra()["elapsed_ms"], 42);
+    assert!(!res.raw_extra().contains_key("status"));
+    assert!(serde_json::to_string(&res)?.contains("\"elapsed_ms\":42"));
+
+    // the snapshot carries a "provider" field the crate doesn't model
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let compiler = wbox.get_compiler("gcc-head").expect("gcc-head is available");
+    assert_eq!(compiler.raw_extra()["provider"], 0);
+    assert!(!compiler.raw_extra().contains_key("switches"));
+
+    Ok(())
+}
+
+#[test]
+fn compilation_result_outcome() {
+    use crate::Outcome;
+
+    assert_eq!(result_fixture("success").outcome(), Outcome::Exited(0));
+    assert_eq!(result_fixture("nonzero_exit").outcome(), Outcome::Exited(3));
+    assert_eq!(result_fixture("compile_error").outcome(), Outcome::CompileError);
+    assert_eq!(result_fixture("segfault").outcome(), Outcome::Signaled(String::from("Segmentation fault")));
+    assert_eq!(result_fixture("empty").outcome(), Outcome::CompileError);
+
+    // warnings on stderr don't stop the program from running
+    let res = result_fixture("warning");
+    assert!(!res.compiler_stderr.is_empty());
+    assert_eq!(res.outcome(), Outcome::Exited(0));
+
+    // nor do warnings that happen to mention "error"
+    let res = result_fixture("warning_mentions_error");
+    assert!(res.compiler_stderr.contains("error"));
+    assert_eq!(res.outcome(), Outcome::Exited(0));
+    assert_eq!(result_fixture("nonzero_exit_warning").outcome(), Outcome::Exited(2));
+
+    // or of one that failed without printing anything
+    assert_eq!(result_fixture("nonzero_exit_silent").outcome(), Outcome::Exited(1));
+}
+
+#[tokio::test]
+async fn fetch_and_build_cache() -> Result<(), Box<dyn Error>> {
+    use crate::{build_cache, fetch_compiler_list};
+
+    let server = MockServer::wandbox("{}");
+    let compilers = fetch_compiler_list(Some(&server.url()), None).await?;
+    assert_eq!(compilers.len(), 12);
+    assert!(compilers.iter().any(|c| c.name == "gcc-head"));
+    assert_eq!(server.requests()[0].path, "/api/list.json");
+
+    let cache = build_cache(compilers);
+    assert_eq!(cache.len(), 6);
+    assert!(cache["c++"].compilers.iter().any(|c| c.name == "gcc-head"));
+
+    // an invalid endpoint is rejected before anything is sent
+    let client = reqwest::Client::new();
+    assert!(fetch_compiler_list(Some("not a url"), Some(&client)).await.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn compiler_cache() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use crate::{Compiler, CompilerCache, Language};
+    use crate::tests::mock::LIST_FIXTURE;
+
+    let compilers : Vec<Compiler> = serde_json::from_str(LIST_FIXTURE)?;
+    let mut cache = CompilerCache::new();
+    assert!(cache.is_empty());
+    for c in compilers.iter().cloned() {
+        cache.insert_compiler(c);
+    }
+    assert_eq!(cache.len(), 6);
+    assert_eq!(cache.languages().count(), 6);
+    for lang in cache.languages() {
+        assert!(lang.compilers.iter().all(|c| Arc::ptr_eq(&c.language, &lang.name)));
+    }
+
+    // lookups & removals
+    let gcc = cache.find_compiler("gcc-head").expect("gcc-head is cached").clone();
+    assert_eq!(&*gcc.language, "c++");
+    assert!(cache.find_compiler("not-a-compiler").is_none());
+    assert_eq!(cache.remove_compiler("gcc-head"), Some(gcc.clone()));
+    assert!(cache.find_compiler("gcc-head").is_none());
+    assert_eq!(cache.remove_compiler("gcc-head"), None);
+    assert_eq!(cache.remove_language("Rust").map(|l| l.name), Some(Arc::from("rust")));
+    assert!(!cache.contains_key("rust"));
+
+    // removing the last compiler drops its language
+    let bash : Vec<String> = cache["bash script"].compilers.iter().map(|c| c.name.clone()).collect();
+    for name in bash {
+        cache.remove_compiler(&name);
+    }
+    assert!(!cache.contains_key("bash script"));
+
+    // replacing a compiler keeps its position, a new one goes last
+    let mut first = cache["c++"].compilers[0].clone();
+    first.display_name = String::from("replaced");
+    cache.insert_compiler(first.clone());
+    assert_eq!(cache["c++"].compilers[0].display_name, "replaced");
+    cache.insert_compiler(gcc.clone());
+    assert_eq!(cache["c++"].compilers.last(), Some(&gcc));
+
+    // a compiler moving to another language leaves its old one
+    let mut moved = gcc.clone();
+    moved.language = Arc::from("C");
+    cache.insert_compiler(moved);
+    assert_eq!(&*cache.find_compiler("gcc-head").unwrap().language, "c");
+    assert!(cache["c++"].compilers.iter().all(|c| c.name != "gcc-head"));
+
+    // deserialized caches are regrouped too
+    let mut raw : HashMap<String, Language> = HashMap::new();
+    let mut upper = gcc.clone();
+    upper.language = Arc::from("C++");
+    raw.insert(String::from("C++"), Language { name : Arc::from("C++"), compilers : Arc::from(vec![upper]) });
+    let cache : CompilerCache = serde_json::from_str(&serde_json::to_string(&raw)?)?;
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec!["c++"]);
+    assert_eq!(&*cache["c++"].name, "c++");
+
+    Ok(())
+}
+
+#[test]
+fn cache_diff() -> Result<(), Box<dyn Error>> {
+    use crate::{build_cache, CacheDiff, Compiler};
+    use crate::tests::mock::LIST_FIXTURE;
+
+    let before : Vec<Compiler> = serde_json::from_str(LIST_FIXTURE)?;
+    let mut after = before.clone();
+    after.retain(|c| c.name != "clang-head");
+    let mut added = after[0].clone();
+    added.name = String::from("gcc-11.1.0");
+    after.push(added);
+    let updated = after.iter_mut().find(|c| c.name == "gcc-10.1.0").expect("gcc-10.1.0 is listed");
+    updated.version = String::from("10.1.1");
+
+    let old = build_cache(before);
+    let new = build_cache(after);
+    let diff = CacheDiff::between(&old, &new);
+    let names = |compilers : &[Compiler]| compilers.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names(&diff.added), vec!["gcc-11.1.0"]);
+    assert_eq!(names(&diff.removed), vec!["clang-head"]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!((diff.changed[0].0.version.as_str(), diff.changed[0].1.version.as_str()), ("10.1.0", "10.1.1"));
+    assert!(!diff.not_modified);
+
+    // swapping the caches swaps the diff
+    let reverse = CacheDiff::between(&new, &old);
+    assert_eq!(reverse.added, diff.removed);
+    assert_eq!(reverse.removed, diff.added);
+
+    assert!(CacheDiff::between(&old, &old).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_cache_fallback() -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+    use crate::{CacheOrigin, WandboxBuilder, WandboxError};
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let path = std::env::temp_dir().join(format!("wandbox-fallback-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // a successful fetch is saved for later
+    let server = MockServer::wandbox("{}");
+    let (wbox, origin) = WandboxBuilder::new().endpoint(&server.url()).build_with_fallback(&path).await?;
+    assert_eq!(origin, CacheOrigin::Network);
+    assert!(wbox.is_valid_compiler_str("gcc-head"));
+    assert!(path.exists());
+
+    // Wandbox is down, the saved list is used along with the builder's block lists
+    let down = MockServer::start(|_req| MockResponse::status(503, "Service Unavailable"));
+    let mut blocked = HashSet::new();
+    blocked.insert(String::from("gcc-head"));
+    let (wbox, origin) = WandboxBuilder::new()
+        .endpoint(&down.url())
+        .blocked_compilers(blocked)
+        .build_with_fallback(&path).await?;
+    match origin {
+        CacheOrigin::Disk { age } => assert!(age < Duration::from_secs(60)),
+        CacheOrigin::Network => panic!("expected the saved list to be used")
+    }
+    assert!(!wbox.is_valid_compiler_str("gcc-head"));
+    assert!(wbox.is_valid_compiler_str("clang-head"));
+    assert_eq!(wbox.stats().compilers, serde_json::from_str::<Vec<serde_json::Value>>(LIST_FIXTURE)?.len() - 1);
+
+    // with neither, both failures are reported
+    std::fs::remove_file(&path)?;
+    let err = match WandboxBuilder::new().endpoint(&down.url()).build_with_fallback(&path).await {
+        Ok(_wbox) => panic!("expected both attempts to fail"),
+        Err(e) => e
+    };
+    match err {
+        WandboxError::FallbackFailed { network, cache } => {
+            assert!(matches!(*network, WandboxError::Http { status : 503, .. }));
+            assert!(!cache.to_string().is_empty());
+        },
+        other => panic!("unexpected error {:?}", other)
+    }
+
+    Ok(())
+}
+
+#[test]
+fn errors_are_send_sync() {
+    use crate::{BuildError, Compilation, CompilationBuilder, CompilationResult, WandboxError};
+
+    fn assert_send_sync<T : Send + Sync + 'static>() {}
+    assert_send_sync::<WandboxError>();
+    assert_send_sync::<BuildError>();
+    assert_send_sync::<Wandbox>();
+    assert_send_sync::<Compilation>();
+    assert_send_sync::<CompilationBuilder>();
+    assert_send_sync::<CompilationResult>();
+
+    // `?` still boxes them for callers returning boxed errors
+    fn boxed() -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err(WandboxError::Build(BuildError::MissingTarget))?;
+        Ok(())
+    }
+    assert!(boxed().is_err());
+}
+
+#[test]
+fn format_compilers() -> Result<(), Box<dyn Error>> {
+    use crate::ListStyle;
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    assert_eq!(wbox.format_compilers("Rust", ListStyle::Plain).unwrap(),
+               "rust HEAD — 1.54.0-nightly\n\
+                rust — 1.52.1");
+    assert_eq!(wbox.format_compilers("go", ListStyle::Markdown).unwrap(),
+               "```\n\
+                | Compiler | Version           |\n\
+                |----------|-------------------|\n\
+                | go HEAD  | devel +a0a3c9e1e5 |\n\
+                | go       | 1.16.3            |\n\
+                ```");
+
+    // long lists keep their ends
+    assert_eq!(wbox.format_compilers_capped("c++", ListStyle::Plain, 4).unwrap(),
+               "gcc HEAD — 12.0.0 20210529 (experimental)\n\
+                gcc — 11.1.0\n\
+                … and 14 more\n\
+                clang — 3.8.1\n\
+                zapcc — 1.0.1");
+    assert_eq!(wbox.format_compilers_capped("c++", ListStyle::Markdown, 1).unwrap(),
+               "```\n\
+                | Compiler | Version                        |\n\
+                |----------|--------------------------------|\n\
+                | gcc HEAD | 12.0.0 20210529 (experimental) |\n\
+                … and 17 more\n\
+                ```");
+    assert_eq!(wbox.format_compilers_capped("rust", ListStyle::Plain, 2), wbox.format_compilers("rust", ListStyle::Plain));
+
+    // the cap holds even when there's no room for both ends
+    assert_eq!(wbox.format_compilers_capped("c++", ListStyle::Plain, 1).unwrap(),
+               "gcc HEAD — 12.0.0 20210529 (experimental)\n\
+                … and 17 more");
+    assert_eq!(wbox.format_compilers_capped("c++", ListStyle::Plain, 0).unwrap(), "… and 18 more");
+    assert_eq!(wbox.format_compilers_capped("c++", ListStyle::Markdown, 0).unwrap(),
+               "```\n\
+                | Compiler | Version |\n\
+                |----------|---------|\n\
+                … and 18 more\n\
+                ```");
+
+    assert_eq!(wbox.format_compilers("not-a-language", ListStyle::Plain), None);
+
+    Ok(())
+}
+
+#[test]
+fn sanitized_output() {
+    use crate::{Sanitize, TRUNCATION_MARKER};
+
+    let res = result_fixture("rustc_colored");
+    let clean = res.sanitized(Sanitize { strip_ansi : true, normalize_crlf : true, max_len : None });
+    assert_eq!(clean.compiler_stderr,
+               "error[E0308]: mismatched types\n \
+                --> prog.rs:2:18\n  \
+                |\n\
+                2 |     let x: i32 = \"one\";\n  \
+                |                  ^^^^^ expected `i32`, found `&str`\n\
+                \n\
+                E0308\n");
+    assert_eq!(clean.compiler_all, clean.compiler_stderr);
+    assert!(clean.full_output().starts_with("error[E0308]"));
+
+    // an escape character that isn't part of a sequence stays
+    assert_eq!(clean.program_stdout, "raw \x1b byte, cut \x1b[3");
+
+    // only what's asked for is cleaned
+    let untouched = res.sanitized(Sanitize::default());
+    assert_eq!(untouched.compiler_stderr, res.compiler_stderr);
+    let crlf_only = res.sanitized(Sanitize { normalize_crlf : true, ..Default::default() });
+    assert!(crlf_only.compiler_stderr.contains('\x1b') && !crlf_only.compiler_stderr.contains('\r'));
+
+    let limited = res.sanitized(Sanitize { strip_ansi : true, max_len : Some(20), ..Default::default() });
+    assert!(limited.compiler_stderr.len() <= 20);
+    assert!(limited.compiler_stderr.starts_with("error") && limited.compiler_stderr.ends_with(TRUNCATION_MARKER));
+    assert_eq!(limited.program_stdout, "raw \x1b byte, cut \x1b[3");
+}
+
+#[tokio::test]
+async fn request_size_limits() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, RequestLimits, WandboxBuilder, WandboxError, DEFAULT_MAX_CODE_BYTES, DEFAULT_MAX_STDIN_BYTES};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let request = |code : String, stdin : String| {
+        let mut builder = crate::CompilationBuilder::new();
+        builder.target("python");
+        builder.code(&code);
+        builder.stdin(&stdin);
+        builder
+    };
+
+    // the defaults
+    assert_eq!(wbox.limits(), RequestLimits::default());
+    let code = format!("#{}", " ".repeat(DEFAULT_MAX_CODE_BYTES));
+    assert_eq!(request(code, String::new()).build(&wbox).err().as_ref().and_then(WandboxError::build_error),
+               Some(&BuildError::TooLarge { field : String::from("code"), size : DEFAULT_MAX_CODE_BYTES + 1, limit : DEFAULT_MAX_CODE_BYTES }));
+    let stdin = "x".repeat(DEFAULT_MAX_STDIN_BYTES + 1);
+    assert!(matches!(request(String::from("print(input())"), stdin).build(&wbox), Err(WandboxError::Build(BuildError::TooLarge { .. }))));
+    assert!(request("#".repeat(DEFAULT_MAX_CODE_BYTES), "x".repeat(DEFAULT_MAX_STDIN_BYTES)).build(&wbox).is_ok());
+
+    // tightened on the handle, which its clones share
+    let clone = wbox.clone();
+    wbox.set_limits(RequestLimits { max_code_bytes : 100, max_stdin_bytes : 10 });
+    let stdin = "x".repeat(11);
+    let err = request(String::from("print(input())"), stdin).build(&clone).err().expect("stdin is too large");
+    assert_eq!(err.build_error(), Some(&BuildError::TooLarge { field : String::from("stdin"), size : 11, limit : 10 }));
+    assert_eq!(err.to_string(), "The stdin is 11 bytes, more than the limit of 10 bytes");
+
+    // additional files count towards the code
+    let mut builder = request(String::from("print(1)"), String::new());
+    builder.add_file("lib.py", "#".repeat(95))?;
+    assert!(matches!(builder.build(&wbox), Err(WandboxError::Build(BuildError::TooLarge { size : 103, .. }))));
+
+    // and set up front on the builder
+    let server = MockServer::wandbox("{}");
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).max_code_bytes(8).build().await?;
+    assert_eq!(wbox.limits().max_stdin_bytes, DEFAULT_MAX_STDIN_BYTES);
+    assert!(request(String::from("print(1)"), String::new()).build(&wbox).is_ok());
+    assert!(request(String::from("print(10)"), String::new()).build(&wbox).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn compiler_families() -> Result<(), Box<dyn Error>> {
+    use crate::version::family;
+
+    assert_eq!(family("gcc-10.1.0"), "gcc");
+    assert_eq!(family("gcc-head-c"), "gcc");
+    assert_eq!(family("cpython-2.7-head"), "cpython");
+    assert_eq!(family("rill-head"), "rill");
+    assert_eq!(family("openjdk-jdk-15.0.2+7"), "openjdk-jdk");
+    assert_eq!(family("bash"), "bash");
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let names = |family : &crate::CompilerFamily| family.compilers.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+
+    let families = wbox.get_compiler_families("C++");
+    assert_eq!(families.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["gcc", "clang", "zapcc"]);
+    assert_eq!(names(&families[0]), vec!["gcc-head", "gcc-11.1.0", "gcc-10.2.0", "gcc-10.1.0", "gcc-9.3.0", "gcc-8.4.0",
+                                         "gcc-7.5.0", "gcc-6.3.0", "gcc-5.5.0", "gcc-4.9.4"]);
+    assert_eq!(names(&families[1]), vec!["clang-head", "clang-12.0.0", "clang-11.0.0", "clang-10.0.0", "clang-9.0.0",
+                                         "clang-7.0.0", "clang-3.8.1"]);
+
+    // C compilers carry a suffix after their version
+    let families = wbox.get_compiler_families("c");
+    assert_eq!(families.iter().map(|f| (f.name.as_str(), f.compilers.len())).collect::<Vec<_>>(), vec![("gcc", 3), ("clang", 2)]);
+
+    let families = wbox.get_compiler_families("python");
+    assert_eq!(families.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), vec!["cpython", "pypy"]);
+    assert_eq!(names(&families[0])[..2], [String::from("cpython-head"), String::from("cpython-2.7-head")]);
+
+    // a longer family joins the shorter one it extends
+    let families = wbox.get_compiler_families("java");
+    assert_eq!(families.len(), 1);
+    assert_eq!(names(&families[0]), vec!["openjdk-head", "openjdk-jdk-15.0.2+7"]);
+
+    assert!(wbox.get_compiler_families("not-a-language").is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn switch_validation() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    // an unknown switch lists the options of the group it's closest to
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head").code("int main() {}").switches(vec!["c++99"]);
+    match builder.build(&wbox) {
+        Err(WandboxError::Build(BuildError::UnknownSwitch { valid, .. })) => {
+            assert_eq!(valid.first().map(|v| v.as_str()), Some("c++98"));
+            assert!(valid.iter().any(|v| v == "gnu++2a") && !valid.iter().any(|v| v == "warning"));
+        },
+        other => panic!("unexpected result {:?}", other.map(|c| c.request().clone()))
+    }
+
+    // or every switch when nothing is close, i.e a C++ standard for a C compiler
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head-c").code("int main() {}").switches(vec!["c++2a"]);
+    let err = builder.build(&wbox).err().expect("c++2a is not a C switch");
+    assert!(matches!(err.build_error(), Some(BuildError::UnknownSwitch { valid, .. }) if valid.len() == 8));
+    assert!(err.to_string().starts_with("Compiler gcc-head-c has no switch named c++2a, valid switches are warning, optimize, c89"));
+
+    // conflicting standards resolve to the last one, with a warning
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head").code("int main() {}").switches(vec!["c++14", "warning", "c++17", "cpp-pedantic"]);
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.request().switches, "warning,c++17,cpp-pedantic");
+    assert_eq!(compilation.warnings(), ["gcc-head takes only one of c++14, c++17, using c++17"]);
+
+    // boolean switches are kept as is, and repeating the same standard is no conflict
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head").code("int main() {}").switches(vec!["c++17", "warning", "c++17"]);
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.request().switches, "warning,c++17");
+    assert!(compilation.warnings().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn default_switches() -> Result<(), Box<dyn Error>> {
+    use crate::{CompilationBuilder, Switch};
+    use crate::tests::mock::LIST_FIXTURE;
+
+    let wbox = Wandbox::from_list_json(LIST_FIXTURE, None, None)?;
+    let request = |builder : &mut CompilationBuilder| -> Result<serde_json::Value, Box<dyn Error>> {
+        Ok(serde_json::to_value(builder.code("int main() {}").clone().build(&wbox)?.request())?)
+    };
+
+    // the defaults as the fixture lists them
+    let list : Vec<serde_json::Value> = serde_json::from_str(LIST_FIXTURE)?;
+    let gcc = list.iter().find(|c| c["name"] == "gcc-head").expect("gcc-head is listed");
+    let expected : Vec<&str> = gcc["switches"].as_array().unwrap().iter()
+        .filter_map(|s| match s["type"].as_str() {
+            Some("single") if s["default"] == true => s["name"].as_str(),
+            Some("select") => s["default"].as_str(),
+            _ => None
+        })
+        .collect();
+    assert!(!expected.is_empty());
+    assert_eq!(request(CompilationBuilder::new().target("gcc-head"))?["options"], expected.join(","));
+
+    let compiler = wbox.get_compiler("gcc-head").unwrap();
+    assert_eq!(compiler.default_switches(), expected);
+    assert!(compiler.switches.iter().any(|s| matches!(s, Switch::Single { default : false, .. })));
+
+    // switches given explicitly replace the defaults, and they can be turned off
+    assert_eq!(request(CompilationBuilder::new().target("gcc-head").switches(vec!["c++17"]))?["options"], "c++17");
+    assert_eq!(request(CompilationBuilder::new().target("gcc-head").no_default_switches())?["options"], "");
+
+    Ok(())
+}
+
+#[test]
+fn preview_command() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+    let preview = |target : &str, switches : Option<Vec<&str>>, options : Vec<&str>| {
+        let mut builder = CompilationBuilder::new();
+        builder.target(target).code("int main() {}").options(options);
+        if let Some(switches) = switches {
+            builder.switches(switches);
+        }
+        builder.preview_command(&wbox)
+    };
+
+    // the defaults give back the display command
+    assert_eq!(preview("gcc-head", None, vec![])?, "g++ prog.cc -Wall -Wextra -std=gnu++2a");
+    assert_eq!(preview("gcc-head-c", None, vec![])?, "gcc prog.c -Wall -Wextra -std=gnu11");
+
+    assert_eq!(preview("gcc-head", Some(vec!["optimize", "c++17", "warning"]), vec!["-DNAME=a b", "-I/usr/include"])?,
+               "g++ prog.cc -Wall -Wextra -O2 -march=native -std=c++17 '-DNAME=a b' -I/usr/include");
+    assert_eq!(preview("gcc-head", Some(vec!["cpp-pedantic"]), vec!["-DQUOTE='x'"])?,
+               "g++ prog.cc -pedantic '-DQUOTE='\\''x'\\'''");
+    assert_eq!(preview("rust-1.52.1", None, vec!["-C", "opt-level=2"])?, "rustc prog.rs -C opt-level=2");
+
+    assert!(matches!(preview("not-a-compiler", None, vec![]), Err(WandboxError::Build(BuildError::UnknownTarget { .. }))));
+
+    Ok(())
+}
+
+#[test]
+fn builder_json_roundtrip() -> Result<(), Box<dyn Error>> {
+    use crate::{CompilationBuilder, WandboxError};
+
+    let wbox : Wandbox = Wandbox::from_embedded_snapshot(None, None)?;
+
+    // an un-built builder keeps every setting, the target & options included
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head")
+        .code("int main() {}")
+        .stdin("input")
+        .options(vec!["-O2", "-DNAME=a b"])
+        .runtime_options(vec!["first"])
+        .switches(vec!["warning", "c++17"])
+        .save(true)
+        .lenient(true)
+        .no_default_switches();
+    builder.add_file("util.h", "int x;")?;
+    let json = builder.to_json();
+    let value : serde_json::Value = serde_json::from_str(&json)?;
+    assert_eq!(value["version"], 1);
+    assert_eq!(value["builder"]["target"], "gcc-head");
+    let restored = CompilationBuilder::from_json(&json)?;
+    assert_eq!(restored, builder);
+
+    // one that was built before restores to the same request
+    let built = builder.clone().build(&wbox)?;
+    let rebuilt = CompilationBuilder::from_json(&builder.to_json())?.build(&wbox)?;
+    assert_eq!(rebuilt.request(), built.request());
+    assert_eq!(CompilationBuilder::from_json(&CompilationBuilder::new().to_json())?, CompilationBuilder::new());
+
+    // settings missing from older saves keep their defaults
+    let old = r#"{"version":1,"builder":{"target":"python","code":"print(1)"}}"#;
+    let mut expected = CompilationBuilder::new();
+    expected.target("python").code("print(1)");
+    assert_eq!(CompilationBuilder::from_json(old)?, expected);
+
+    // newer or unversioned formats are rejected
+    assert!(matches!(CompilationBuilder::from_json(r#"{"version":2,"builder":{}}"#),
+                     Err(WandboxError::UnsupportedBuilderVersion { found : Some(2), expected : 1 })));
+    assert!(matches!(CompilationBuilder::from_json(r#"{"builder":{}}"#),
+                     Err(WandboxError::UnsupportedBuilderVersion { found : None, .. })));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn builder_from_permlink() -> Result<(), Box<dyn Error>> {
+    use crate::{CodeFile, CompilationBuilder};
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let saved = |compiler : &str| serde_json::json!({
+        "parameter" : {
+            "compiler" : compiler,
+            "code" : "#include <iostream>\nint main() { std::cout << X; }",
+            "codes" : [{"file" : "util.h", "code" : "int x;"}],
+            "options" : "warning,c++17,boost-1.60.0-gcc-4.8.1",
+            "compiler-option-raw" : "-O3\n-DX=1",
+            "runtime-option-raw" : "first\nsecond",
+            "stdin" : "input"
+        },
+        "result" : {"status" : "0", "program_message" : "1"}
+    }).to_string();
+    let current = saved("gcc-10.1.0");
+    let removed = saved("gcc-4.8.1");
+    let server = MockServer::start(move |req| match req.path.as_str() {
+        "/api/list.json" => MockResponse::json(LIST_FIXTURE),
+        "/api/permlink/current" => MockResponse::json(&current),
+        "/api/permlink/removed" => MockResponse::json(&removed),
+        _ => MockResponse::status(404, "Not Found")
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    // every field is restored, the permlink given by id or url
+    let builder = CompilationBuilder::from_permlink(&wbox, "current").await?;
+    assert!(builder.warnings().is_empty());
+    let compilation = builder.clone().build(&wbox);
+    assert!(compilation.is_err(), "boost-1.60.0-gcc-4.8.1 isn't offered by gcc-10.1.0");
+    let value : serde_json::Value = serde_json::from_str(&builder.to_json())?;
+    let restored = &value["builder"];
+    assert_eq!(restored["target"], "gcc-10.1.0");
+    assert_eq!(restored["code"], "#include <iostream>\nint main() { std::cout << X; }");
+    assert_eq!(serde_json::from_value::<Vec<CodeFile>>(restored["codes"].clone())?, vec![CodeFile { file : String::from("util.h"), code : String::from("int x;") }]);
+    assert_eq!(restored["stdin"], "input");
+    assert_eq!(restored["options"], serde_json::json!(["-O3", "-DX=1"]));
+    assert_eq!(restored["runtime_options"], serde_json::json!(["first", "second"]));
+    assert_eq!(restored["switches"], serde_json::json!(["warning", "c++17", "boost-1.60.0-gcc-4.8.1"]));
+    let by_url = CompilationBuilder::from_permlink(&wbox, &format!("{}/permlink/current/", server.url())).await?;
+    assert_eq!(by_url, builder);
+
+    // a compiler that's gone falls back to its language's default
+    let builder = CompilationBuilder::from_permlink(&wbox, "removed").await?;
+    assert_eq!(builder.warnings(), [
+        "gcc-4.8.1 is no longer available, using gcc-head instead",
+        "gcc-head does not offer the switches boost-1.60.0-gcc-4.8.1, dropped"
+    ]);
+    let compilation = builder.build(&wbox)?;
+    assert_eq!(compilation.compiler(), "gcc-head");
+    assert_eq!(compilation.request().switches, "warning,c++17");
+    assert_eq!(compilation.request().compiler_options_raw, "-O3\n-DX=1");
+    assert_eq!(compilation.warnings().len(), 2);
+
+    assert!(CompilationBuilder::from_permlink(&wbox, "missing").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn build_unchecked() -> Result<(), Box<dyn Error>> {
+    use crate::{BuildError, CompilationBuilder, WandboxError};
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+
+    // a compiler the cache doesn't know about is sent as is
+    let mut builder = CompilationBuilder::new();
+    builder.compiler_unchecked("gcc-99.0.0", "C++");
+    builder.code("int main() {}");
+    builder.options(vec!["-O2", "-Wall"]);
+    builder.switches(vec!["warning"]);
+    assert!(builder.clone().build(&wbox).is_err());
+    let compilation = builder.build_unchecked()?;
+    assert_eq!(compilation.compiler(), "gcc-99.0.0");
+    assert_eq!(compilation.lang(), "C++");
+
+    let res = compilation.dispatch_with(&wbox).await?;
+    assert_eq!(res.program_all, "test");
+    let sent = sent(&server, 1);
+    assert_eq!(sent["compiler"], "gcc-99.0.0");
+    assert_eq!(sent["compiler-option-raw"], "-O2\n-Wall");
+    assert_eq!(sent["options"], "warning");
+
+    // local checks still run
+    let mut builder = CompilationBuilder::new();
+    builder.compiler_unchecked("gcc-head", "c++");
+    assert!(matches!(builder.build_unchecked(), Err(WandboxError::Build(BuildError::EmptyCode))));
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head");
+    builder.code("int main() {}");
+    assert!(matches!(builder.build_unchecked(), Err(WandboxError::Build(BuildError::MissingTarget))));
+
+    Ok(())
+}
+
+#[test]
+fn options_from_str() {
+    use crate::{BuildError, CompilationBuilder};
+
+    let cases : [(&str, &[&str]); 10] = [
+        ("", &[]),
+        ("   ", &[]),
+        ("-O2 -Wall", &["-O2", "-Wall"]),
+        ("  -O2\t\n-Wall  ", &["-O2", "-Wall"]),
+        (r#"-O2 -Wall "-DNAME=hello world""#, &["-O2", "-Wall", "-DNAME=hello world"]),
+        ("'-DNAME=hello world' -g", &["-DNAME=hello world", "-g"]),
+        (r#"-DQ="say \"hi\"""#, &["-DQ=say \"hi\""]),
+        (r#"'it'\''s' \"bare\""#, &["it's", "\"bare\""]),
+        (r#"-Dpath=a\ b "\n" '\n'"#, &["-Dpath=a b", "\\n", "\\n"]),
+        (r#"'' "" x"#, &["", "", "x"]),
+    ];
+    for (input, expected) in cases {
+        let mut builder = CompilationBuilder::new();
+        builder.options_from_str(input).unwrap_or_else(|e| panic!("{:?} failed to split: {}", input, e));
+        let value : serde_json::Value = serde_json::from_str(&builder.to_json()).unwrap();
+        assert_eq!(value["builder"]["options"], serde_json::json!(expected), "splitting {:?}", input);
+    }
+
+    let malformed = [
+        (r#"-DNAME="hello"#, "unterminated double quote"),
+        ("-DNAME='hello", "unterminated single quote"),
+        (r#"-DQ="\"#, "unterminated double quote"),
+        ("-O2 \\", "trailing backslash"),
+    ];
+    for (input, reason) in malformed {
+        let mut builder = CompilationBuilder::new();
+        builder.options(vec!["-g"]);
+        let err = builder.options_from_str(input).expect_err("malformed quoting is an error");
+        assert_eq!(err.build_error(), Some(&BuildError::MalformedOptions { input : input.to_string(), reason : reason.to_string() }));
+        assert_eq!(builder, { let mut b = CompilationBuilder::new(); b.options(vec!["-g"]); b });
+    }
+
+    let mut builder = CompilationBuilder::new();
+    builder.add_option("-O2").add_option("-DNAME=hello world");
+    builder.options_from_str("-Wall").unwrap().add_option("-Werror");
+    let value : serde_json::Value = serde_json::from_str(&builder.to_json()).unwrap();
+    assert_eq!(value["builder"]["options"], serde_json::json!(["-Wall", "-Werror"]));
+}
+
+#[test]
+fn flag_helpers() -> Result<(), Box<dyn Error>> {
+    use crate::{build_cache, BuildError, CompilationBuilder, Compiler, OptLevel, WandboxError};
+    use crate::tests::mock::LIST_FIXTURE;
+
+    let mut compilers : Vec<Compiler> = serde_json::from_str(LIST_FIXTURE)?;
+    let mut bare = compilers.iter().find(|c| c.name == "clang-head").unwrap().clone();
+    bare.name = String::from("clang-7.0.0");
+    bare.switches.clear();
+    compilers.push(bare);
+    let wbox = Wandbox::from_cache(build_cache(compilers), None, None);
+
+    let helpers = |target : &str| {
+        let mut builder = CompilationBuilder::new();
+        builder.target(target);
+        builder.code("int main() {}");
+        builder.options(vec!["-g"]);
+        builder.warnings_as_errors(true).optimization(OptLevel::O2).cpp_standard("c++17");
+        builder
+    };
+
+    // gcc advertises the standard as a switch, which replaces the default one
+    let compilation = helpers("gcc-head").build(&wbox)?;
+    assert_eq!(compilation.request().switches, "warning,c++17");
+    assert_eq!(compilation.request().compiler_options_raw, "-g\n-Werror\n-O2");
+    assert!(compilation.warnings().is_empty());
+
+    // without switches the standard is a raw flag
+    let compilation = helpers("clang-7.0.0").build(&wbox)?;
+    assert_eq!(compilation.request().switches, "");
+    assert_eq!(compilation.request().compiler_options_raw, "-g\n-Werror\n-O2\n-std=c++17");
+
+    let mut builder = CompilationBuilder::new();
+    builder.compiler_unchecked("gcc-99.0.0", "c++").code("int main() {}").cpp_standard("gnu++2b");
+    assert_eq!(builder.build_unchecked()?.request().compiler_options_raw, "-std=gnu++2b");
+
+    // other compilers can't take them
+    let mut builder = CompilationBuilder::new();
+    builder.target("python").code("print(1)").optimization(OptLevel::O3);
+    let err = builder.build(&wbox).err().expect("cpython isn't gcc or clang");
+    assert_eq!(err.build_error(), Some(&BuildError::FlagUnsupported { compiler : String::from("cpython-3.8.0"), flag : String::from("optimization") }));
+    assert_eq!(err.to_string(), "Compiler cpython-3.8.0 does not support optimization, only gcc & clang do");
+
+    // gcc's C compilers take the other helpers, but not a C++ standard
+    let err = helpers("gcc-head-c").build(&wbox).err().expect("gcc-head-c compiles C");
+    assert_eq!(err.build_error(), Some(&BuildError::FlagUnsupported { compiler : String::from("gcc-head-c"), flag : String::from("cpp_standard") }));
+    assert_eq!(err.to_string(), "Compiler gcc-head-c does not support cpp_standard, only gcc & clang C++ compilers do");
+    let mut builder = CompilationBuilder::new();
+    builder.compiler_unchecked("gcc-99.0.0", "c").code("int main() {}").cpp_standard("c++17");
+    assert!(matches!(builder.build_unchecked(), Err(WandboxError::Build(BuildError::FlagUnsupported { .. }))));
+
+    let mut builder = CompilationBuilder::new();
+    builder.target("gcc-head-c").code("int main() {}").warnings_as_errors(true).optimization(OptLevel::O2);
+    assert_eq!(builder.build(&wbox)?.request().compiler_options_raw, "-Werror\n-O2");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn on_response_headers() -> Result<(), Box<dyn Error>> {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::{DispatchInfo, WandboxBuilder, MAX_CAPTURED_HEADER_BYTES};
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let header = |name : &str, value : &str| (name.to_string(), value.to_string());
+    let compiles = AtomicUsize::new(0);
+    let server = MockServer::start(move |req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        if compiles.fetch_add(1, Ordering::SeqCst) == 0 {
+            let mut response = MockResponse::status(429, "");
+            response.headers.extend([header("Retry-After", "0"), header("x-ratelimit-remaining", "0")]);
+            return response;
+        }
+        let mut response = MockResponse::json(r#"{"status":"0","program_message":"test"}"#);
+        response.headers.extend([
+            header("X-Oversized", &"x".repeat(MAX_CAPTURED_HEADER_BYTES)),
+            header("x-ratelimit-remaining", "9"),
+            header("cf-ray", "8a1b2c3d4e5f-AMS")
+        ]);
+        response
+    });
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).retry_rate_limited(Duration::from_secs(1)).build().await?;
+
+    // compilations built before the hook is set are observed too
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    let seen : Arc<Mutex<Vec<DispatchInfo>>> = Arc::default();
+    let recorder = seen.clone();
+    wbox.on_response(move |info| recorder.lock().unwrap().push(info.clone()));
+
+    let (_result, info) = compilation.dispatch_detailed().await?;
+    let seen = seen.lock().unwrap().clone();
+    assert_eq!(seen.len(), 2, "the rate limited reply is observed too");
+    assert_eq!((seen[0].status, seen[0].retries), (429, 0));
+    assert_eq!(seen[0].header("retry-after"), Some("0"));
+    assert_eq!(seen[0].header("X-RateLimit-Remaining"), Some("0"));
+
+    assert_eq!(seen[1].headers, info.headers);
+    assert_eq!((info.status, info.retries), (200, 1));
+    assert_eq!(info.header("x-ratelimit-remaining"), Some("9"));
+    assert_eq!(info.header("cf-ray"), Some("8a1b2c3d4e5f-AMS"));
+    // headers past the size bound are dropped
+    assert_eq!(info.header("x-oversized"), None);
+    assert!(info.headers.iter().map(|(n, v)| n.len() + v.len()).sum::<usize>() <= MAX_CAPTURED_HEADER_BYTES);
+
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn tracing_spans() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    type Fields = HashMap<String, String>;
+    type Log<T> = Arc<Mutex<Vec<(T, Fields)>>>;
+
+    struct Recorder<'a>(&'a mut Fields);
+    impl Visit for Recorder<'_> {
+        fn record_debug(&mut self, field : &Field, value : &dyn fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+        fn record_str(&mut self, field : &Field, value : &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    /// Keeps every span with its fields, and every event with the span it belongs to
+    #[derive(Clone, Default)]
+    struct Capture {
+        spans : Log<&'static str>,
+        events : Log<Option<&'static str>>,
+    }
+    impl tracing::Subscriber for Capture {
+        fn enabled(&self, _metadata : &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span : &Attributes<'_>) -> Id {
+            let mut fields = Fields::new();
+            span.record(&mut Recorder(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, span : &Id, values : &Record<'_>) {
+            values.record(&mut Recorder(&mut self.spans.lock().unwrap()[span.into_u64() as usize - 1].1));
+        }
+        fn record_follows_from(&self, _span : &Id, _follows : &Id) {}
+        fn event(&self, event : &Event<'_>) {
+            let mut fields = Fields::new();
+            event.record(&mut Recorder(&mut fields));
+            let parent = event.parent().map(|id| self.spans.lock().unwrap()[id.into_u64() as usize - 1].0);
+            self.events.lock().unwrap().push((parent, fields));
+        }
+        fn enter(&self, _span : &Id) {}
+        fn exit(&self, _span : &Id) {}
+    }
+
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(capture.clone());
+
+    let server = MockServer::wandbox(r#"{"status":"0","program_message":"test"}"#);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head").code("int secret() { return 42; }");
+    builder.clone().build(&wbox)?.dispatch().await?;
+    builder.target("gcc-haed");
+    assert!(builder.build(&wbox).is_err());
+
+    let spans : Vec<(&str, Fields)> = capture.spans.lock().unwrap().iter()
+        .filter(|(name, _fields)| name.starts_with("wandbox::"))
+        .cloned()
+        .collect();
+    let names : Vec<&str> = spans.iter().map(|(name, _fields)| *name).collect();
+    assert_eq!(names, ["wandbox::load", "wandbox::build", "wandbox::dispatch", "wandbox::build"]);
+
+    let field = |i : usize, name : &str| spans[i].1.get(name).map(|v| v.as_str());
+    assert_eq!(field(0, "endpoint"), Some(server.url().as_str()));
+    assert_eq!(field(0, "status"), Some("200"));
+    assert_eq!(field(0, "compilers"), Some("12"));
+    assert_eq!(field(1, "target"), Some("gcc-head"));
+    assert_eq!(field(1, "compiler"), Some("gcc-head"));
+    assert_eq!(field(1, "language"), Some("c++"));
+    assert_eq!(field(1, "code_len"), Some("27"));
+    assert_eq!(field(2, "compiler"), Some("gcc-head"));
+    assert_eq!(field(2, "code_len"), Some("27"));
+    assert_eq!(field(2, "status"), Some("200"));
+    assert_eq!(field(2, "retries"), Some("0"));
+    assert!(spans.iter().all(|(_name, fields)| fields.contains_key("elapsed_ms")));
+    // the code itself is never recorded
+    assert!(spans.iter().all(|(_name, fields)| fields.values().all(|v| !v.contains("secret"))));
+
+    // the failed build has its error as an event
+    assert_eq!(field(3, "compiler"), None);
+    let events = capture.events.lock().unwrap();
+    let errors : Vec<&Fields> = events.iter()
+        .filter(|(parent, _fields)| parent.is_some_and(|p| p.starts_with("wandbox::")))
+        .map(|(_parent, fields)| fields)
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0]["error"].contains("gcc-haed"), "{:?}", errors[0]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn wire_logger() -> Result<(), Box<dyn Error>> {
+    use std::sync::{Arc, Mutex};
+    use crate::{WireEvent, TRUNCATION_MARKER};
+
+    const REPLY : &str = r#"{"status":"0","program_message":"test"}"#;
+    let server = MockServer::wandbox(REPLY);
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("gcc-head").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+
+    let events : Arc<Mutex<Vec<WireEvent>>> = Arc::default();
+    let logged = events.clone();
+    wbox.set_wire_logger(move |event| logged.lock().unwrap().push(event));
+
+    compilation.dispatch().await?;
+    assert_eq!(*events.lock().unwrap(), [
+        WireEvent::Request { url : format!("{}/api/compile.json", server.url()), body : compilation.to_json() },
+        WireEvent::Response { status : 200, body : REPLY.to_string() }
+    ]);
+
+    // bodies are cut short to the limit
+    events.lock().unwrap().clear();
+    wbox.set_wire_log_limit(24);
+    compilation.dispatch().await?;
+    for event in events.lock().unwrap().iter() {
+        let body = match event {
+            WireEvent::Request { body, .. } | WireEvent::Response { body, .. } => body
+        };
+        assert!(body.len() <= 24 && body.ends_with(TRUNCATION_MARKER), "{:?}", event);
+    }
+
+    // and nothing is logged once cleared
+    events.lock().unwrap().clear();
+    wbox.clear_wire_logger();
+    compilation.dispatch().await?;
+    assert!(events.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn circuit_breaker() -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, SystemTime};
+    use crate::{CircuitBreaker, CircuitState, WandboxBuilder, WandboxError};
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let down = Arc::new(AtomicBool::new(true));
+    let status = down.clone();
+    let server = MockServer::start(move |req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        let response = match status.load(Ordering::SeqCst) {
+            true => MockResponse::status(503, "Service Unavailable"),
+            false => MockResponse::json(r#"{"status":"0","program_message":"test"}"#)
+        };
+        MockResponse { delay : Some(Duration::from_millis(50)), ..response }
+    });
+    let cool_down = Duration::from_millis(200);
+    let wbox = WandboxBuilder::new()
+        .endpoint(&server.url())
+        .circuit_breaker(CircuitBreaker { failures : 2, window : Duration::from_secs(60), cool_down })
+        .build().await?;
+    let mut builder = crate::CompilationBuilder::new();
+    builder.target("c++").code("int main() {}");
+    let compilation = builder.build(&wbox)?;
+    let compiles = || server.requests().iter().filter(|r| r.path == "/api/compile.json").count();
+
+    // closed, a success resets the count
+    assert!(matches!(compilation.dispatch().await, Err(WandboxError::Http { status : 503, .. })));
+    assert_eq!(wbox.circuit_state(), Some(CircuitState::Closed { failures : 1 }));
+    down.store(false, Ordering::SeqCst);
+    compilation.dispatch().await?;
+    assert_eq!(wbox.circuit_state(), Some(CircuitState::Closed { failures : 0 }));
+
+    // open after two failures in a row, dispatches then fail without reaching Wandbox
+    down.store(true, Ordering::SeqCst);
+    assert!(compilation.dispatch().await.is_err());
+    assert!(compilation.dispatch().await.is_err());
+    assert!(matches!(wbox.circuit_state(), Some(CircuitState::Open { .. })));
+    let sent = compiles();
+    match compilation.dispatch().await {
+        Err(WandboxError::CircuitOpen { retry_at }) => assert!(retry_at > SystemTime::now() && retry_at <= SystemTime::now() + cool_down),
+        _ => panic!("the breaker should be open")
+    }
+    assert_eq!(compiles(), sent);
+
+    // half-open after the cool-down, a failed probe opens it again
+    tokio::time::sleep(cool_down).await;
+    assert_eq!(wbox.circuit_state(), Some(CircuitState::HalfOpen));
+    assert!(matches!(compilation.dispatch().await, Err(WandboxError::Http { status : 503, .. })));
+    assert!(matches!(wbox.circuit_state(), Some(CircuitState::Open { .. })));
+    assert_eq!(compiles(), sent + 1);
+
+    // only one probe goes through at a time, and its success closes the breaker
+    tokio::time::sleep(cool_down).await;
+    down.store(false, Ordering::SeqCst);
+    let (probe, other) = tokio::join!(compilation.dispatch(), compilation.dispatch());
+    assert_eq!(probe?.program_all, "test");
+    assert!(matches!(other, Err(WandboxError::CircuitOpen { .. })));
+    assert_eq!(compiles(), sent + 2);
+    assert_eq!(wbox.circuit_state(), Some(CircuitState::Closed { failures : 0 }));
+    compilation.dispatch().await?;
+
+    // and it can be turned off
+    wbox.set_circuit_breaker(None);
+    assert_eq!(wbox.circuit_state(), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn coalesce_requests() -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+    use crate::{CompilationBuilder, WandboxBuilder};
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let server = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        MockResponse { delay : Some(Duration::from_millis(200)), ..MockResponse::json(r#"{"status":"0","program_message":"42\n"}"#) }
+    });
+    let wbox = WandboxBuilder::new().endpoint(&server.url()).coalesce_requests(true).build().await?;
+    let compiles = || server.requests().iter().filter(|r| r.path == "/api/compile.json").count();
+    let build = |code : &str, save : bool| {
+        let mut builder = CompilationBuilder::new();
+        builder.target("python").code(code).save(save);
+        builder.build(&wbox)
+    };
+
+    // the same snippet from two users is sent once
+    let (first, second) = (build("print(42)", false)?, build("print(42)", false)?);
+    let (a, b) = tokio::join!(first.dispatch(), second.dispatch_with(&wbox));
+    assert_eq!(compiles(), 1);
+    assert_eq!(a?.program_all, "42\n");
+    assert_eq!(b?.program_all, "42\n");
+
+    // once it's done the next one is sent again
+    first.dispatch().await?;
+    assert_eq!(compiles(), 2);
+
+    // different code and saved compilations are never shared
+    let other = build("print(43)", false)?;
+    let saved = build("print(42)", true)?;
+    let _ = tokio::join!(first.dispatch(), other.dispatch(), saved.dispatch(), saved.dispatch());
+    assert_eq!(compiles(), 6);
+
+    // and nothing is shared once turned off
+    wbox.set_coalesce_requests(false);
+    let _ = tokio::join!(first.dispatch(), second.dispatch());
+    assert_eq!(compiles(), 8);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn result_cache() -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+    use crate::CompilationBuilder;
+    use crate::tests::mock::{MockResponse, LIST_FIXTURE};
+
+    let server = MockServer::start(|req| {
+        if req.path == "/api/list.json" {
+            return MockResponse::json(LIST_FIXTURE);
+        }
+        match req.body.contains("fail") {
+            true => MockResponse::status(500, "Internal Server Error"),
+            false => MockResponse::json(r#"{"status":"0","program_message":"42\n"}"#)
+        }
+    });
+    let wbox : Wandbox = Wandbox::with_endpoint(&server.url(), None, None).await?;
+    let compiles = || server.requests().iter().filter(|r| r.path == "/api/compile.json").count();
+    let build = |code : &str, stdin : &str| {
+        let mut builder = CompilationBuilder::new();
+        builder.target("python").code(code).stdin(stdin);
+        builder.build(&wbox)
+    };
+    let compilation = build("print(input())", "42")?;
+
+    // off by default
+    compilation.dispatch_with(&wbox).await?;
+    compilation.dispatch_with(&wbox).await?;
+    assert_eq!(compiles(), 2);
+
+    wbox.enable_result_cache(2, Duration::from_millis(300));
+    let (_result, info) = compilation.dispatch_detailed().await?;
+    assert!(!info.cache_hit);
+    let (result, info) = compilation.dispatch_detailed().await?;
+    assert!(info.cache_hit);
+    assert_eq!((result.program_all.as_str(), info.status), ("42\n", 200));
+    assert_eq!(compilation.dispatch_with(&wbox).await?.program_all, "42\n");
+    assert_eq!(compiles(), 3);
+
+    // every field counts, saved runs & errors are never cached
+    build("print(input())", "43")?.dispatch().await?;
+    assert_eq!(compiles(), 4);
+    let mut saved = CompilationBuilder::new();
+    saved.target("python").code("print(input())").stdin("42").save(true);
+    let saved = saved.build(&wbox)?;
+    let _ = saved.dispatch().await;
+    let _ = saved.dispatch().await;
+    assert_eq!(compiles(), 6);
+    let failing = build("fail()", "")?;
+    assert!(failing.dispatch().await.is_err());
+    assert!(failing.dispatch().await.is_err());
+    assert_eq!(compiles(), 8);
+
+    // the result used longest ago makes room
+    compilation.dispatch().await?;
+    build("print(1)", "")?.dispatch().await?;
+    assert_eq!(compiles(), 9);
+    compilation.dispatch().await?;
+    build("print(input())", "43")?.dispatch().await?;
+    assert_eq!(compiles(), 10);
+
+    // cleared & expired results are sent again
+    wbox.clear_result_cache();
+    compilation.dispatch().await?;
+    assert_eq!(compiles(), 11);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!compilation.dispatch_detailed().await?.1.cache_hit);
+    assert_eq!(compiles(), 12);
 
     Ok(())
 }
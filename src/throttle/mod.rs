@@ -0,0 +1,57 @@
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{read_lock, write_lock};
+
+/// Bounds how many compilations are sent to Wandbox at once
+#[derive(Default)]
+pub(crate) struct Throttle {
+    // replaced whenever the limit changes, permits of the old semaphore are simply dropped
+    semaphore : RwLock<Option<Arc<Semaphore>>>,
+    in_flight : Arc<AtomicUsize>,
+}
+
+/// Held for as long as a compilation is in flight
+pub(crate) struct Permit {
+    _permit : Option<OwnedSemaphorePermit>,
+    in_flight : Arc<AtomicUsize>,
+}
+
+impl Throttle {
+    /// Limits compilations to `max` at once, 0 lifts the limit
+    pub fn set_max(&self, max : usize) {
+        let semaphore = match max {
+            0 => None,
+            max => Some(Arc::new(Semaphore::new(max)))
+        };
+        *write_lock(&self.semaphore) = semaphore;
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits for room under the limit
+    pub async fn acquire(&self) -> Permit {
+        let semaphore = read_lock(&self.semaphore).clone();
+        let permit = match semaphore {
+            // the semaphore is never closed
+            Some(s) => s.acquire_owned().await.ok(),
+            None => None
+        };
+
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Permit {
+            _permit : permit,
+            in_flight : self.in_flight.clone()
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,90 @@
+#[cfg(feature = "tracing")]
+use core::fmt;
+use std::future::Future;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+
+/// Creates a `Span` with the given name & fields, along with an `elapsed_ms` field filled in by
+/// `Span::run`. Without the `tracing` feature the fields aren't evaluated at all.
+macro_rules! span {
+    ($name:literal $(, $($fields:tt)*)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = crate::trace::Span::new(tracing::info_span!($name, elapsed_ms = tracing::field::Empty $(, $($fields)*)?));
+        #[cfg(not(feature = "tracing"))]
+        let span = crate::trace::Span::disabled();
+        span
+    }};
+}
+pub(crate) use span;
+
+/// A tracing span around one of the crate's operations, which does nothing without the
+/// `tracing` feature
+#[derive(Clone)]
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    inner : tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl Span {
+    pub(crate) fn new(inner : tracing::Span) -> Span {
+        Span { inner }
+    }
+
+    /// A span that records nothing, for callers that aren't traced
+    pub(crate) fn none() -> Span {
+        Span { inner : tracing::Span::none() }
+    }
+
+    /// Records a field declared when the span was created
+    pub(crate) fn record<V : tracing::Value>(&self, field : &str, value : V) {
+        self.inner.record(field, value);
+    }
+
+    /// Runs `f` within the span, recording how long it took & its error as an event
+    pub(crate) fn run<T, E : fmt::Display>(&self, f : impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let start = Instant::now();
+        let result = self.inner.in_scope(f);
+        self.finish(start, &result);
+        result
+    }
+
+    /// Awaits `future` within the span, recording how long it took & its error as an event
+    pub(crate) async fn run_async<T, E : fmt::Display>(&self, future : impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let start = Instant::now();
+        let result = future.instrument(self.inner.clone()).await;
+        self.finish(start, &result);
+        result
+    }
+
+    fn finish<T, E : fmt::Display>(&self, start : Instant, result : &Result<T, E>) {
+        self.inner.record("elapsed_ms", start.elapsed().as_millis() as u64);
+        if let Err(e) = result {
+            tracing::error!(parent : &self.inner, error = %e, "failed");
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn disabled() -> Span {
+        Span {}
+    }
+
+    pub(crate) fn none() -> Span {
+        Span {}
+    }
+
+    pub(crate) fn record<V>(&self, _field : &str, _value : V) {}
+
+    pub(crate) fn run<T, E>(&self, f : impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        f()
+    }
+
+    pub(crate) async fn run_async<T, E>(&self, future : impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        future.await
+    }
+}
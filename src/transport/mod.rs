@@ -0,0 +1,302 @@
+#[cfg(any(test, feature = "test-util"))]
+use std::collections::HashMap;
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::{Arc, RwLock};
+
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, BoxStream, StreamExt};
+
+use crate::{WandboxError, WandboxResult};
+
+/// A reply from Wandbox as a transport hands it back, with its body read in full
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransportResponse {
+    /// The HTTP status code, i.e 200
+    pub status : u16,
+    /// The headers of the reply as (name, value) pairs
+    pub headers : Vec<(String, String)>,
+    /// The body of the reply
+    pub body : String,
+}
+impl TransportResponse {
+    /// Creates a reply without headers
+    ///
+    /// # Arguments
+    /// * `status` - The HTTP status code, i.e 200
+    /// * `body` - The body of the reply
+    pub fn new(status : u16, body : &str) -> TransportResponse {
+        TransportResponse {
+            status,
+            headers : Vec::new(),
+            body : body.to_string()
+        }
+    }
+
+    /// Adds a header to the reply
+    ///
+    /// # Arguments
+    /// * `name` - The header name i.e "Retry-After"
+    /// * `value` - The header value
+    pub fn with_header(mut self, name : &str, value : &str) -> TransportResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Looks up a header, ignoring the case of its name
+    pub fn header(&self, name : &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _v)| k.eq_ignore_ascii_case(name))
+            .map(|(_k, v)| v.as_str())
+    }
+
+    /// Whether the status code is in the 2xx range
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// The chunks of a streamed reply, as they arrive
+pub type ChunkStream = BoxStream<'static, WandboxResult<Vec<u8>>>;
+
+/// A reply from Wandbox whose body is handed out as it arrives
+pub struct StreamResponse {
+    /// The HTTP status code, i.e 200
+    pub status : u16,
+    /// The headers of the reply as (name, value) pairs
+    pub headers : Vec<(String, String)>,
+    /// The body of the reply
+    pub chunks : ChunkStream,
+}
+impl StreamResponse {
+    /// Whether the status code is in the 2xx range
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Reads the rest of the body, i.e to report an unsuccessful reply
+    pub async fn read_all(mut self) -> WandboxResult<TransportResponse> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunks.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+
+        Ok(TransportResponse {
+            status : self.status,
+            headers : self.headers,
+            body : String::from_utf8_lossy(&body).into_owned()
+        })
+    }
+}
+
+/// Carries requests to Wandbox & brings back its replies
+///
+/// `Wandbox` & `Compilation` send everything through a transport, `ReqwestTransport` unless
+/// another one is given with `WandboxBuilder::transport`. Replacing it allows serving canned
+/// replies in tests, or routing requests through an HTTP stack of your own. Unsuccessful status
+/// codes are returned as replies, turning them into errors is left to the caller.
+pub trait WandboxTransport : Send + Sync {
+    /// Fetches a JSON document
+    ///
+    /// # Arguments
+    /// * `url` - The full url i.e "https://wandbox.org/api/list.json"
+    fn get_json<'a>(&'a self, url : &'a str) -> BoxFuture<'a, WandboxResult<TransportResponse>>;
+
+    /// Fetches a JSON document with extra request headers, i.e `If-None-Match`
+    ///
+    /// By default the headers are dropped & the document is fetched through `get_json`, so
+    /// conditional requests always download the whole document.
+    ///
+    /// # Arguments
+    /// * `url` - The full url i.e "https://wandbox.org/api/list.json"
+    /// * `headers` - The extra headers as (name, value) pairs
+    fn get_json_with<'a>(&'a self, url : &'a str, _headers : Vec<(String, String)>) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        self.get_json(url)
+    }
+
+    /// Posts a JSON body
+    ///
+    /// # Arguments
+    /// * `url` - The full url i.e "https://wandbox.org/api/compile.json"
+    /// * `body` - The serialized JSON body
+    fn post_json<'a>(&'a self, url : &'a str, body : String) -> BoxFuture<'a, WandboxResult<TransportResponse>>;
+
+    /// Posts a JSON body & streams the reply as it arrives
+    ///
+    /// By default the whole reply is read through `post_json` & handed out as a single chunk.
+    ///
+    /// # Arguments
+    /// * `url` - The full url i.e "https://wandbox.org/api/compile.ndjson"
+    /// * `body` - The serialized JSON body
+    fn post_stream<'a>(&'a self, url : &'a str, body : String) -> BoxFuture<'a, WandboxResult<StreamResponse>> {
+        Box::pin(async move {
+            let response = self.post_json(url, body).await?;
+            let body = response.body.into_bytes();
+            let chunks : ChunkStream = Box::pin(stream::once(async move { Ok(body) }));
+            Ok(StreamResponse {
+                status : response.status,
+                headers : response.headers,
+                chunks
+            })
+        })
+    }
+}
+
+/// The default transport, sending requests through a `reqwest::Client`
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client : reqwest::Client,
+}
+impl ReqwestTransport {
+    /// Wraps an existing client, whose settings such as timeouts & proxies apply to every request
+    ///
+    /// # Arguments
+    /// * `client` - The client used to reach Wandbox
+    pub fn new(client : reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+
+    fn post(&self, url : &str, body : String) -> reqwest::RequestBuilder {
+        self.client.post(url)
+            .body(body)
+            .header("Content-Type", "application/json; charset=utf-8")
+    }
+}
+
+/// The headers of a reqwest reply, dropping values that aren't valid text
+fn headers(response : &reqwest::Response) -> Vec<(String, String)> {
+    response.headers().iter()
+        .filter_map(|(k, v)| Some((k.as_str().to_string(), v.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Reads a reqwest reply in full
+async fn read(response : reqwest::Response) -> WandboxResult<TransportResponse> {
+    let status = response.status().as_u16();
+    let headers = headers(&response);
+
+    Ok(TransportResponse { status, headers, body : response.text().await? })
+}
+
+impl WandboxTransport for ReqwestTransport {
+    fn get_json<'a>(&'a self, url : &'a str) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        self.get_json_with(url, Vec::new())
+    }
+
+    fn get_json_with<'a>(&'a self, url : &'a str, headers : Vec<(String, String)>) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        Box::pin(async move {
+            let mut request = self.client.get(url);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            read(request.send().await?).await
+        })
+    }
+
+    fn post_json<'a>(&'a self, url : &'a str, body : String) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        Box::pin(async move {
+            read(self.post(url, body).send().await?).await
+        })
+    }
+
+    fn post_stream<'a>(&'a self, url : &'a str, body : String) -> BoxFuture<'a, WandboxResult<StreamResponse>> {
+        Box::pin(async move {
+            let response = self.post(url, body).send().await?;
+            let status = response.status().as_u16();
+            let headers = headers(&response);
+
+            let chunks : ChunkStream = Box::pin(stream::unfold(Some(response), |response| async move {
+                let mut response = response?;
+                match response.chunk().await {
+                    Ok(Some(chunk)) => Some((Ok(chunk.to_vec()), Some(response))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(WandboxError::from(e)), None))
+                }
+            }));
+            Ok(StreamResponse { status, headers, chunks })
+        })
+    }
+}
+
+/// A request seen by a `MemoryTransport`
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedRequest {
+    /// "GET" or "POST"
+    pub method : String,
+    /// The full url the request was sent to
+    pub url : String,
+    /// The extra headers the request was sent with, as (name, value) pairs
+    pub headers : Vec<(String, String)>,
+    /// The body of a POST request
+    pub body : Option<String>,
+}
+
+/// A transport serving canned replies without any network access, available with the `test-util`
+/// feature
+///
+/// Replies are matched on the path of the url i.e "/api/list.json", whatever the endpoint.
+/// Requests without a reply get a 404. Clones share their replies & recorded requests, so a clone
+/// kept aside can inspect what a `Wandbox` sent.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    replies : Arc<RwLock<HashMap<String, TransportResponse>>>,
+    requests : Arc<RwLock<Vec<RecordedRequest>>>,
+}
+#[cfg(any(test, feature = "test-util"))]
+impl MemoryTransport {
+    /// Creates a transport without any replies
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// Sets the reply served for a path, replacing any previous one
+    ///
+    /// # Arguments
+    /// * `path` - The path of the url i.e "/api/list.json"
+    /// * `response` - The reply to serve
+    pub fn respond(&self, path : &str, response : TransportResponse) -> &MemoryTransport {
+        crate::write_lock(&self.replies).insert(path.to_string(), response);
+        self
+    }
+
+    /// The requests received so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        crate::read_lock(&self.requests).clone()
+    }
+
+    fn reply(&self, method : &str, url : &str, headers : Vec<(String, String)>, body : Option<String>) -> TransportResponse {
+        crate::write_lock(&self.requests).push(RecordedRequest {
+            method : method.to_string(),
+            url : url.to_string(),
+            headers,
+            body
+        });
+
+        let path = match url.split_once("://") {
+            Some((_scheme, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+            None => url
+        };
+        match crate::read_lock(&self.replies).get(path) {
+            Some(response) => response.clone(),
+            None => TransportResponse::new(404, "Not Found")
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl WandboxTransport for MemoryTransport {
+    fn get_json<'a>(&'a self, url : &'a str) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        self.get_json_with(url, Vec::new())
+    }
+
+    fn get_json_with<'a>(&'a self, url : &'a str, headers : Vec<(String, String)>) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        let response = self.reply("GET", url, headers, None);
+        Box::pin(async move { Ok(response) })
+    }
+
+    fn post_json<'a>(&'a self, url : &'a str, body : String) -> BoxFuture<'a, WandboxResult<TransportResponse>> {
+        let response = self.reply("POST", url, Vec::new(), Some(body));
+        Box::pin(async move { Ok(response) })
+    }
+}
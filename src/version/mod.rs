@@ -0,0 +1,94 @@
+use core::fmt;
+use std::cmp::Ordering;
+
+/// The numeric part of a compiler version, i.e 10.1.0 out of "10.1.0 (trunk)"
+///
+/// Versions order numerically, missing components count as 0 so "24.0" equals "24.0.0".
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major : u64,
+    pub minor : u64,
+    pub patch : u64,
+}
+
+impl Version {
+    /// Extracts the leading version number of a compiler version, i.e "10.1.0", "HEAD 12.0.0" or
+    /// "jdk-15.0.2+7"
+    ///
+    /// A leading tag such as "v", "jdk-" or "HEAD " is skipped, and parsing stops at the first
+    /// character that doesn't belong to a dotted version number. Returns `None` when no number
+    /// follows the tag.
+    ///
+    /// # Arguments
+    /// * `version` - The version as Wandbox lists it
+    pub fn parse(version : &str) -> Option<Version> {
+        let mut parts = components(version)?.map(|part| part.parse::<u64>());
+        let mut next = || parts.next().unwrap_or(Ok(0)).ok();
+        Some(Version {
+            major : next()?,
+            minor : next()?,
+            patch : next()?
+        })
+    }
+}
+
+/// The dotted components of the leading version number, see `Version::parse`
+fn components(version : &str) -> Option<impl Iterator<Item = &str>> {
+    let rest = version.trim_start_matches(|c : char| c.is_ascii_alphabetic() || c == '-' || c.is_whitespace());
+    if !rest.starts_with(|c : char| c.is_ascii_digit()) {
+        return None;
+    }
+    let end = rest.find(|c : char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+
+    Some(rest[..end].split('.').take_while(|part| !part.is_empty()))
+}
+
+/// The toolchain a compiler id belongs to, i.e "gcc" for "gcc-10.1.0" & "gcc-head-c"
+///
+/// The family is every part of the id before the first one that is "head" or starts with a
+/// digit, or the whole id when there is no such part, such as "bash".
+pub fn family(name : &str) -> &str {
+    let mut end = 0;
+    for part in name.split('-') {
+        if part == "head" || part.starts_with(|c : char| c.is_ascii_digit()) {
+            break;
+        }
+        end += part.len() + 1;
+    }
+
+    match end {
+        0 => name,
+        _ => &name[..end - 1]
+    }
+}
+
+/// Compares a version to a bound only as precisely as the bound is written, so a bound of "10"
+/// is equal to every 10.x.y. Returns `None` when the bound has no version number.
+pub fn compare_to_bound(version : &Version, bound : &str) -> Option<Ordering> {
+    let precision = components(bound)?.count();
+    let bound = Version::parse(bound)?;
+
+    let ordering = match precision {
+        1 => version.major.cmp(&bound.major),
+        2 => (version.major, version.minor).cmp(&(bound.major, bound.minor)),
+        _ => version.cmp(&bound)
+    };
+    Some(ordering)
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Orders two compiler versions, versions without a number are older than any numbered version
+/// and fall back to a plain string comparison among themselves
+pub fn compare(a : &str, b : &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_a), None) => Ordering::Greater,
+        (None, Some(_b)) => Ordering::Less,
+        (None, None) => a.cmp(b)
+    }
+}